@@ -4,6 +4,9 @@
 #![test_runner(crate::tests::test_runner)]
 #![reexport_test_harness_main = "test_main"]
 #![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
+
+extern crate alloc;
 
 // #[cfg(test)]
 // use limine::BaseRevision;
@@ -19,16 +22,27 @@ pub mod usr;
 pub use sys::kernel::drivers::framebuffer::textwriter::{
     _print,
     _printerr,
-    _log,
 };
 
+pub use log;
+
 pub use sys::kernel::drivers::serial::{
     _serial_write,
     serial_read
 };
 
 pub fn init() {
+    sys::kernel::logger::init();
     sys::kernel::cpu::init();
+    sys::kernel::memory::init();
+    sys::kernel::drivers::framebuffer::render::enable_back_buffer();
+    sys::kernel::acpi::init();
+    sys::kernel::cpu::interrupts::init();
+    sys::kernel::drivers::serial::serial::init();
+    sys::kernel::time::init();
+    sys::kernel::cpu::exceptions::init();
+    sys::kernel::drivers::rtc::init();
+    sys::kernel::drivers::ps2::init();
 }
 
 pub fn hcf() -> ! {
@@ -40,9 +54,8 @@ pub fn hcf() -> ! {
 // Called on panic
 #[cfg(not(test))]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    println!("{}", _info);
-    hcf()
+fn panic(info: &PanicInfo) -> ! {
+    sys::kernel::panic::panic(info)
 }
 
 // code for testing etc.
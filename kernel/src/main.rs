@@ -6,9 +6,9 @@
 
 use limine::*;
 
-use GoofyAhhOS::sys::kernel::drivers::serial::serial_read;
 use GoofyAhhOS::{print, println, serial_println};
 use GoofyAhhOS::sys::kernel::drivers::framebuffer::textwriter::clear_screen;
+use GoofyAhhOS::sys::kernel::{acpi, drivers::{ps2::keyboard, rtc}, power};
 
 // Set the base revision
 static BASE_REVISION: BaseRevision = BaseRevision::new();
@@ -22,10 +22,8 @@ pub extern "C" fn kmain() -> ! {
     println!("Hello from GoofyAhhOS!");
     // serial_println!("SERIAL OUT ACHIEVED :check:");
 
-    loop {}
-
     loop {
-        let input: &str = serial_read();
+        let input = keyboard::read_line();
 
         clear_screen();
 
@@ -37,6 +35,14 @@ pub extern "C" fn kmain() -> ! {
             let y: i32 = 123456678;
 
             print!("num: {} {}", x, y);
+        } else if input == "acpi" {
+            acpi::dump();
+        } else if input == "date" {
+            println!("{}", rtc::now());
+        } else if input == "shutdown" {
+            power::shutdown();
+        } else if input == "reboot" {
+            power::reboot();
         } else {
             println!("Unknown command: {}", input);
         }
@@ -0,0 +1,78 @@
+//! Minimal DSDT scanning
+//!
+//! We don't have an AML interpreter, but the sleep state packages (`\_S5`
+//! and friends) are plain named constants that can be found by pattern
+//! matching the byte code.
+
+use super::Table;
+
+const NAME_OP: u8 = 0x08;
+const PACKAGE_OP: u8 = 0x12;
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const BYTE_PREFIX: u8 = 0x0a;
+const WORD_PREFIX: u8 = 0x0b;
+const DWORD_PREFIX: u8 = 0x0c;
+const QWORD_PREFIX: u8 = 0x0e;
+const ROOT_CHAR: u8 = b'\\';
+
+/// `SLP_TYPa` and `SLP_TYPb` values of a sleep state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepType {
+    pub a: u8,
+    pub b: u8,
+}
+
+/// Look up the sleep type package for `state` (0-5), e.g. 5 for `\_S5`.
+pub fn sleep_type(dsdt: &Table, state: u8) -> Option<SleepType> {
+    sleep_type_in(dsdt.body(), state)
+}
+
+/// Like [`sleep_type`], in raw AML byte code.
+pub fn sleep_type_in(aml: &[u8], state: u8) -> Option<SleepType> {
+    let name = [b'_', b'S', b'0' + state, b'_'];
+
+    let start = aml.windows(4).enumerate().find_map(|(i, window)| {
+        let named = (i >= 1 && aml[i - 1] == NAME_OP)
+            || (i >= 2 && aml[i - 2] == NAME_OP && aml[i - 1] == ROOT_CHAR);
+        (window == name && named).then_some(i + 4)
+    })?;
+
+    if *aml.get(start)? != PACKAGE_OP {
+        return None;
+    }
+
+    // the top two bits of the first PkgLength byte count the extra length bytes
+    let pkg_length_bytes = ((aml.get(start + 1)? >> 6) + 1) as usize;
+    // skip PackageOp, PkgLength and NumElements
+    let mut offset = start + 1 + pkg_length_bytes + 1;
+
+    let a = integer(aml, &mut offset)?;
+    let b = integer(aml, &mut offset)?;
+
+    Some(SleepType { a, b })
+}
+
+/// Decode an integer constant that fits a byte and advance past it.
+/// Anything else, including expressions we can't evaluate, is `None`.
+fn integer(aml: &[u8], offset: &mut usize) -> Option<u8> {
+    let size = match *aml.get(*offset)? {
+        ZERO_OP | ONE_OP => 0,
+        BYTE_PREFIX => 1,
+        WORD_PREFIX => 2,
+        DWORD_PREFIX => 4,
+        QWORD_PREFIX => 8,
+        _ => return None,
+    };
+
+    let value = match size {
+        0 => aml[*offset] as u64,
+        _ => {
+            let bytes = aml.get(*offset + 1..*offset + 1 + size)?;
+            bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64)
+        }
+    };
+
+    *offset += 1 + size;
+    u8::try_from(value).ok()
+}
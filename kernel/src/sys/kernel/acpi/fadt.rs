@@ -0,0 +1,116 @@
+//! Fixed ACPI Description Table
+
+use super::{sdt, sdt::GenericAddress, Table};
+
+/// The FADT body as of ACPI 6. Older, shorter revisions are zero padded.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Fadt {
+    pub firmware_ctrl: u32,
+    pub dsdt: u32,
+    reserved0: u8,
+    pub preferred_pm_profile: u8,
+    pub sci_interrupt: u16,
+    pub smi_command: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub s4bios_request: u8,
+    pub pstate_control: u8,
+    pub pm1a_event_block: u32,
+    pub pm1b_event_block: u32,
+    pub pm1a_control_block: u32,
+    pub pm1b_control_block: u32,
+    pub pm2_control_block: u32,
+    pub pm_timer_block: u32,
+    pub gpe0_block: u32,
+    pub gpe1_block: u32,
+    pub pm1_event_length: u8,
+    pub pm1_control_length: u8,
+    pub pm2_control_length: u8,
+    pub pm_timer_length: u8,
+    pub gpe0_length: u8,
+    pub gpe1_length: u8,
+    pub gpe1_base: u8,
+    pub cstate_control: u8,
+    pub worst_c2_latency: u16,
+    pub worst_c3_latency: u16,
+    pub flush_size: u16,
+    pub flush_stride: u16,
+    pub duty_offset: u8,
+    pub duty_width: u8,
+    pub day_alarm: u8,
+    pub month_alarm: u8,
+    pub century: u8,
+    pub boot_architecture_flags: u16,
+    reserved1: u8,
+    pub flags: u32,
+    pub reset_register: GenericAddress,
+    pub reset_value: u8,
+    pub arm_boot_architecture_flags: u16,
+    pub minor_version: u8,
+    pub x_firmware_ctrl: u64,
+    pub x_dsdt: u64,
+    pub x_pm1a_event_block: GenericAddress,
+    pub x_pm1b_event_block: GenericAddress,
+    pub x_pm1a_control_block: GenericAddress,
+    pub x_pm1b_control_block: GenericAddress,
+    pub x_pm2_control_block: GenericAddress,
+    pub x_pm_timer_block: GenericAddress,
+    pub x_gpe0_block: GenericAddress,
+    pub x_gpe1_block: GenericAddress,
+    pub sleep_control_register: GenericAddress,
+    pub sleep_status_register: GenericAddress,
+    pub hypervisor_vendor_id: u64,
+}
+
+/// `reset_register` is valid.
+pub const FLAG_RESET_REG_SUP: u32 = 1 << 10;
+/// The ACPI PM timer is 32 bits wide instead of 24.
+pub const FLAG_TMR_VAL_EXT: u32 = 1 << 8;
+/// IA-PC boot architecture: an 8042 keyboard controller is present.
+pub const BOOT_ARCH_8042: u16 = 1 << 1;
+
+impl Fadt {
+    pub fn parse(table: &Table) -> Self {
+        sdt::read_padded(table.body())
+    }
+
+    /// Physical address of the DSDT, preferring the 64 bit field.
+    pub fn dsdt_address(&self) -> u64 {
+        match self.x_dsdt {
+            0 => self.dsdt as u64,
+            x_dsdt => x_dsdt,
+        }
+    }
+
+    /// I/O port of the PM1a control block.
+    pub fn pm1a_control_port(&self) -> Option<u16> {
+        port(self.x_pm1a_control_block, self.pm1a_control_block)
+    }
+
+    /// I/O port of the PM1b control block, most machines don't have one.
+    pub fn pm1b_control_port(&self) -> Option<u16> {
+        port(self.x_pm1b_control_block, self.pm1b_control_block)
+    }
+
+    /// I/O port of the ACPI power management timer.
+    pub fn pm_timer_port(&self) -> Option<u16> {
+        port(self.x_pm_timer_block, self.pm_timer_block)
+    }
+
+    pub fn reset_register(&self) -> Option<GenericAddress> {
+        let reset = self.reset_register;
+        (self.flags & FLAG_RESET_REG_SUP != 0 && reset.is_present()).then_some(reset)
+    }
+}
+
+/// Pick the extended block if it describes an I/O port, else the legacy one.
+fn port(extended: GenericAddress, legacy: u32) -> Option<u16> {
+    if extended.is_present() && extended.address_space == sdt::ADDRESS_SPACE_IO {
+        Some(extended.address as u16)
+    } else if legacy != 0 {
+        Some(legacy as u16)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,34 @@
+//! High Precision Event Timer description table
+
+use super::{sdt, sdt::GenericAddress, Table};
+
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    /// Hardware revision, comparator count, counter size and vendor ID.
+    pub event_timer_block_id: u32,
+    pub base_address: GenericAddress,
+    pub hpet_number: u8,
+    /// Minimum periodic tick, in main counter ticks.
+    pub minimum_tick: u16,
+    pub page_protection: u8,
+}
+
+impl Hpet {
+    pub fn parse(table: &Table) -> Self {
+        sdt::read_padded(table.body())
+    }
+
+    /// Physical address of the HPET register block.
+    pub fn address(&self) -> u64 {
+        self.base_address.address
+    }
+
+    pub fn comparators(&self) -> u8 {
+        ((self.event_timer_block_id >> 8) & 0x1f) as u8 + 1
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        (self.event_timer_block_id >> 16) as u16
+    }
+}
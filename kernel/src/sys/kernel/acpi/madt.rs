@@ -0,0 +1,158 @@
+//! Multiple APIC Description Table
+
+use alloc::vec::Vec;
+
+use super::{sdt, Table};
+
+// offsets into the table body
+const LOCAL_APIC_ADDRESS: usize = 0;
+const FLAGS: usize = 4;
+const ENTRIES: usize = 8;
+
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_INTERRUPT_SOURCE_OVERRIDE: u8 = 2;
+const ENTRY_NMI_SOURCE: u8 = 3;
+const ENTRY_LOCAL_APIC_NMI: u8 = 4;
+const ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE: u8 = 5;
+const ENTRY_LOCAL_X2APIC: u8 = 9;
+
+/// The system also has dual 8259s that need to be masked.
+pub const FLAG_PCAT_COMPAT: u32 = 1 << 0;
+
+pub const LOCAL_APIC_ENABLED: u32 = 1 << 0;
+pub const LOCAL_APIC_ONLINE_CAPABLE: u32 = 1 << 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApic {
+    pub processor_uid: u32,
+    pub apic_id: u32,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    pub id: u8,
+    pub address: u32,
+    pub gsi_base: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptSourceOverride {
+    pub bus: u8,
+    pub irq: u8,
+    pub gsi: u32,
+    /// MPS INTI flags, polarity in bits 0-1 and trigger mode in bits 2-3.
+    pub flags: u16,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NmiSource {
+    pub flags: u16,
+    pub gsi: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LocalApicNmi {
+    /// 0xff means all processors.
+    pub processor_uid: u8,
+    pub flags: u16,
+    pub lint: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Madt {
+    /// Physical address of the local APIC, with any 64 bit override applied.
+    pub local_apic_address: u64,
+    pub flags: u32,
+    pub local_apics: Vec<LocalApic>,
+    pub io_apics: Vec<IoApic>,
+    pub overrides: Vec<InterruptSourceOverride>,
+    pub nmi_sources: Vec<NmiSource>,
+    pub local_apic_nmis: Vec<LocalApicNmi>,
+}
+
+impl Madt {
+    pub fn parse(table: &Table) -> Self {
+        let body = table.body();
+
+        let mut madt = Self {
+            local_apic_address: sdt::read::<u32>(body, LOCAL_APIC_ADDRESS).unwrap_or(0) as u64,
+            flags: sdt::read::<u32>(body, FLAGS).unwrap_or(0),
+            local_apics: Vec::new(),
+            io_apics: Vec::new(),
+            overrides: Vec::new(),
+            nmi_sources: Vec::new(),
+            local_apic_nmis: Vec::new(),
+        };
+
+        let mut offset = ENTRIES;
+
+        while offset + 2 <= body.len() {
+            let kind = body[offset];
+            let length = body[offset + 1] as usize;
+            if length < 2 || offset + length > body.len() {
+                break;
+            }
+
+            let entry = &body[offset..offset + length];
+            madt.parse_entry(kind, entry);
+
+            offset += length;
+        }
+
+        madt
+    }
+
+    fn parse_entry(&mut self, kind: u8, entry: &[u8]) {
+        let u8_at = |offset| sdt::read::<u8>(entry, offset).unwrap_or(0);
+        let u16_at = |offset| sdt::read::<u16>(entry, offset).unwrap_or(0);
+        let u32_at = |offset| sdt::read::<u32>(entry, offset).unwrap_or(0);
+
+        match kind {
+            ENTRY_LOCAL_APIC => self.local_apics.push(LocalApic {
+                processor_uid: u8_at(2) as u32,
+                apic_id: u8_at(3) as u32,
+                flags: u32_at(4),
+            }),
+            ENTRY_IO_APIC => self.io_apics.push(IoApic {
+                id: u8_at(2),
+                address: u32_at(4),
+                gsi_base: u32_at(8),
+            }),
+            ENTRY_INTERRUPT_SOURCE_OVERRIDE => self.overrides.push(InterruptSourceOverride {
+                bus: u8_at(2),
+                irq: u8_at(3),
+                gsi: u32_at(4),
+                flags: u16_at(8),
+            }),
+            ENTRY_NMI_SOURCE => self.nmi_sources.push(NmiSource {
+                flags: u16_at(2),
+                gsi: u32_at(4),
+            }),
+            ENTRY_LOCAL_APIC_NMI => self.local_apic_nmis.push(LocalApicNmi {
+                processor_uid: u8_at(2),
+                flags: u16_at(3),
+                lint: u8_at(5),
+            }),
+            ENTRY_LOCAL_APIC_ADDRESS_OVERRIDE => {
+                if let Some(address) = sdt::read::<u64>(entry, 4) {
+                    self.local_apic_address = address;
+                }
+            }
+            ENTRY_LOCAL_X2APIC => self.local_apics.push(LocalApic {
+                processor_uid: u32_at(12),
+                apic_id: u32_at(4),
+                flags: u32_at(8),
+            }),
+            _ => {}
+        }
+    }
+
+    /// Usable processors, enabled now or able to be brought online later.
+    pub fn processors(&self) -> impl Iterator<Item = &LocalApic> {
+        self.local_apics
+            .iter()
+            .filter(|lapic| lapic.flags & (LOCAL_APIC_ENABLED | LOCAL_APIC_ONLINE_CAPABLE) != 0)
+    }
+}
@@ -0,0 +1,56 @@
+//! PCI Express memory mapped configuration table
+
+use alloc::vec::Vec;
+
+use super::{sdt, Table};
+
+// the body starts with 8 reserved bytes
+const ENTRIES: usize = 8;
+const ENTRY_LENGTH: usize = 16;
+
+/// One ECAM window, covering `start_bus..=end_bus` of a PCI segment group.
+#[derive(Debug, Clone, Copy)]
+pub struct EcamRegion {
+    pub base_address: u64,
+    pub segment: u16,
+    pub start_bus: u8,
+    pub end_bus: u8,
+}
+
+impl EcamRegion {
+    /// Physical address of the configuration space of a function.
+    pub fn config_address(&self, bus: u8, device: u8, function: u8) -> Option<u64> {
+        if bus < self.start_bus || bus > self.end_bus || device >= 32 || function >= 8 {
+            return None;
+        }
+
+        let offset = ((bus - self.start_bus) as u64) << 20 | (device as u64) << 15 | (function as u64) << 12;
+        Some(self.base_address + offset)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mcfg {
+    pub regions: Vec<EcamRegion>,
+}
+
+impl Mcfg {
+    pub fn parse(table: &Table) -> Self {
+        let body = table.body();
+        let entries = body.get(ENTRIES..).unwrap_or(&[]);
+
+        let regions = entries
+            .as_chunks::<ENTRY_LENGTH>()
+            .0
+            .iter()
+            .map(|entry| EcamRegion {
+                base_address: sdt::read(entry, 0).unwrap(),
+                segment: sdt::read(entry, 8).unwrap(),
+                start_bus: entry[10],
+                end_bus: entry[11],
+            })
+            .collect();
+
+        Self { regions }
+    }
+}
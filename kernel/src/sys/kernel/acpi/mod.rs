@@ -0,0 +1,238 @@
+//! ACPI table discovery
+//!
+//! Finds the RSDP through Limine, walks the RSDT/XSDT and remembers every
+//! table with a valid checksum. The tables we care about are parsed on demand
+//! into the typed structures of the submodules.
+
+pub mod sdt;
+pub mod madt;
+pub mod fadt;
+pub mod hpet;
+pub mod mcfg;
+pub mod dsdt;
+
+use alloc::vec::Vec;
+use core::{fmt, mem::size_of, str};
+
+use limine::request::RsdpRequest;
+use spin::Mutex;
+use x86_64::{instructions::interrupts::without_interrupts, PhysAddr, VirtAddr};
+
+use crate::println_log;
+use crate::sys::kernel::cpu::x86_64::paging;
+
+use sdt::{Rsdp, SdtHeader, RSDP_V1_LENGTH, SDT_HEADER_LENGTH};
+
+pub use fadt::Fadt;
+pub use hpet::Hpet;
+pub use madt::Madt;
+pub use mcfg::Mcfg;
+
+static RSDP_REQUEST: RsdpRequest = RsdpRequest::new();
+
+static TABLES: Mutex<Vec<Table>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// The bootloader didn't hand us an RSDP.
+    NoRsdp,
+    InvalidRsdp,
+    /// A table failed its signature or checksum check.
+    InvalidTable([u8; 4]),
+}
+
+/// A discovered system description table.
+#[derive(Clone, Copy)]
+pub struct Table {
+    pub phys: u64,
+    pub header: SdtHeader,
+    /// The whole table, header included.
+    pub bytes: &'static [u8],
+}
+
+impl Table {
+    /// Bytes following the common header.
+    pub fn body(&self) -> &'static [u8] {
+        &self.bytes[SDT_HEADER_LENGTH..]
+    }
+
+    pub fn signature(&self) -> &str {
+        str::from_utf8(&self.header.signature).unwrap_or("????")
+    }
+}
+
+impl fmt::Debug for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (length, revision) = (self.header.length, self.header.revision);
+        write!(
+            f,
+            "{} at {:#x}, {} bytes, rev {}, oem {:?}",
+            self.signature(),
+            self.phys,
+            length,
+            revision,
+            str::from_utf8(&self.header.oem_id).unwrap_or("?")
+        )
+    }
+}
+
+pub fn init() {
+    match discover() {
+        Ok(tables) => {
+            for table in tables.iter() {
+                println_log!("ACPI: {:?}", table);
+            }
+            without_interrupts(|| *TABLES.lock() = tables);
+        }
+        Err(err) => println_log!("ACPI: discovery failed ({:?})", err),
+    }
+}
+
+fn discover() -> Result<Vec<Table>, AcpiError> {
+    let response = RSDP_REQUEST.get_response().ok_or(AcpiError::NoRsdp)?;
+
+    // older base revisions hand out a higher half pointer, newer ones the
+    // physical address
+    let address = response.address() as u64;
+    let rsdp_addr = if address >= paging::hhdm_offset() {
+        VirtAddr::new(address)
+    } else {
+        map_physical(address, size_of::<Rsdp>() as u64)
+    };
+
+    let v1 = unsafe { core::slice::from_raw_parts(rsdp_addr.as_ptr::<u8>(), RSDP_V1_LENGTH) };
+    let rsdp = unsafe { rsdp_addr.as_ptr::<Rsdp>().read_unaligned() };
+
+    if &rsdp.signature != b"RSD PTR " || !sdt::checksum_valid(v1) {
+        return Err(AcpiError::InvalidRsdp);
+    }
+
+    let (root, entry_size) = if rsdp.revision >= 2 && rsdp.xsdt_address != 0 {
+        let full = unsafe { core::slice::from_raw_parts(rsdp_addr.as_ptr::<u8>(), rsdp.length as usize) };
+        if !sdt::checksum_valid(full) {
+            return Err(AcpiError::InvalidRsdp);
+        }
+        (load(rsdp.xsdt_address)?, 8)
+    } else {
+        (load(rsdp.rsdt_address as u64)?, 4)
+    };
+
+    let mut tables = Vec::new();
+
+    for entry in root.body().chunks_exact(entry_size) {
+        let phys = match entry_size {
+            8 => u64::from_le_bytes(entry.try_into().unwrap()),
+            _ => u32::from_le_bytes(entry.try_into().unwrap()) as u64,
+        };
+
+        match load(phys) {
+            Ok(table) => tables.push(table),
+            Err(err) => println_log!("ACPI: skipping table at {:#x} ({:?})", phys, err),
+        }
+    }
+
+    // the DSDT isn't listed in the root table, only referenced by the FADT
+    let dsdt = tables
+        .iter()
+        .find(|table| &table.header.signature == b"FACP")
+        .map(|table| Fadt::parse(table).dsdt_address());
+
+    if let Some(dsdt) = dsdt.filter(|&addr| addr != 0) {
+        match load(dsdt) {
+            Ok(table) => tables.push(table),
+            Err(err) => println_log!("ACPI: skipping DSDT at {:#x} ({:?})", dsdt, err),
+        }
+    }
+
+    tables.insert(0, root);
+    Ok(tables)
+}
+
+/// Map and validate the table at `phys`.
+fn load(phys: u64) -> Result<Table, AcpiError> {
+    let header_addr = map_physical(phys, SDT_HEADER_LENGTH as u64);
+    let header = unsafe { header_addr.as_ptr::<SdtHeader>().read_unaligned() };
+    let length = header.length as usize;
+
+    if length < SDT_HEADER_LENGTH {
+        return Err(AcpiError::InvalidTable(header.signature));
+    }
+
+    let addr = map_physical(phys, length as u64);
+    let bytes = unsafe { core::slice::from_raw_parts(addr.as_ptr::<u8>(), length) };
+
+    if !sdt::checksum_valid(bytes) {
+        return Err(AcpiError::InvalidTable(header.signature));
+    }
+
+    Ok(Table { phys, header, bytes })
+}
+
+/// Get a virtual address for `len` bytes of firmware memory at `phys`. The
+/// higher half direct map is used if it covers the range, otherwise the
+/// range is mapped separately.
+fn map_physical(phys: u64, len: u64) -> VirtAddr {
+    let virt = paging::phys_to_virt(PhysAddr::new(phys));
+
+    if paging::translate(virt).is_some() && paging::translate(virt + (len.max(1) - 1)).is_some() {
+        virt
+    } else {
+        paging::map_mmio(PhysAddr::new(phys), len).expect("failed to map ACPI table")
+    }
+}
+
+/// Look up a table by its signature, e.g. `b"APIC"`.
+pub fn find(signature: &[u8; 4]) -> Option<Table> {
+    without_interrupts(|| {
+        TABLES
+            .lock()
+            .iter()
+            .find(|table| &table.header.signature == signature)
+            .copied()
+    })
+}
+
+pub fn tables() -> Vec<Table> {
+    without_interrupts(|| TABLES.lock().clone())
+}
+
+pub fn madt() -> Option<Madt> {
+    find(b"APIC").map(|table| Madt::parse(&table))
+}
+
+pub fn fadt() -> Option<Fadt> {
+    find(b"FACP").map(|table| Fadt::parse(&table))
+}
+
+pub fn hpet() -> Option<Hpet> {
+    find(b"HPET").map(|table| Hpet::parse(&table))
+}
+
+pub fn mcfg() -> Option<Mcfg> {
+    find(b"MCFG").map(|table| Mcfg::parse(&table))
+}
+
+/// `SLP_TYPx` values for sleep state `state` from the DSDT.
+pub fn sleep_type(state: u8) -> Option<dsdt::SleepType> {
+    find(b"DSDT").and_then(|table| dsdt::sleep_type(&table, state))
+}
+
+/// Log every discovered table and the contents of the ones we understand.
+pub fn dump() {
+    for table in tables() {
+        println_log!("{:?}", table);
+    }
+
+    if let Some(madt) = madt() {
+        println_log!("{:#?}", madt);
+    }
+    if let Some(fadt) = fadt() {
+        println_log!("{:#?}", fadt);
+    }
+    if let Some(hpet) = hpet() {
+        println_log!("{:#?}", hpet);
+    }
+    if let Some(mcfg) = mcfg() {
+        println_log!("{:#?}", mcfg);
+    }
+}
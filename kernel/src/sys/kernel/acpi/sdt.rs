@@ -0,0 +1,85 @@
+//! Raw ACPI table layouts shared by every table
+
+use core::mem::size_of;
+
+/// Root System Description Pointer (ACPI 2.0+ layout, the tail is only
+/// valid if `revision >= 2`).
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct Rsdp {
+    pub signature: [u8; 8],
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub revision: u8,
+    pub rsdt_address: u32,
+    pub length: u32,
+    pub xsdt_address: u64,
+    pub extended_checksum: u8,
+    pub reserved: [u8; 3],
+}
+
+/// Length of the ACPI 1.0 part of the RSDP covered by `checksum`.
+pub const RSDP_V1_LENGTH: usize = 20;
+
+/// Header every system description table starts with.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy)]
+pub struct SdtHeader {
+    pub signature: [u8; 4],
+    pub length: u32,
+    pub revision: u8,
+    pub checksum: u8,
+    pub oem_id: [u8; 6],
+    pub oem_table_id: [u8; 8],
+    pub oem_revision: u32,
+    pub creator_id: u32,
+    pub creator_revision: u32,
+}
+
+pub const SDT_HEADER_LENGTH: usize = size_of::<SdtHeader>();
+
+/// Generic Address Structure, describes a register in some address space.
+#[repr(C, packed)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GenericAddress {
+    pub address_space: u8,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub access_size: u8,
+    pub address: u64,
+}
+
+pub const ADDRESS_SPACE_MEMORY: u8 = 0;
+pub const ADDRESS_SPACE_IO: u8 = 1;
+pub const ADDRESS_SPACE_PCI: u8 = 2;
+
+impl GenericAddress {
+    pub fn is_present(&self) -> bool {
+        let address = self.address;
+        address != 0
+    }
+}
+
+/// ACPI checksums are valid if all bytes sum up to zero.
+pub fn checksum_valid(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) == 0
+}
+
+/// Read a `T` at `offset`, or `None` if the table is too short.
+pub fn read<T: Copy>(bytes: &[u8], offset: usize) -> Option<T> {
+    if offset + size_of::<T>() > bytes.len() {
+        return None;
+    }
+    Some(unsafe { (bytes.as_ptr().add(offset) as *const T).read_unaligned() })
+}
+
+/// Read a packed table body, zero filling fields past the end of older,
+/// shorter revisions of the table.
+pub fn read_padded<T: Copy>(bytes: &[u8]) -> T {
+    let mut value = core::mem::MaybeUninit::<T>::zeroed();
+    let len = bytes.len().min(size_of::<T>());
+    unsafe {
+        core::ptr::copy_nonoverlapping(bytes.as_ptr(), value.as_mut_ptr() as *mut u8, len);
+        value.assume_init()
+    }
+}
@@ -0,0 +1,36 @@
+//! Kernel command line
+//!
+//! Whatever `kernel_cmdline:` says in limine.conf, read as space separated
+//! `key=value` options. Limine hands it over with the kernel file, which
+//! [`symbols`](super::symbols) reads the symbol table from as well.
+
+use limine::file::File;
+use limine::request::KernelFileRequest;
+
+static KERNEL_FILE_REQUEST: KernelFileRequest = KernelFileRequest::new();
+
+/// The kernel ELF Limine booted, mapped for as long as we run.
+pub fn kernel_file() -> Option<&'static File> {
+    KERNEL_FILE_REQUEST.get_response().map(|response| response.file())
+}
+
+/// The whole command line, empty if there is none or it isn't UTF-8.
+pub fn get() -> &'static str {
+    kernel_file().and_then(|file| core::str::from_utf8(file.cmdline()).ok()).unwrap_or("")
+}
+
+/// The value of `key=value` on the command line, `""` for a bare `key`.
+pub fn option(key: &str) -> Option<&'static str> {
+    find(get(), key)
+}
+
+/// The value of `key` in `cmdline`, the last one wins if it is given twice.
+pub fn find<'a>(cmdline: &'a str, key: &str) -> Option<&'a str> {
+    cmdline
+        .split_ascii_whitespace()
+        .filter_map(|option| match option.split_once('=') {
+            Some((name, value)) => (name == key).then_some(value),
+            None => (option == key).then_some(""),
+        })
+        .next_back()
+}
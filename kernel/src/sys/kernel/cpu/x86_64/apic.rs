@@ -1,14 +1,168 @@
-// use core::arch::x86_64::__cpuid;
+//! Local APIC
+//!
+//! Supports both the memory mapped xAPIC and the MSR based x2APIC interface.
+//! The mode is picked once at init, afterwards register accesses go through
+//! [`read`] and [`write`] which hide the difference.
 
+use core::arch::x86_64::__cpuid;
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
 
+use x86_64::{registers::model_specific::Msr, PhysAddr};
 
-// static IA32_APIC_BASE_MSR: u32 = 0x1b;
-// static IA32_APIC_BASE_MSR_BSP: u32 = 0x100;
-// static IA32_APIC_BASE_MSR_ENABLE: u32 = 0x800;
+use crate::println_log;
 
-// fn check_apic() -> bool {
-//     let edx = unsafe { 
-//         __cpuid(1,) 
-//     }.edx;
-//     return edx & CPU_ID_FEAT_EDX_APIC != 0
-// }
\ No newline at end of file
+use super::paging;
+
+const IA32_APIC_BASE_MSR: u32 = 0x1b;
+const IA32_APIC_BASE_MSR_ENABLE: u64 = 1 << 11;
+const IA32_APIC_BASE_MSR_X2APIC: u64 = 1 << 10;
+const IA32_APIC_BASE_ADDR_MASK: u64 = 0x000f_ffff_ffff_f000;
+
+const CPUID_FEAT_EDX_APIC: u32 = 1 << 9;
+const CPUID_FEAT_ECX_X2APIC: u32 = 1 << 21;
+
+/// First MSR of the x2APIC register block, register `n` lives at `0x800 + (n >> 4)`.
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+// register offsets (xAPIC layout)
+pub const REG_ID: u32 = 0x20;
+pub const REG_VERSION: u32 = 0x30;
+pub const REG_TPR: u32 = 0x80;
+pub const REG_EOI: u32 = 0xb0;
+pub const REG_SVR: u32 = 0xf0;
+pub const REG_ESR: u32 = 0x280;
+pub const REG_LVT_TIMER: u32 = 0x320;
+pub const REG_LVT_LINT0: u32 = 0x350;
+pub const REG_LVT_LINT1: u32 = 0x360;
+pub const REG_LVT_ERROR: u32 = 0x370;
+pub const REG_TIMER_INITIAL: u32 = 0x380;
+pub const REG_TIMER_CURRENT: u32 = 0x390;
+pub const REG_TIMER_DIVIDE: u32 = 0x3e0;
+
+const SVR_ENABLE: u32 = 1 << 8;
+pub const LVT_MASKED: u32 = 1 << 16;
+
+/// Vector the local APIC delivers spurious interrupts on.
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+/// Vector for APIC internal errors.
+pub const ERROR_VECTOR: u8 = 0xfe;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ApicMode {
+    None = 0,
+    XApic = 1,
+    X2Apic = 2,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(ApicMode::None as u8);
+// virtual address of the xAPIC register page
+static BASE: AtomicU64 = AtomicU64::new(0);
+
+/// Check what kind of local APIC the CPU has.
+pub fn detect() -> ApicMode {
+    let features = __cpuid(1);
+
+    if features.ecx & CPUID_FEAT_ECX_X2APIC != 0 {
+        ApicMode::X2Apic
+    } else if features.edx & CPUID_FEAT_EDX_APIC != 0 {
+        ApicMode::XApic
+    } else {
+        ApicMode::None
+    }
+}
+
+pub fn mode() -> ApicMode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => ApicMode::XApic,
+        2 => ApicMode::X2Apic,
+        _ => ApicMode::None,
+    }
+}
+
+pub fn is_enabled() -> bool {
+    mode() != ApicMode::None
+}
+
+/// Enable the local APIC of the current CPU. Returns the mode it ended up
+/// in, `ApicMode::None` means there is no APIC and the legacy PIC has to be used.
+pub fn init() -> ApicMode {
+    let mode = detect();
+    if mode == ApicMode::None {
+        return mode;
+    }
+
+    let mut base_msr = Msr::new(IA32_APIC_BASE_MSR);
+    let base = unsafe { base_msr.read() };
+
+    // x2APIC can only be entered from an enabled xAPIC, so always enable first
+    unsafe { base_msr.write(base | IA32_APIC_BASE_MSR_ENABLE) };
+
+    if mode == ApicMode::X2Apic {
+        unsafe { base_msr.write(base | IA32_APIC_BASE_MSR_ENABLE | IA32_APIC_BASE_MSR_X2APIC) };
+    } else {
+        let phys = PhysAddr::new(base & IA32_APIC_BASE_ADDR_MASK);
+        let virt = paging::map_mmio(phys, 0x1000).expect("failed to map the local APIC");
+        BASE.store(virt.as_u64(), Ordering::SeqCst);
+    }
+
+    MODE.store(mode as u8, Ordering::SeqCst);
+
+    unsafe {
+        // accept every priority
+        write(REG_TPR, 0);
+
+        // LINT0/1 are wired to the legacy PIC and NMI, we don't use them
+        write(REG_LVT_LINT0, LVT_MASKED);
+        write(REG_LVT_LINT1, LVT_MASKED);
+        write(REG_LVT_TIMER, LVT_MASKED);
+        write(REG_LVT_ERROR, ERROR_VECTOR as u32);
+
+        // the error status register has to be written before it can be read
+        write(REG_ESR, 0);
+        write(REG_ESR, 0);
+
+        write(REG_SVR, SVR_ENABLE | SPURIOUS_VECTOR as u32);
+    }
+
+    end_of_interrupt();
+
+    println_log!("Local APIC enabled ({:?}, id {})...", mode, id());
+    mode
+}
+
+/// Read a local APIC register.
+///
+/// # Safety
+/// The APIC must be enabled and `reg` must be a readable register.
+pub unsafe fn read(reg: u32) -> u32 {
+    match mode() {
+        ApicMode::X2Apic => Msr::new(X2APIC_MSR_BASE + (reg >> 4)).read() as u32,
+        _ => ((BASE.load(Ordering::Relaxed) + reg as u64) as *const u32).read_volatile(),
+    }
+}
+
+/// Write a local APIC register.
+///
+/// # Safety
+/// The APIC must be enabled and `reg` must be a writable register.
+pub unsafe fn write(reg: u32, value: u32) {
+    match mode() {
+        ApicMode::X2Apic => Msr::new(X2APIC_MSR_BASE + (reg >> 4)).write(value as u64),
+        _ => ((BASE.load(Ordering::Relaxed) + reg as u64) as *mut u32).write_volatile(value),
+    }
+}
+
+/// APIC ID of the current CPU.
+pub fn id() -> u32 {
+    let id = unsafe { read(REG_ID) };
+    match mode() {
+        ApicMode::X2Apic => id,
+        _ => id >> 24,
+    }
+}
+
+/// Signal the end of the current interrupt.
+pub fn end_of_interrupt() {
+    unsafe { write(REG_EOI, 0) };
+}
@@ -0,0 +1,82 @@
+//! Stack walking
+//!
+//! The kernel is built with frame pointers (see `frame-pointer` in the
+//! target spec), so every function starts by pushing `rbp` and pointing
+//! `rbp` at it. Each frame then holds the caller's `rbp` with the return
+//! address right above it, and following that chain gives the call stack.
+
+use core::arch::asm;
+use core::fmt;
+
+use crate::sys::kernel::symbols;
+
+/// Walks deeper than this are cut off, in case the chain loops.
+pub const MAX_FRAMES: usize = 64;
+
+/// Return addresses up the call stack, innermost first.
+pub struct Frames {
+    rbp: u64,
+    depth: usize,
+}
+
+impl Frames {
+    /// Start walking at the frame `rbp` points to.
+    ///
+    /// # Safety
+    /// `rbp` must be zero or come from a chain of frame pointers on a
+    /// mapped stack.
+    pub unsafe fn from_rbp(rbp: u64) -> Self {
+        Self { rbp, depth: 0 }
+    }
+
+    /// Walk from wherever this is called, the first address is the
+    /// caller's.
+    #[inline(always)]
+    pub fn current() -> Self {
+        let rbp: u64;
+        unsafe {
+            asm!("mov {}, rbp", out(reg) rbp, options(nomem, nostack, preserves_flags));
+            Self::from_rbp(rbp)
+        }
+    }
+}
+
+/// Frames are 8 byte aligned in the higher half, anything else means the
+/// chain is broken.
+fn is_frame(rbp: u64) -> bool {
+    rbp % 8 == 0 && (0xffff_8000_0000_0000..=u64::MAX - 16).contains(&rbp)
+}
+
+impl Iterator for Frames {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        if self.depth >= MAX_FRAMES || !is_frame(self.rbp) {
+            return None;
+        }
+
+        let (caller_rbp, return_address) = unsafe {
+            let frame = self.rbp as *const u64;
+            (frame.read(), frame.add(1).read())
+        };
+        if return_address == 0 {
+            return None;
+        }
+
+        // callers' frames are further up the stack, stop rather than loop
+        self.rbp = if caller_rbp > self.rbp { caller_rbp } else { 0 };
+        self.depth += 1;
+        Some(return_address)
+    }
+}
+
+/// One `index: address function+offset` line per frame, starting with
+/// `rip` when the innermost address isn't a return address, e.g. where a
+/// fault happened.
+pub fn write(out: &mut impl fmt::Write, rip: Option<u64>, frames: Frames) -> fmt::Result {
+    let located = rip.map(symbols::locate).into_iter().chain(frames.map(symbols::locate_return));
+    for (index, frame) in located.enumerate() {
+        writeln!(out, "{:>3}: {}", index, frame)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,581 @@
+//! CPU exceptions
+//!
+//! Every one of the 32 exception vectors, reserved ones included, goes
+//! through a small assembly stub that saves the general purpose registers
+//! next to the frame the CPU pushed. From there one handler turns it into a
+//! [`Fault`]: which exception, its error code taken apart, and the full
+//! register state. [`action`] then decides what happens to whatever was
+//! running, in one place for every vector.
+//!
+//! Exceptions that are resumed are only counted where they happen and
+//! logged later from the timer: an NMI or a breakpoint can land while the
+//! logger's locks are held, so nothing on that path may take a lock.
+
+use core::arch::naked_asm;
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use x86_64::structures::idt::{DescriptorTable, Entry, HandlerFunc, InterruptDescriptorTable, PageFaultErrorCode, SelectorErrorCode};
+use x86_64::registers::rflags::RFlags;
+use x86_64::VirtAddr;
+
+use crate::sys::kernel::{panic, symbols, time};
+
+use super::{gdt, registers::Registers};
+
+/// Vectors the CPU reserves for exceptions.
+pub const EXCEPTION_VECTORS: usize = 32;
+
+/// How often resumed exceptions are logged.
+pub const REPORT_PERIOD: Duration = Duration::from_millis(100);
+
+// per vector: how many were resumed, how many of those were logged, and
+// where the last one happened
+static RESUMED: [AtomicU64; EXCEPTION_VECTORS] = [const { AtomicU64::new(0) }; EXCEPTION_VECTORS];
+static REPORTED: [AtomicU64; EXCEPTION_VECTORS] = [const { AtomicU64::new(0) }; EXCEPTION_VECTORS];
+static LAST_RIP: [AtomicU64; EXCEPTION_VECTORS] = [const { AtomicU64::new(0) }; EXCEPTION_VECTORS];
+
+/// The architectural exceptions, by vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    DivideError,
+    Debug,
+    NonMaskableInterrupt,
+    Breakpoint,
+    Overflow,
+    BoundRangeExceeded,
+    InvalidOpcode,
+    DeviceNotAvailable,
+    DoubleFault,
+    CoprocessorSegmentOverrun,
+    InvalidTss,
+    SegmentNotPresent,
+    StackSegmentFault,
+    GeneralProtectionFault,
+    PageFault,
+    X87FloatingPoint,
+    AlignmentCheck,
+    MachineCheck,
+    SimdFloatingPoint,
+    Virtualization,
+    ControlProtection,
+    HypervisorInjection,
+    VmmCommunication,
+    Security,
+    /// One of the vectors the CPU doesn't use, only `int` reaches these.
+    Reserved(u8),
+}
+
+impl Exception {
+    pub fn from_vector(vector: u8) -> Self {
+        match vector {
+            0 => Self::DivideError,
+            1 => Self::Debug,
+            2 => Self::NonMaskableInterrupt,
+            3 => Self::Breakpoint,
+            4 => Self::Overflow,
+            5 => Self::BoundRangeExceeded,
+            6 => Self::InvalidOpcode,
+            7 => Self::DeviceNotAvailable,
+            8 => Self::DoubleFault,
+            9 => Self::CoprocessorSegmentOverrun,
+            10 => Self::InvalidTss,
+            11 => Self::SegmentNotPresent,
+            12 => Self::StackSegmentFault,
+            13 => Self::GeneralProtectionFault,
+            14 => Self::PageFault,
+            16 => Self::X87FloatingPoint,
+            17 => Self::AlignmentCheck,
+            18 => Self::MachineCheck,
+            19 => Self::SimdFloatingPoint,
+            20 => Self::Virtualization,
+            21 => Self::ControlProtection,
+            28 => Self::HypervisorInjection,
+            29 => Self::VmmCommunication,
+            30 => Self::Security,
+            vector => Self::Reserved(vector),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::DivideError => "Divide Error",
+            Self::Debug => "Debug",
+            Self::NonMaskableInterrupt => "Non-Maskable Interrupt",
+            Self::Breakpoint => "Breakpoint",
+            Self::Overflow => "Overflow",
+            Self::BoundRangeExceeded => "Bound Range Exceeded",
+            Self::InvalidOpcode => "Invalid Opcode",
+            Self::DeviceNotAvailable => "Device Not Available",
+            Self::DoubleFault => "Double Fault",
+            Self::CoprocessorSegmentOverrun => "Coprocessor Segment Overrun",
+            Self::InvalidTss => "Invalid TSS",
+            Self::SegmentNotPresent => "Segment Not Present",
+            Self::StackSegmentFault => "Stack-Segment Fault",
+            Self::GeneralProtectionFault => "General Protection Fault",
+            Self::PageFault => "Page Fault",
+            Self::X87FloatingPoint => "x87 Floating-Point Exception",
+            Self::AlignmentCheck => "Alignment Check",
+            Self::MachineCheck => "Machine Check",
+            Self::SimdFloatingPoint => "SIMD Floating-Point Exception",
+            Self::Virtualization => "Virtualization Exception",
+            Self::ControlProtection => "Control Protection Exception",
+            Self::HypervisorInjection => "Hypervisor Injection Exception",
+            Self::VmmCommunication => "VMM Communication Exception",
+            Self::Security => "Security Exception",
+            Self::Reserved(_) => "Reserved",
+        }
+    }
+
+    /// `#PF` and the like, empty for the ones without.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Self::DivideError => "#DE",
+            Self::Debug => "#DB",
+            Self::NonMaskableInterrupt => "NMI",
+            Self::Breakpoint => "#BP",
+            Self::Overflow => "#OF",
+            Self::BoundRangeExceeded => "#BR",
+            Self::InvalidOpcode => "#UD",
+            Self::DeviceNotAvailable => "#NM",
+            Self::DoubleFault => "#DF",
+            Self::CoprocessorSegmentOverrun => "",
+            Self::InvalidTss => "#TS",
+            Self::SegmentNotPresent => "#NP",
+            Self::StackSegmentFault => "#SS",
+            Self::GeneralProtectionFault => "#GP",
+            Self::PageFault => "#PF",
+            Self::X87FloatingPoint => "#MF",
+            Self::AlignmentCheck => "#AC",
+            Self::MachineCheck => "#MC",
+            Self::SimdFloatingPoint => "#XM",
+            Self::Virtualization => "#VE",
+            Self::ControlProtection => "#CP",
+            Self::HypervisorInjection => "#HV",
+            Self::VmmCommunication => "#VC",
+            Self::Security => "#SX",
+            Self::Reserved(_) => "",
+        }
+    }
+
+    /// Whether the CPU pushes an error code for it.
+    pub fn has_error_code(self) -> bool {
+        matches!(
+            self,
+            Self::DoubleFault
+                | Self::InvalidTss
+                | Self::SegmentNotPresent
+                | Self::StackSegmentFault
+                | Self::GeneralProtectionFault
+                | Self::PageFault
+                | Self::AlignmentCheck
+                | Self::ControlProtection
+                | Self::VmmCommunication
+                | Self::Security
+        )
+    }
+
+    /// Nothing can continue after these, whatever was running.
+    pub fn is_abort(self) -> bool {
+        matches!(self, Self::DoubleFault | Self::MachineCheck)
+    }
+}
+
+/// `Page Fault (#PF)`.
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self, self.mnemonic()) {
+            (Self::Reserved(vector), _) => write!(f, "Reserved Vector {}", vector),
+            (_, "") => write!(f, "{}", self.name()),
+            (_, mnemonic) => write!(f, "{} ({})", self.name(), mnemonic),
+        }
+    }
+}
+
+/// An exception's error code, taken apart the way that exception defines it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// The exception doesn't push one.
+    None,
+    /// The segment, gate or TSS selector the exception is about.
+    Selector(SelectorErrorCode),
+    Page(PageFaultErrorCode),
+    /// Always zero, or a meaning of its own (`#CP`, `#VC`, `#SX`).
+    Other(u64),
+}
+
+impl ErrorCode {
+    pub fn decode(exception: Exception, code: u64) -> Self {
+        match exception {
+            Exception::InvalidTss
+            | Exception::SegmentNotPresent
+            | Exception::StackSegmentFault
+            | Exception::GeneralProtectionFault => Self::Selector(SelectorErrorCode::new_truncate(code)),
+            Exception::PageFault => Self::Page(PageFaultErrorCode::from_bits_retain(code)),
+            exception if exception.has_error_code() => Self::Other(code),
+            _ => Self::None,
+        }
+    }
+}
+
+/// What the bits mean, e.g. `write to a page not present in kernel mode`.
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::None => write!(f, "none"),
+            Self::Selector(selector) if selector.is_null() => write!(f, "no selector"),
+            Self::Selector(selector) => {
+                let table = match selector.descriptor_table() {
+                    DescriptorTable::Gdt => "GDT",
+                    DescriptorTable::Idt => "IDT",
+                    DescriptorTable::Ldt => "LDT",
+                };
+                write!(f, "{} entry {}", table, selector.index())?;
+                if selector.external() {
+                    write!(f, ", during an external event")?;
+                }
+                Ok(())
+            }
+            Self::Page(page) => {
+                let access = if page.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+                    "instruction fetch from"
+                } else if page.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+                    "write to"
+                } else {
+                    "read from"
+                };
+                let cause = if page.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+                    "a page it isn't allowed on"
+                } else {
+                    "a page not present"
+                };
+                let mode = if page.contains(PageFaultErrorCode::USER_MODE) { "user" } else { "kernel" };
+                write!(f, "{} {} in {} mode", access, cause, mode)?;
+
+                for (flag, name) in [
+                    (PageFaultErrorCode::MALFORMED_TABLE, "reserved bit set"),
+                    (PageFaultErrorCode::PROTECTION_KEY, "protection key"),
+                    (PageFaultErrorCode::SHADOW_STACK, "shadow stack"),
+                ] {
+                    if page.contains(flag) {
+                        write!(f, ", {}", name)?;
+                    }
+                }
+                Ok(())
+            }
+            Self::Other(code) => write!(f, "{:#x}", code),
+        }
+    }
+}
+
+/// The privilege level the exception came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Kernel,
+    User,
+}
+
+/// Everything known about an exception when it happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub vector: u8,
+    pub exception: Exception,
+    /// As the CPU pushed it, zero if the exception has none.
+    pub error_code: u64,
+    pub error: ErrorCode,
+    pub mode: Mode,
+    /// As they were at the faulting instruction, `cr2` included.
+    pub registers: Registers,
+}
+
+impl Fault {
+    pub fn new(vector: u8, error_code: u64, registers: Registers) -> Self {
+        let exception = Exception::from_vector(vector);
+        Self {
+            vector,
+            exception,
+            error_code,
+            error: ErrorCode::decode(exception, error_code),
+            mode: if registers.cs & 3 == 0 { Mode::Kernel } else { Mode::User },
+            registers,
+        }
+    }
+
+    /// The address a page fault was about.
+    pub fn address(&self) -> Option<u64> {
+        (self.exception == Exception::PageFault).then_some(self.registers.cr2)
+    }
+}
+
+/// A few lines on what happened and where, the registers are left to the
+/// caller.
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self.mode {
+            Mode::Kernel => "kernel",
+            Mode::User => "user",
+        };
+        writeln!(f, "{}, vector {}, in {} mode", self.exception, self.vector, mode)?;
+        if self.exception.has_error_code() {
+            writeln!(f, "error code {:#x}: {}", self.error_code, self.error)?;
+        }
+        if let Some(address) = self.address() {
+            writeln!(f, "address {:#018x}", address)?;
+        }
+        writeln!(f, "at {}", symbols::locate(self.registers.rip))
+    }
+}
+
+/// What becomes of the code that took an exception.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Note it and carry on.
+    Resume,
+    /// Stop the offending task and let everything else run.
+    KillTask,
+    /// Take the kernel down.
+    Panic,
+}
+
+/// The policy for every exception.
+pub fn action(fault: &Fault) -> Action {
+    match fault.exception {
+        exception if exception.is_abort() => Action::Panic,
+        // not caused by whatever was running
+        Exception::NonMaskableInterrupt => Action::Resume,
+        _ if fault.mode == Mode::User => Action::KillTask,
+        Exception::Breakpoint | Exception::Debug => Action::Resume,
+        _ => Action::Panic,
+    }
+}
+
+/// How many times exceptions on `vector` were resumed since boot.
+pub fn resumed(vector: u8) -> u64 {
+    RESUMED[vector as usize].load(Ordering::Relaxed)
+}
+
+/// Log the exceptions resumed since the last call, one line per vector.
+pub fn report_resumed() {
+    for (vector, (resumed, reported)) in RESUMED.iter().zip(&REPORTED).enumerate() {
+        let count = resumed.load(Ordering::Relaxed);
+        // the timer's report can interrupt one called directly, whichever
+        // loaded the larger count logs the difference
+        let new = count.saturating_sub(reported.fetch_max(count, Ordering::Relaxed));
+        if new > 0 {
+            let rip = LAST_RIP[vector].load(Ordering::Relaxed);
+            log::warn!("{} x{}, last at {}", Exception::from_vector(vector as u8), new, symbols::locate(rip));
+        }
+    }
+}
+
+/// Log resumed exceptions every [`REPORT_PERIOD`] from now on. Needs the
+/// timer running.
+pub fn init() {
+    time::every(REPORT_PERIOD, report_resumed);
+}
+
+/// What the stubs leave on the stack, lowest address first: the general
+/// purpose registers, the vector, the error code (zero if the exception
+/// has none) and the interrupt stack frame.
+#[derive(Debug)]
+#[repr(C)]
+struct ExceptionFrame {
+    rax: u64,
+    rbx: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    rbp: u64,
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    vector: u64,
+    error_code: u64,
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+impl ExceptionFrame {
+    fn registers(&self) -> Registers {
+        let mut registers = Registers {
+            rax: self.rax,
+            rbx: self.rbx,
+            rcx: self.rcx,
+            rdx: self.rdx,
+            rsi: self.rsi,
+            rdi: self.rdi,
+            rbp: self.rbp,
+            rsp: self.rsp,
+            r8: self.r8,
+            r9: self.r9,
+            r10: self.r10,
+            r11: self.r11,
+            r12: self.r12,
+            r13: self.r13,
+            r14: self.r14,
+            r15: self.r15,
+            rip: self.rip,
+            rflags: self.rflags,
+            cs: self.cs & 0xffff,
+            ss: self.ss & 0xffff,
+            ..Registers::default()
+        };
+        registers.capture_control();
+        registers
+    }
+}
+
+// called by `common_stub` with everything saved, returning resumes the
+// interrupted code with the frame as it is then
+extern "C" fn dispatch(frame: &mut ExceptionFrame) {
+    // first, before anything else can fault and change cr2
+    let fault = Fault::new(frame.vector as u8, frame.error_code, frame.registers());
+
+    match action(&fault) {
+        Action::Resume => {
+            if fault.exception == Exception::Debug {
+                // single stepping would trap again after the next
+                // instruction and an instruction breakpoint on the same one
+                frame.rflags &= !RFlags::TRAP_FLAG.bits();
+                frame.rflags |= RFlags::RESUME_FLAG.bits();
+            }
+            LAST_RIP[fault.vector as usize].store(fault.registers.rip, Ordering::Relaxed);
+            RESUMED[fault.vector as usize].fetch_add(1, Ordering::Relaxed);
+        }
+        // there are no tasks yet, so the kernel is all there is to stop
+        Action::KillTask | Action::Panic => panic::fault(&fault),
+    }
+}
+
+#[unsafe(naked)]
+unsafe extern "C" fn common_stub() -> ! {
+    naked_asm!(
+        "push r15",
+        "push r14",
+        "push r13",
+        "push r12",
+        "push r11",
+        "push r10",
+        "push r9",
+        "push r8",
+        "push rbp",
+        "push rdi",
+        "push rsi",
+        "push rdx",
+        "push rcx",
+        "push rbx",
+        "push rax",
+        // the CPU aligned the stack before its frame, with the error code,
+        // vector and registers on top it is aligned again for the call
+        "mov rdi, rsp",
+        "cld",
+        "call {dispatch}",
+        "pop rax",
+        "pop rbx",
+        "pop rcx",
+        "pop rdx",
+        "pop rsi",
+        "pop rdi",
+        "pop rbp",
+        "pop r8",
+        "pop r9",
+        "pop r10",
+        "pop r11",
+        "pop r12",
+        "pop r13",
+        "pop r14",
+        "pop r15",
+        // vector and error code
+        "add rsp, 16",
+        "iretq",
+        dispatch = sym dispatch,
+    )
+}
+
+/// Entry point for one vector, pushing a zero in place of the error code
+/// the CPU only pushes for some, so every frame looks the same.
+macro_rules! stub {
+    ($vector:literal) => {{
+        #[unsafe(naked)]
+        unsafe extern "C" fn stub() -> ! {
+            naked_asm!("push 0", "push {}", "jmp {}", const $vector, sym common_stub)
+        }
+        stub
+    }};
+    ($vector:literal, error_code) => {{
+        #[unsafe(naked)]
+        unsafe extern "C" fn stub() -> ! {
+            naked_asm!("push {}", "jmp {}", const $vector, sym common_stub)
+        }
+        stub
+    }};
+}
+
+static STUBS: [unsafe extern "C" fn() -> !; EXCEPTION_VECTORS] = [
+    stub!(0),
+    stub!(1),
+    stub!(2),
+    stub!(3),
+    stub!(4),
+    stub!(5),
+    stub!(6),
+    stub!(7),
+    stub!(8, error_code),
+    stub!(9),
+    stub!(10, error_code),
+    stub!(11, error_code),
+    stub!(12, error_code),
+    stub!(13, error_code),
+    stub!(14, error_code),
+    stub!(15),
+    stub!(16),
+    stub!(17, error_code),
+    stub!(18),
+    stub!(19),
+    stub!(20),
+    stub!(21, error_code),
+    stub!(22),
+    stub!(23),
+    stub!(24),
+    stub!(25),
+    stub!(26),
+    stub!(27),
+    stub!(28),
+    stub!(29, error_code),
+    stub!(30, error_code),
+    stub!(31),
+];
+
+/// Point every exception vector of `idt` at its stub.
+pub fn install(idt: &mut InterruptDescriptorTable) {
+    // the crate keeps the reserved vectors' entries private, every entry
+    // has the same layout whatever handler type it is declared with
+    let entries = unsafe { &mut *(idt as *mut InterruptDescriptorTable as *mut [Entry<HandlerFunc>; 256]) };
+
+    for (vector, (entry, stub)) in entries.iter_mut().zip(STUBS).enumerate() {
+        let options = unsafe { entry.set_handler_addr(VirtAddr::new(stub as usize as u64)) };
+
+        // a double fault is often a kernel stack overflow, it needs a good
+        // stack; NMIs and machine checks can come in at any instruction,
+        // whatever state the stack is in then
+        let stack = match Exception::from_vector(vector as u8) {
+            Exception::DoubleFault => Some(gdt::DOUBLE_FAULT_IST_INDEX),
+            Exception::NonMaskableInterrupt => Some(gdt::NMI_IST_INDEX),
+            Exception::MachineCheck => Some(gdt::MACHINE_CHECK_IST_INDEX),
+            _ => None,
+        };
+        if let Some(stack) = stack {
+            unsafe { options.set_stack_index(stack) };
+        }
+    }
+}
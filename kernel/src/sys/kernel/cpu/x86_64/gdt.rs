@@ -12,18 +12,23 @@ use lazy_static::lazy_static;
 use crate::println_log;
 
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const NMI_IST_INDEX: u16 = 1;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+
+const IST_STACKS: usize = 3;
+const STACK_SIZE: usize = 4096 * 8;
+
+// one each, an NMI or machine check can arrive while the double fault
+// handler is running
+static mut STACKS: [[u8; STACK_SIZE]; IST_STACKS] = [[0; STACK_SIZE]; IST_STACKS];
 
 lazy_static! {
     static ref TSS: TaskStateSegment = {
         let mut tss = TaskStateSegment::new();
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = 4096 * 8;
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-
-            let stack_start = VirtAddr::from_ptr(unsafe { &STACK });
-            let stack_end = stack_start + STACK_SIZE.try_into().unwrap();
-            stack_end
-        };
+        for index in [DOUBLE_FAULT_IST_INDEX, NMI_IST_INDEX, MACHINE_CHECK_IST_INDEX] {
+            let stack_start = VirtAddr::from_ptr(unsafe { &raw const STACKS[index as usize] });
+            tss.interrupt_stack_table[index as usize] = stack_start + STACK_SIZE as u64;
+        }
         tss
     };
 }
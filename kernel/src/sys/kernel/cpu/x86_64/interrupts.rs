@@ -1,10 +1,13 @@
 use core::arch::asm;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use lazy_static::lazy_static;
 use spin::lazy;
-use x86_64::{instructions::{self, hlt, interrupts}, structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode}};
+use x86_64::{instructions::{self, hlt, interrupts}, structures::idt::{InterruptDescriptorTable, InterruptStackFrame}};
 
-use crate::{println_log, serial_println, sys::kernel::cpu::gdt};
+use crate::{println_log, serial_println, sys::kernel::{drivers::{ps2::{keyboard, mouse}, rtc, serial::serial}, time}};
+
+use super::{apic, exceptions, ioapic};
 
 // use super::pics::ChainedPics;
 
@@ -13,6 +16,12 @@ use pic8259::ChainedPics;
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
+/// IRQ line the second PIC is chained to.
+const CASCADE_IRQ: u8 = 2;
+
+// set once the I/O APIC has taken over from the 8259s
+static USING_APIC: AtomicBool = AtomicBool::new(false);
+
 pub static PICS: spin::Mutex<ChainedPics> = spin::Mutex::new(
     unsafe { 
         ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) 
@@ -24,13 +33,22 @@ pub static PICS: spin::Mutex<ChainedPics> = spin::Mutex::new(
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    Com2 = PIC_1_OFFSET + 3,
+    Com1 = PIC_1_OFFSET + 4,
+    Rtc = PIC_2_OFFSET,
+    Mouse = PIC_2_OFFSET + 4,
 }
 
 impl InterruptIndex {
-    fn as_u8(self) -> u8 {
+    pub fn as_u8(self) -> u8 {
         self as u8
     }
 
+    /// The ISA IRQ line behind this vector.
+    pub fn irq(self) -> u8 {
+        self.as_u8() - PIC_1_OFFSET
+    }
+
     fn as_usize(self) -> usize {
         usize::from(self.as_u8())
     }
@@ -39,21 +57,25 @@ impl InterruptIndex {
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
-        idt.page_fault.set_handler_fn(page_fault_handler);
-        idt.general_protection_fault.set_handler_fn(general_protection_fault_handler);
-
-
-        unsafe {
-            idt.double_fault.set_handler_fn(double_fault_handler)
-            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
-        }
+        exceptions::install(&mut idt);
 
         idt[InterruptIndex::Timer.as_u8()].set_handler_fn(timer_interrupt_handler);
 
         idt[InterruptIndex::Keyboard.as_u8()]
             .set_handler_fn(keyboard_interrupt_handler);
 
+        idt[InterruptIndex::Com1.as_u8()].set_handler_fn(com1_interrupt_handler);
+        idt[InterruptIndex::Com2.as_u8()].set_handler_fn(com2_interrupt_handler);
+        idt[InterruptIndex::Rtc.as_u8()].set_handler_fn(rtc_interrupt_handler);
+        idt[InterruptIndex::Mouse.as_u8()].set_handler_fn(mouse_interrupt_handler);
+
+        // IRQ7 and IRQ15 are where the 8259s deliver spurious interrupts
+        idt[PIC_1_OFFSET + 7].set_handler_fn(master_spurious_interrupt_handler);
+        idt[PIC_2_OFFSET + 7].set_handler_fn(slave_spurious_interrupt_handler);
+
+        idt[apic::ERROR_VECTOR].set_handler_fn(apic_error_interrupt_handler);
+        idt[apic::SPURIOUS_VECTOR].set_handler_fn(apic_spurious_interrupt_handler);
+
         idt
     };
 }
@@ -62,93 +84,43 @@ pub fn init() {
     IDT.load();
     println_log!("Loaded IDT...");
 
+    // remap the 8259s even if we end up not using them, so spurious
+    // interrupts they raise don't land on exception vectors
     unsafe {
         PICS.lock().initialize();
-        PICS.lock().write_masks(0xfc, 0xff);
-        // println_log!("PIC initialized with masks: {:02x}, {:02x}", 
-        //     PICS.lock().read_masks()[0],
-        //     PICS.lock().read_masks()[1]
-        // );
-        println_log!("weird");
+        PICS.lock().disable();
     }
 
+    if apic::init() != apic::ApicMode::None && ioapic::init() {
+        USING_APIC.store(true, Ordering::SeqCst);
+        println_log!("Legacy PIC masked, using the APIC...");
+    } else {
+        // everything but the cascade from the second PIC
+        unsafe { PICS.lock().write_masks(!(1 << CASCADE_IRQ), 0xff) };
+        println_log!("No APIC found, using the legacy PIC...");
+    }
+
+    enable_irq(InterruptIndex::Timer);
+    enable_irq(InterruptIndex::Keyboard);
+
     unsafe { asm!("sti"); }
     println_log!("Enabled interrupts...");
 }
 
-extern "x86-interrupt" fn timer_interrupt_handler(stack_frame: InterruptStackFrame) {
-    without(|| {
-        println_log!("Timer interrupt!");
-    });
+extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    time::tick();
 
-    unsafe {
-        PICS.lock().notify_end_of_interrupt(InterruptIndex::Timer.as_u8());
-    }
+    end_of_interrupt(InterruptIndex::Timer);
 }
 
-extern "x86-interrupt" fn general_protection_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: u64,
-) {
-    without(|| {
-        // hlt();
-
-        let rsp: u64;
-        unsafe {
-            asm!("mov {}, rsp", out(reg) rsp);
-        }
-        serial_println!("EXCEPTION: GENERAL PROTECTION FAULT");
-        serial_println!("Error Code: {:#x}", error_code);
-        serial_println!("RSP: {:#x}", rsp);
-        serial_println!("Instruction Pointer: {:#x}", stack_frame.instruction_pointer.as_u64());
-        serial_println!("Stack Pointer: {:#x}", stack_frame.stack_pointer.as_u64());
-        serial_println!("CPU Flags: {:#x}", stack_frame.cpu_flags.bits());
-        serial_println!("Code Segment: {:?}", stack_frame.code_segment);
-        serial_println!("Stack Segment: {:?}", stack_frame.stack_segment);
-    });
-    
-    panic!("EXCEPTION: GENERAL PROTECTION FAULT");
-}
-
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    without(|| {
-        println_log!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
-        serial_println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
-    });
-    
-    loop {}
-}
-
-extern "x86-interrupt" fn double_fault_handler(stack_frame: InterruptStackFrame, _error_code: u64) -> ! {
-    without(|| {
-        serial_println!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
-    });
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame)
-}
-
-extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
-    use x86_64::registers::control::Cr2;
-
-    without(|| {
-        serial_println!("EXCEPTION: PAGE FAULT");
-        serial_println!("Accessed Address: {:?}", Cr2::read());
-        serial_println!("Error Code: {:?}", error_code);
-        serial_println!("Stack Frame: {:#?}", stack_frame);
-    });
-    
-    panic!("EXCEPTION: PAGE FAULT");
-}
-
-pub fn without(func: impl Fn()) {
+pub fn without<R>(func: impl FnOnce() -> R) -> R {
     if are_enabled() {
         unsafe { asm!("cli"); }
-        func();
+        let result = func();
         unsafe { asm!("sti"); }
+        result
     } else {
-        func();
+        func()
     }
 }
 
@@ -164,12 +136,110 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStac
     use x86_64::instructions::port::Port;
 
     let mut port = Port::new(0x60);
+    let scancode: u8 = unsafe { port.read() };
 
-    serial_println!("KEYBOARD INTERRUPT");
+    keyboard::handle_byte(scancode);
 
-    let scancode: u8 = unsafe { port.read() };
+    end_of_interrupt(InterruptIndex::Keyboard);
+}
+
+extern "x86-interrupt" fn mouse_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    use x86_64::instructions::port::Port;
+
+    let mut port = Port::new(0x60);
+    let data: u8 = unsafe { port.read() };
+
+    mouse::handle_byte(data);
+
+    end_of_interrupt(InterruptIndex::Mouse);
+}
+
+/// Whether hardware interrupts arrive through the I/O APIC instead of the 8259s.
+pub fn using_apic() -> bool {
+    USING_APIC.load(Ordering::Relaxed)
+}
+
+/// Unmask the IRQ behind `index` on whichever controller is in use.
+pub fn enable_irq(index: InterruptIndex) {
+    if using_apic() {
+        ioapic::route_irq(index.irq(), index.as_u8());
+    } else {
+        without(|| unsafe {
+            let mut pics = PICS.lock();
+            let mut masks = pics.read_masks();
+            let irq = index.irq();
+            masks[irq as usize / 8] &= !(1 << (irq % 8));
+            pics.write_masks(masks[0], masks[1]);
+        });
+    }
+}
+
+pub fn disable_irq(index: InterruptIndex) {
+    if using_apic() {
+        ioapic::mask_irq(index.irq());
+    } else {
+        without(|| unsafe {
+            let mut pics = PICS.lock();
+            let mut masks = pics.read_masks();
+            let irq = index.irq();
+            masks[irq as usize / 8] |= 1 << (irq % 8);
+            pics.write_masks(masks[0], masks[1]);
+        });
+    }
+}
+
+/// Acknowledge a hardware interrupt on whichever controller delivered it.
+pub fn end_of_interrupt(index: InterruptIndex) {
+    if USING_APIC.load(Ordering::Relaxed) {
+        apic::end_of_interrupt();
+    } else {
+        unsafe {
+            PICS.lock().notify_end_of_interrupt(index.as_u8());
+        }
+    }
+}
+
+extern "x86-interrupt" fn com1_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    serial::handle_interrupt(InterruptIndex::Com1);
 
+    end_of_interrupt(InterruptIndex::Com1);
+}
+
+extern "x86-interrupt" fn com2_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    serial::handle_interrupt(InterruptIndex::Com2);
+
+    end_of_interrupt(InterruptIndex::Com2);
+}
+
+extern "x86-interrupt" fn rtc_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    rtc::handle_interrupt();
+
+    end_of_interrupt(InterruptIndex::Rtc);
+}
+
+extern "x86-interrupt" fn master_spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // nothing to acknowledge, a spurious IRQ never set the in-service bit
+}
+
+extern "x86-interrupt" fn slave_spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // the slave set nothing, but the master took it as a real IRQ on the
+    // cascade line and needs its EOI, or IRQ2 and everything of lower
+    // priority stays blocked
     unsafe {
-        PICS.lock().notify_end_of_interrupt(InterruptIndex::Keyboard.as_u8());
+        PICS.lock().notify_end_of_interrupt(PIC_1_OFFSET + CASCADE_IRQ);
     }
+}
+
+extern "x86-interrupt" fn apic_spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    // spurious APIC interrupts must not be acknowledged with an EOI
+}
+
+extern "x86-interrupt" fn apic_error_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    let status = unsafe {
+        apic::write(apic::REG_ESR, 0);
+        apic::read(apic::REG_ESR)
+    };
+
+    serial_println!("APIC ERROR: status {:#x}", status);
+    apic::end_of_interrupt();
 }
\ No newline at end of file
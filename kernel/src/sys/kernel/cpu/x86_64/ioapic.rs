@@ -0,0 +1,254 @@
+//! I/O APIC
+//!
+//! Routes legacy ISA IRQs and other global system interrupts (GSIs) to local
+//! APIC vectors. Which I/O APICs exist and how ISA IRQs map onto GSIs comes
+//! from the ACPI MADT.
+
+use alloc::vec::Vec;
+
+use spin::Mutex;
+use x86_64::{instructions::interrupts::without_interrupts, PhysAddr, VirtAddr};
+
+use crate::println_log;
+use crate::sys::kernel::acpi;
+
+use super::{apic, paging};
+
+const IOREGSEL: u64 = 0x00;
+const IOWIN: u64 = 0x10;
+
+const REG_ID: u32 = 0x00;
+const REG_VERSION: u32 = 0x01;
+const REG_REDIRECTION_BASE: u32 = 0x10;
+
+const REDIRECTION_MASKED: u64 = 1 << 16;
+const REDIRECTION_LEVEL: u64 = 1 << 15;
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+
+// MPS INTI flags used by interrupt source overrides
+const POLARITY_MASK: u16 = 0b11;
+const POLARITY_ACTIVE_LOW: u16 = 0b11;
+const TRIGGER_MASK: u16 = 0b11 << 2;
+const TRIGGER_LEVEL: u16 = 0b11 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge,
+    Level,
+}
+
+/// An ISA IRQ that is not identity mapped onto a GSI.
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger: TriggerMode,
+}
+
+impl InterruptOverride {
+    pub fn from_flags(irq: u8, gsi: u32, flags: u16) -> Self {
+        // "conforms to the bus" means edge triggered, active high for ISA
+        Self {
+            irq,
+            gsi,
+            polarity: if flags & POLARITY_MASK == POLARITY_ACTIVE_LOW {
+                Polarity::ActiveLow
+            } else {
+                Polarity::ActiveHigh
+            },
+            trigger: if flags & TRIGGER_MASK == TRIGGER_LEVEL {
+                TriggerMode::Level
+            } else {
+                TriggerMode::Edge
+            },
+        }
+    }
+}
+
+pub struct IoApic {
+    id: u8,
+    base: VirtAddr,
+    gsi_base: u32,
+    entries: u32,
+}
+
+// only ever touched with the ROUTING lock held
+unsafe impl Send for IoApic {}
+
+impl IoApic {
+    /// # Safety
+    /// `phys` must be the register base of an I/O APIC.
+    pub unsafe fn new(phys: PhysAddr, gsi_base: u32) -> Self {
+        let base = paging::map_mmio(phys, 0x20).expect("failed to map I/O APIC");
+
+        let mut ioapic = Self {
+            id: 0,
+            base,
+            gsi_base,
+            entries: 0,
+        };
+
+        ioapic.id = ((ioapic.read(REG_ID) >> 24) & 0x0f) as u8;
+        ioapic.entries = ((ioapic.read(REG_VERSION) >> 16) & 0xff) + 1;
+        ioapic
+    }
+
+    unsafe fn read(&self, reg: u32) -> u32 {
+        (self.base + IOREGSEL).as_mut_ptr::<u32>().write_volatile(reg);
+        (self.base + IOWIN).as_ptr::<u32>().read_volatile()
+    }
+
+    unsafe fn write(&mut self, reg: u32, value: u32) {
+        (self.base + IOREGSEL).as_mut_ptr::<u32>().write_volatile(reg);
+        (self.base + IOWIN).as_mut_ptr::<u32>().write_volatile(value);
+    }
+
+    pub fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.entries
+    }
+
+    fn read_entry(&self, gsi: u32) -> u64 {
+        let reg = REG_REDIRECTION_BASE + (gsi - self.gsi_base) * 2;
+        unsafe { self.read(reg) as u64 | (self.read(reg + 1) as u64) << 32 }
+    }
+
+    fn write_entry(&mut self, gsi: u32, entry: u64) {
+        let reg = REG_REDIRECTION_BASE + (gsi - self.gsi_base) * 2;
+        unsafe {
+            // keep the entry masked while it's half written
+            self.write(reg, REDIRECTION_MASKED as u32);
+            self.write(reg + 1, (entry >> 32) as u32);
+            self.write(reg, entry as u32);
+        }
+    }
+
+    /// Send `gsi` to `vector` on the local APIC `dest`.
+    pub fn route(&mut self, gsi: u32, vector: u8, dest: u32, polarity: Polarity, trigger: TriggerMode) {
+        let mut entry = vector as u64 | (dest as u64 & 0xff) << 56;
+        if polarity == Polarity::ActiveLow {
+            entry |= REDIRECTION_ACTIVE_LOW;
+        }
+        if trigger == TriggerMode::Level {
+            entry |= REDIRECTION_LEVEL;
+        }
+        self.write_entry(gsi, entry);
+    }
+
+    pub fn set_masked(&mut self, gsi: u32, masked: bool) {
+        let entry = self.read_entry(gsi);
+        if masked {
+            self.write_entry(gsi, entry | REDIRECTION_MASKED);
+        } else {
+            self.write_entry(gsi, entry & !REDIRECTION_MASKED);
+        }
+    }
+
+    pub fn mask_all(&mut self) {
+        for gsi in self.gsi_base..self.gsi_base + self.entries {
+            self.write_entry(gsi, REDIRECTION_MASKED);
+        }
+    }
+}
+
+struct Routing {
+    ioapics: Vec<IoApic>,
+    overrides: Vec<InterruptOverride>,
+}
+
+static ROUTING: Mutex<Routing> = Mutex::new(Routing {
+    ioapics: Vec::new(),
+    overrides: Vec::new(),
+});
+
+/// Find the I/O APICs and ISA overrides and mask every input. Returns false
+/// if the MADT lists no I/O APIC.
+pub fn init() -> bool {
+    let Some(madt) = acpi::madt() else {
+        return false;
+    };
+
+    let overrides: Vec<InterruptOverride> = madt
+        .overrides
+        .iter()
+        .map(|iso| InterruptOverride::from_flags(iso.irq, iso.gsi, iso.flags))
+        .collect();
+
+    without_interrupts(|| {
+        let mut routing = ROUTING.lock();
+
+        for entry in madt.io_apics.iter() {
+            let (address, gsi_base) = (entry.address as u64, entry.gsi_base);
+            let mut ioapic = unsafe { IoApic::new(PhysAddr::new(address), gsi_base) };
+            ioapic.mask_all();
+            println_log!(
+                "I/O APIC {} at {:#x}, GSIs {}..{}...",
+                ioapic.id,
+                address,
+                gsi_base,
+                gsi_base + ioapic.entries
+            );
+            routing.ioapics.push(ioapic);
+        }
+
+        for iso in overrides.iter() {
+            println_log!("IRQ {} -> GSI {} ({:?}, {:?})", iso.irq, iso.gsi, iso.polarity, iso.trigger);
+        }
+        routing.overrides = overrides;
+
+        !routing.ioapics.is_empty()
+    })
+}
+
+/// Resolve an ISA IRQ to its GSI and signal properties.
+pub fn isa_irq(irq: u8) -> InterruptOverride {
+    without_interrupts(|| {
+        ROUTING
+            .lock()
+            .overrides
+            .iter()
+            .find(|iso| iso.irq == irq)
+            .copied()
+            .unwrap_or(InterruptOverride {
+                irq,
+                gsi: irq as u32,
+                polarity: Polarity::ActiveHigh,
+                trigger: TriggerMode::Edge,
+            })
+    })
+}
+
+/// Route an ISA IRQ to `vector` on the current CPU and unmask it.
+pub fn route_irq(irq: u8, vector: u8) {
+    let iso = isa_irq(irq);
+    route_gsi(iso.gsi, vector, iso.polarity, iso.trigger);
+}
+
+/// Route a GSI to `vector` on the current CPU and unmask it.
+pub fn route_gsi(gsi: u32, vector: u8, polarity: Polarity, trigger: TriggerMode) {
+    let dest = apic::id();
+
+    without_interrupts(|| {
+        let mut routing = ROUTING.lock();
+        match routing.ioapics.iter_mut().find(|ioapic| ioapic.handles(gsi)) {
+            Some(ioapic) => ioapic.route(gsi, vector, dest, polarity, trigger),
+            None => println_log!("No I/O APIC handles GSI {}", gsi),
+        }
+    });
+}
+
+pub fn mask_irq(irq: u8) {
+    let gsi = isa_irq(irq).gsi;
+
+    without_interrupts(|| {
+        if let Some(ioapic) = ROUTING.lock().ioapics.iter_mut().find(|ioapic| ioapic.handles(gsi)) {
+            ioapic.set_masked(gsi, true);
+        }
+    });
+}
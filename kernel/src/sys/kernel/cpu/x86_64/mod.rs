@@ -1,10 +1,15 @@
 pub mod interrupts;
 pub mod gdt;
 pub mod apic;
+pub mod ioapic;
+pub mod paging;
+pub mod registers;
+pub mod backtrace;
+pub mod exceptions;
 
 mod pics;
 
 pub fn init() {
     gdt::init();
-    interrupts::init();
+    paging::init();
 }
\ No newline at end of file
@@ -0,0 +1,182 @@
+//! Kernel page table management
+//!
+//! Limine leaves us running on its own page tables with all of physical memory
+//! mapped at the higher half direct map (HHDM). We keep using those tables and
+//! wrap them in an `OffsetPageTable` so new mappings can be added on top.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use limine::request::HhdmRequest;
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts::without_interrupts,
+    registers::control::Cr3,
+    structures::paging::{
+        mapper::{FlagUpdateError, MapToError, TranslateError, UnmapError},
+        Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+        Translate,
+    },
+    PhysAddr, VirtAddr,
+};
+
+use crate::println_log;
+use crate::sys::kernel::memory::frame::GlobalFrameAllocator;
+
+static HHDM_REQUEST: HhdmRequest = HhdmRequest::new();
+
+/// Start of the virtual window MMIO regions get mapped into.
+pub const MMIO_START: u64 = 0xffff_d000_0000_0000;
+/// Size of the MMIO window (64 GiB).
+pub const MMIO_SIZE: u64 = 64 * 1024 * 1024 * 1024;
+
+static HHDM_OFFSET: AtomicU64 = AtomicU64::new(0);
+static MMIO_NEXT: AtomicU64 = AtomicU64::new(MMIO_START);
+
+static MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+
+/// Flags used for device memory: caching disabled and writes go straight through.
+pub const MMIO_FLAGS: PageTableFlags = PageTableFlags::PRESENT
+    .union(PageTableFlags::WRITABLE)
+    .union(PageTableFlags::NO_CACHE)
+    .union(PageTableFlags::WRITE_THROUGH);
+
+/// Why [`map_mmio`] failed.
+#[derive(Debug)]
+pub enum MmioError {
+    /// The MMIO window has no room left for the region.
+    WindowExhausted,
+    /// Mapping one of its pages failed, nothing of the region stays mapped.
+    Map(MapToError<Size4KiB>),
+}
+
+pub fn init() {
+    let Some(response) = HHDM_REQUEST.get_response() else {
+        panic!("HHDM request failed");
+    };
+
+    HHDM_OFFSET.store(response.offset(), Ordering::SeqCst);
+
+    let (level_4_frame, _) = Cr3::read();
+    let level_4_table = phys_to_virt(level_4_frame.start_address()).as_mut_ptr::<PageTable>();
+
+    without_interrupts(|| {
+        *MAPPER.lock() = Some(unsafe {
+            OffsetPageTable::new(&mut *level_4_table, VirtAddr::new(response.offset()))
+        });
+    });
+
+    println_log!("Paging ready, HHDM at {:#x}...", response.offset());
+}
+
+/// Offset of the higher half direct map.
+pub fn hhdm_offset() -> u64 {
+    HHDM_OFFSET.load(Ordering::Relaxed)
+}
+
+/// Virtual address of `addr` inside the higher half direct map.
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(addr.as_u64() + hhdm_offset())
+}
+
+fn with_mapper<R>(func: impl FnOnce(&mut OffsetPageTable<'static>) -> R) -> R {
+    without_interrupts(|| {
+        let mut mapper = MAPPER.lock();
+        func(mapper.as_mut().expect("paging not initialised"))
+    })
+}
+
+/// Map a single page (4 KiB or 2 MiB) to a frame, allocating page tables as
+/// needed.
+///
+/// # Safety
+/// Mapping a frame that is already in use elsewhere can alias memory.
+pub unsafe fn map<S: PageSize>(
+    page: Page<S>,
+    frame: PhysFrame<S>,
+    flags: PageTableFlags,
+) -> Result<(), MapToError<S>>
+where
+    OffsetPageTable<'static>: Mapper<S>,
+{
+    with_mapper(|mapper| {
+        mapper
+            .map_to(page, frame, flags, &mut GlobalFrameAllocator)
+            .map(|flush| flush.flush())
+    })
+}
+
+/// Remove the mapping of a page and return the frame it pointed to. The
+/// frame itself is not freed.
+pub fn unmap<S: PageSize>(page: Page<S>) -> Result<PhysFrame<S>, UnmapError>
+where
+    OffsetPageTable<'static>: Mapper<S>,
+{
+    with_mapper(|mapper| {
+        mapper.unmap(page).map(|(frame, flush)| {
+            flush.flush();
+            frame
+        })
+    })
+}
+
+/// Change the flags of an already mapped page.
+///
+/// # Safety
+/// Changing flags of memory that is in use (e.g. dropping `WRITABLE` on the
+/// stack) can break the kernel.
+pub unsafe fn protect<S: PageSize>(page: Page<S>, flags: PageTableFlags) -> Result<(), FlagUpdateError>
+where
+    OffsetPageTable<'static>: Mapper<S>,
+{
+    with_mapper(|mapper| mapper.update_flags(page, flags).map(|flush| flush.flush()))
+}
+
+/// Translate a virtual address, regardless of the page size backing it.
+pub fn translate(addr: VirtAddr) -> Option<PhysAddr> {
+    with_mapper(|mapper| mapper.translate_addr(addr))
+}
+
+/// Translate a page of a specific size to the frame backing it.
+pub fn translate_page<S: PageSize>(page: Page<S>) -> Result<PhysFrame<S>, TranslateError>
+where
+    OffsetPageTable<'static>: Mapper<S>,
+{
+    with_mapper(|mapper| mapper.translate_page(page))
+}
+
+/// Map `size` bytes of device memory at `phys` with caching disabled and
+/// return the virtual address of `phys`.
+pub fn map_mmio(phys: PhysAddr, size: u64) -> Result<VirtAddr, MmioError> {
+    let first = PhysFrame::<Size4KiB>::containing_address(phys);
+    let last = PhysFrame::<Size4KiB>::containing_address(phys + size.max(1) - 1u64);
+    let pages = last - first + 1;
+
+    // only take the window space once it is known to fit, so failed
+    // requests don't use any of it up
+    let len = pages * Size4KiB::SIZE;
+    let mut start = MMIO_NEXT.load(Ordering::SeqCst);
+    loop {
+        if start + len > MMIO_START + MMIO_SIZE {
+            return Err(MmioError::WindowExhausted);
+        }
+        match MMIO_NEXT.compare_exchange_weak(start, start + len, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) => break,
+            Err(current) => start = current,
+        }
+    }
+
+    let start_page = Page::<Size4KiB>::containing_address(VirtAddr::new(start));
+
+    for (i, frame) in PhysFrame::range_inclusive(first, last).enumerate() {
+        if let Err(error) = unsafe { map(start_page + i as u64, frame, MMIO_FLAGS) } {
+            for page in Page::range(start_page, start_page + i as u64) {
+                let _ = unmap(page);
+            }
+            // the space can only go back if nothing was taken after it
+            let _ = MMIO_NEXT.compare_exchange(start + len, start, Ordering::SeqCst, Ordering::SeqCst);
+            return Err(MmioError::Map(error));
+        }
+    }
+
+    Ok(start_page.start_address() + phys.as_u64() % Size4KiB::SIZE)
+}
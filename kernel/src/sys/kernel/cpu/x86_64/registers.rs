@@ -0,0 +1,119 @@
+//! CPU register snapshots
+//!
+//! What a panic or fault report shows of the machine state.
+
+use core::arch::asm;
+use core::fmt;
+
+/// The general purpose, instruction pointer, flags, segment and control
+/// registers at one point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct Registers {
+    // filled by offset in `capture`, keep these first and in this order
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+
+    pub rip: u64,
+    pub rflags: u64,
+    pub cs: u64,
+    pub ss: u64,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+}
+
+impl Registers {
+    /// The registers where this is called from. One general purpose
+    /// register holds the address being written to instead of its own
+    /// value, there is no getting around that without a stack switch.
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut registers = Self::default();
+
+        unsafe {
+            asm!(
+                "mov [{0} + 0x00], rax",
+                "mov [{0} + 0x08], rbx",
+                "mov [{0} + 0x10], rcx",
+                "mov [{0} + 0x18], rdx",
+                "mov [{0} + 0x20], rsi",
+                "mov [{0} + 0x28], rdi",
+                "mov [{0} + 0x30], rbp",
+                "mov [{0} + 0x38], rsp",
+                "mov [{0} + 0x40], r8",
+                "mov [{0} + 0x48], r9",
+                "mov [{0} + 0x50], r10",
+                "mov [{0} + 0x58], r11",
+                "mov [{0} + 0x60], r12",
+                "mov [{0} + 0x68], r13",
+                "mov [{0} + 0x70], r14",
+                "mov [{0} + 0x78], r15",
+                in(reg) &mut registers as *mut Self,
+                options(nostack, preserves_flags),
+            );
+
+            asm!("lea {}, [rip]", out(reg) registers.rip, options(nomem, nostack, preserves_flags));
+            asm!("pushfq", "pop {}", out(reg) registers.rflags, options(nomem, preserves_flags));
+            asm!("mov {:x}, cs", out(reg) registers.cs, options(nomem, nostack, preserves_flags));
+            asm!("mov {:x}, ss", out(reg) registers.ss, options(nomem, nostack, preserves_flags));
+        }
+
+        // the segment moves only set the low 16 bits
+        registers.cs &= 0xffff;
+        registers.ss &= 0xffff;
+        registers.capture_control();
+        registers
+    }
+
+    /// Fill in the control registers with their current values, e.g. after
+    /// taking the rest from an exception frame.
+    pub fn capture_control(&mut self) {
+        unsafe {
+            asm!("mov {}, cr0", out(reg) self.cr0, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr2", out(reg) self.cr2, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr3", out(reg) self.cr3, options(nomem, nostack, preserves_flags));
+            asm!("mov {}, cr4", out(reg) self.cr4, options(nomem, nostack, preserves_flags));
+        }
+    }
+}
+
+/// Three registers to a line.
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers = [
+            ("RAX", self.rax), ("RBX", self.rbx), ("RCX", self.rcx),
+            ("RDX", self.rdx), ("RSI", self.rsi), ("RDI", self.rdi),
+            ("RBP", self.rbp), ("RSP", self.rsp), ("R8", self.r8),
+            ("R9", self.r9), ("R10", self.r10), ("R11", self.r11),
+            ("R12", self.r12), ("R13", self.r13), ("R14", self.r14),
+            ("R15", self.r15), ("RIP", self.rip), ("RFL", self.rflags),
+            ("CS", self.cs), ("SS", self.ss), ("CR0", self.cr0),
+            ("CR2", self.cr2), ("CR3", self.cr3), ("CR4", self.cr4),
+        ];
+
+        for line in registers.chunks(3) {
+            for (index, (name, value)) in line.iter().enumerate() {
+                let separator = if index == 0 { "" } else { "  " };
+                write!(f, "{}{:>3} {:016x}", separator, name, value)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! Kernel log buffer
+//!
+//! Every record the logger takes is kept here, in a fixed block of memory
+//! that needs no heap, so it holds what was logged before the console was
+//! up and what has since scrolled off or been cleared. When it fills up the
+//! oldest records make room.
+//!
+//! Records are numbered in the order they came in. A reader remembers the
+//! number after the last record it saw and asks for everything from there
+//! to follow new output, like `dmesg -w`.
+
+use core::fmt::{self, Write};
+use core::time::Duration;
+
+use log::Level;
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+/// Bytes of records the kernel keeps.
+pub const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Longer messages are cut short.
+pub const MAX_MESSAGE: usize = 1024;
+
+/// Longer targets are cut short.
+pub const MAX_TARGET: usize = 255;
+
+// sequence, nanoseconds, level, target length, message length
+const HEADER: usize = 8 + 8 + 1 + 1 + 2;
+
+static BUFFER: Mutex<LogBuffer<BUFFER_SIZE>> = Mutex::new(LogBuffer::new());
+
+/// One log record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry<'a> {
+    pub sequence: u64,
+    /// Uptime when it was logged.
+    pub timestamp: Duration,
+    pub level: Level,
+    pub target: &'a str,
+    pub message: &'a str,
+}
+
+/// `[uptime] LEVEL target: message`, without a newline.
+impl fmt::Display for Entry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{:>5}.{:06}] {:<5} {}: {}",
+            self.timestamp.as_secs(),
+            self.timestamp.subsec_micros(),
+            self.level,
+            self.target,
+            self.message
+        )
+    }
+}
+
+/// A message formatted on the stack, cut at [`MAX_MESSAGE`] bytes.
+pub struct Message {
+    bytes: [u8; MAX_MESSAGE],
+    len: usize,
+}
+
+impl Message {
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; MAX_MESSAGE],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only ever filled with whole characters
+        unsafe { core::str::from_utf8_unchecked(&self.bytes[..self.len]) }
+    }
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let s = truncate(s, MAX_MESSAGE - self.len);
+        self.bytes[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+/// The longest start of `s` that fits in `max` bytes without splitting a
+/// character.
+fn truncate(s: &str, max: usize) -> &str {
+    let mut len = s.len().min(max);
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    &s[..len]
+}
+
+fn level_from(value: u8) -> Level {
+    Level::iter().nth(value as usize - 1).unwrap_or(Level::Error)
+}
+
+/// Records packed one after another in `N` bytes, wrapping around at the
+/// end.
+pub struct LogBuffer<const N: usize> {
+    data: [u8; N],
+    // both only ever count up, the offset is the position modulo N
+    head: u64,
+    tail: u64,
+    /// Sequence number of the record at `head`.
+    first: u64,
+    /// Sequence number the next record gets.
+    next: u64,
+}
+
+impl<const N: usize> LogBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            data: [0; N],
+            head: 0,
+            tail: 0,
+            first: 0,
+            next: 0,
+        }
+    }
+
+    fn copy_in(&mut self, position: u64, bytes: &[u8]) {
+        let offset = (position % N as u64) as usize;
+        let split = bytes.len().min(N - offset);
+        self.data[offset..offset + split].copy_from_slice(&bytes[..split]);
+        self.data[..bytes.len() - split].copy_from_slice(&bytes[split..]);
+    }
+
+    fn copy_out(&self, position: u64, bytes: &mut [u8]) {
+        let offset = (position % N as u64) as usize;
+        let split = bytes.len().min(N - offset);
+        bytes[..split].copy_from_slice(&self.data[offset..offset + split]);
+        let rest = bytes.len() - split;
+        bytes[split..].copy_from_slice(&self.data[..rest]);
+    }
+
+    /// Header of the record at `position`: sequence, nanoseconds, level,
+    /// target length and message length.
+    fn header(&self, position: u64) -> (u64, u64, u8, usize, usize) {
+        let mut header = [0; HEADER];
+        self.copy_out(position, &mut header);
+
+        let word = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+        let message = u16::from_le_bytes([header[18], header[19]]) as usize;
+        (word(0), word(8), header[16], header[17] as usize, message)
+    }
+
+    /// Keep `entry`, dropping the oldest records to make room. Returns the
+    /// sequence number it was given, its own is ignored.
+    pub fn push(&mut self, entry: &Entry) -> u64 {
+        let target = truncate(entry.target, MAX_TARGET);
+        let message = truncate(entry.message, MAX_MESSAGE.min(N - HEADER - target.len()));
+        let size = (HEADER + target.len() + message.len()) as u64;
+
+        while self.tail + size - self.head > N as u64 {
+            let (_, _, _, target, message) = self.header(self.head);
+            self.head += (HEADER + target + message) as u64;
+            self.first += 1;
+        }
+
+        let sequence = self.next;
+        let mut header = [0; HEADER];
+        header[..8].copy_from_slice(&sequence.to_le_bytes());
+        header[8..16].copy_from_slice(&(entry.timestamp.as_nanos() as u64).to_le_bytes());
+        header[16] = entry.level as u8;
+        header[17] = target.len() as u8;
+        header[18..].copy_from_slice(&(message.len() as u16).to_le_bytes());
+
+        self.copy_in(self.tail, &header);
+        self.copy_in(self.tail + HEADER as u64, target.as_bytes());
+        self.copy_in(self.tail + (HEADER + target.len()) as u64, message.as_bytes());
+
+        self.tail += size;
+        self.next += 1;
+        sequence
+    }
+
+    /// Call `func` on every record numbered `from` or later, oldest first.
+    /// Returns the number to pass next time to see only newer records.
+    pub fn read(&self, from: u64, mut func: impl FnMut(&Entry)) -> u64 {
+        let mut text = [0; MAX_TARGET + MAX_MESSAGE];
+        let mut position = self.head;
+
+        while position < self.tail {
+            let (sequence, nanos, level, target, message) = self.header(position);
+            let size = HEADER + target + message;
+
+            if sequence >= from {
+                self.copy_out(position + HEADER as u64, &mut text[..target + message]);
+                // written from whole `&str`s
+                let (target, message) = unsafe {
+                    (
+                        core::str::from_utf8_unchecked(&text[..target]),
+                        core::str::from_utf8_unchecked(&text[target..target + message]),
+                    )
+                };
+
+                func(&Entry {
+                    sequence,
+                    timestamp: Duration::from_nanos(nanos),
+                    level: level_from(level),
+                    target,
+                    message,
+                });
+            }
+            position += size as u64;
+        }
+
+        self.next
+    }
+
+    /// Sequence number of the oldest record still kept.
+    pub fn first_sequence(&self) -> u64 {
+        self.first
+    }
+
+    /// Sequence number the next record will get.
+    pub fn next_sequence(&self) -> u64 {
+        self.next
+    }
+
+    pub fn len(&self) -> usize {
+        (self.next - self.first) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<const N: usize> Default for LogBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keep `entry` in the kernel log, returns its sequence number.
+pub fn push(entry: &Entry) -> u64 {
+    without_interrupts(|| BUFFER.lock().push(entry))
+}
+
+/// Call `func` on every kept record numbered `from` or later, oldest first,
+/// and return the number to continue from. `func` must not log, the buffer
+/// is locked while it runs.
+pub fn read(from: u64, func: impl FnMut(&Entry)) -> u64 {
+    without_interrupts(|| BUFFER.lock().read(from, func))
+}
+
+/// Call `func` on the last `count` records, e.g. for a panic report.
+pub fn tail(count: usize, func: impl FnMut(&Entry)) {
+    without_interrupts(|| {
+        let buffer = BUFFER.lock();
+        buffer.read(buffer.next_sequence().saturating_sub(count as u64), func);
+    });
+}
+
+/// Release the buffer lock whoever holds it, for the panic path.
+///
+/// # Safety
+/// Nothing else may be using the buffer, i.e. the kernel is going down.
+pub unsafe fn force_unlock() {
+    if BUFFER.is_locked() {
+        BUFFER.force_unlock();
+    }
+}
+
+pub fn first_sequence() -> u64 {
+    without_interrupts(|| BUFFER.lock().first_sequence())
+}
+
+pub fn next_sequence() -> u64 {
+    without_interrupts(|| BUFFER.lock().next_sequence())
+}
@@ -1,9 +1,28 @@
-use x86_64::instructions::port::Port;
+use core::ptr::NonNull;
+
+use spin::Once;
+use x86_64::{instructions::port::Port, PhysAddr, VirtAddr};
 
 use crate::println_log;
+use crate::sys::kernel::cpu::x86_64::paging;
 
 use super::types::*;
 
+// generic host control plus 32 port register blocks
+const HBA_MEM_SIZE: u64 = 0x100 + 32 * 0x80;
+
+static HBA: Once<VirtAddr> = Once::new();
+
+/// Map the HBA registers found at `abar` (PCI BAR5) as uncached MMIO. Only
+/// the first call maps them, later ones get the same registers back. The
+/// device changes them under us, so go through the pointer with volatile
+/// accesses rather than holding a reference.
+pub fn map_hba(abar: PhysAddr) -> NonNull<HbaMem> {
+    let addr = HBA.call_once(|| paging::map_mmio(abar, HBA_MEM_SIZE).expect("failed to map AHCI registers"));
+    // the MMIO window is nowhere near address zero
+    NonNull::new(addr.as_mut_ptr::<HbaMem>()).unwrap()
+}
+
 pub fn probe_port(abar: &HbaMem) {
     let pi = abar.pi;
 
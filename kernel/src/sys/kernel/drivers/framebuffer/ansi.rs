@@ -0,0 +1,227 @@
+//! ANSI/VT100 escape sequence parser
+//!
+//! Splits a byte stream into printable characters, C0 controls and escape
+//! sequences. Only the parsing happens here, what a sequence does to the
+//! screen is up to the [`TextWriter`](super::textwriter::TextWriter).
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+// cancel the sequence in progress
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+const DEL: u8 = 0x7f;
+
+/// More parameters than this are dropped.
+pub const MAX_PARAMS: usize = 16;
+
+/// The 16 basic colours, normal then bright, as on a VGA text console.
+pub const COLOURS: [u32; 16] = [
+    0x000000, 0xAA0000, 0x00AA00, 0xAA5500, 0x0000AA, 0xAA00AA, 0x00AAAA, 0xAAAAAA,
+    0x555555, 0xFF5555, 0x55FF55, 0xFFFF55, 0x5555FF, 0xFF55FF, 0x55FFFF, 0xFFFFFF,
+];
+
+// channel levels of the 6x6x6 colour cube
+const CUBE_LEVELS: [u32; 6] = [0x00, 0x5f, 0x87, 0xaf, 0xd7, 0xff];
+
+/// Colour `index` of the xterm 256 colour palette.
+pub fn colour_256(index: u8) -> u32 {
+    match index {
+        0..=15 => COLOURS[index as usize],
+        16..=231 => {
+            let index = index as usize - 16;
+            CUBE_LEVELS[index / 36] << 16 | CUBE_LEVELS[index / 6 % 6] << 8 | CUBE_LEVELS[index % 6]
+        }
+        232..=255 => {
+            let level = 8 + 10 * (index as u32 - 232);
+            level << 16 | level << 8 | level
+        }
+    }
+}
+
+/// A complete control sequence, `ESC [ <private> <params> <intermediate> <action>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Csi {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    /// `?`, `<`, `=` or `>` straight after the `[`, 0 if there is none.
+    pub private: u8,
+    /// Last byte from 0x20..=0x2f before the action, 0 if there is none.
+    pub intermediate: u8,
+    /// The final byte that says what to do, e.g. `m` for SGR.
+    pub action: u8,
+}
+
+impl Csi {
+    const fn new() -> Self {
+        Self {
+            params: [0; MAX_PARAMS],
+            len: 0,
+            private: 0,
+            intermediate: 0,
+            action: 0,
+        }
+    }
+
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+
+    /// Parameter `index`, or `default` if it was left out or is 0.
+    pub fn param(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Draw this byte.
+    Print(u8),
+    /// A C0 control such as `\n` or backspace.
+    Execute(u8),
+    /// `ESC <byte>` without a `[`, e.g. `ESC 7` to save the cursor.
+    Escape(u8),
+    Csi(Csi),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    /// `ESC ( B` and friends, character set selection which we ignore.
+    EscapeIntermediate,
+    Csi,
+    /// Skip the rest of a malformed control sequence.
+    CsiIgnore,
+    /// Operating system command, e.g. a window title. Ignored.
+    Osc,
+}
+
+pub struct Parser {
+    state: State,
+    csi: Csi,
+    // a parameter digit or separator has been seen
+    has_params: bool,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Ground,
+            csi: Csi::new(),
+            has_params: false,
+        }
+    }
+
+    /// Feed one byte, returns what to do once something is complete.
+    pub fn advance(&mut self, byte: u8) -> Option<Action> {
+        match byte {
+            CAN | SUB => {
+                self.state = State::Ground;
+                return None;
+            }
+            ESC => {
+                self.state = State::Escape;
+                return None;
+            }
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => match byte {
+                0x00..=0x1f => Some(Action::Execute(byte)),
+                DEL => None,
+                _ => Some(Action::Print(byte)),
+            },
+            State::Escape => match byte {
+                b'[' => {
+                    self.csi = Csi::new();
+                    self.has_params = false;
+                    self.state = State::Csi;
+                    None
+                }
+                b']' => {
+                    self.state = State::Osc;
+                    None
+                }
+                0x00..=0x1f => Some(Action::Execute(byte)),
+                0x20..=0x2f => {
+                    self.state = State::EscapeIntermediate;
+                    None
+                }
+                // also ends an OSC string with `ESC \`
+                _ => {
+                    self.state = State::Ground;
+                    (byte != b'\\' && byte != DEL).then_some(Action::Escape(byte))
+                }
+            },
+            State::EscapeIntermediate => match byte {
+                0x00..=0x1f => Some(Action::Execute(byte)),
+                0x20..=0x2f => None,
+                _ => {
+                    self.state = State::Ground;
+                    None
+                }
+            },
+            State::Csi => self.csi_byte(byte),
+            State::CsiIgnore => match byte {
+                0x00..=0x1f => Some(Action::Execute(byte)),
+                0x40..=0x7e => {
+                    self.state = State::Ground;
+                    None
+                }
+                _ => None,
+            },
+            State::Osc => {
+                if byte == BEL {
+                    self.state = State::Ground;
+                }
+                None
+            }
+        }
+    }
+
+    fn csi_byte(&mut self, byte: u8) -> Option<Action> {
+        let csi = &mut self.csi;
+
+        match byte {
+            // controls still work in the middle of a sequence
+            0x00..=0x1f => return Some(Action::Execute(byte)),
+            b'0'..=b'9' if csi.intermediate == 0 => {
+                let param = &mut csi.params[csi.len.min(MAX_PARAMS - 1)];
+                *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                self.has_params = true;
+            }
+            b';' if csi.intermediate == 0 => {
+                if csi.len < MAX_PARAMS {
+                    csi.len += 1;
+                }
+                self.has_params = true;
+            }
+            b'<'..=b'?' if !self.has_params && csi.private == 0 && csi.intermediate == 0 => {
+                csi.private = byte;
+            }
+            0x20..=0x2f => csi.intermediate = byte,
+            0x40..=0x7e => {
+                if self.has_params && csi.len < MAX_PARAMS {
+                    csi.len += 1;
+                }
+                csi.action = byte;
+                self.state = State::Ground;
+                return Some(Action::Csi(*csi));
+            }
+            // `:` sub-parameters and anything out of place
+            _ => self.state = State::CsiIgnore,
+        }
+
+        None
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
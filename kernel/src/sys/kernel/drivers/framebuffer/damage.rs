@@ -0,0 +1,129 @@
+//! Damage tracking
+//!
+//! Remembers which parts of the back buffer changed since the last flush so
+//! only those get copied to video memory.
+
+/// An axis aligned rectangle in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    pub fn right(&self) -> usize {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> usize {
+        self.y + self.height
+    }
+
+    /// The smallest rectangle covering both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        Rect::new(x, y, self.right().max(other.right()) - x, self.bottom().max(other.bottom()) - y)
+    }
+
+    /// The part both have in common, empty if they don't overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let (right, bottom) = (self.right().min(other.right()), self.bottom().min(other.bottom()));
+
+        if right <= x || bottom <= y {
+            return Rect::default();
+        }
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    /// Overlapping or sharing an edge.
+    pub fn touches(&self, other: &Rect) -> bool {
+        self.x <= other.right() && other.x <= self.right() && self.y <= other.bottom() && other.y <= self.bottom()
+    }
+
+    pub fn area(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+/// More separate rectangles than this are merged into their bounding box.
+pub const MAX_RECTS: usize = 16;
+
+/// A set of dirty rectangles, kept small by merging neighbours.
+#[derive(Debug, Clone, Default)]
+pub struct Damage {
+    rects: [Rect; MAX_RECTS],
+    len: usize,
+}
+
+impl Damage {
+    pub const fn new() -> Self {
+        Self {
+            rects: [Rect::new(0, 0, 0, 0); MAX_RECTS],
+            len: 0,
+        }
+    }
+
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+
+        // grow whatever it touches, then keep merging while that touches more
+        let mut merged = rect;
+        let mut index = 0;
+        while index < self.len {
+            if self.rects[index].touches(&merged) {
+                merged = merged.union(&self.rects[index]);
+                self.len -= 1;
+                self.rects[index] = self.rects[self.len];
+                index = 0;
+            } else {
+                index += 1;
+            }
+        }
+
+        if self.len == MAX_RECTS {
+            let bounds = self.bounds().union(&merged);
+            self.rects[0] = bounds;
+            self.len = 1;
+        } else {
+            self.rects[self.len] = merged;
+            self.len += 1;
+        }
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects[..self.len]
+    }
+
+    /// Bounding box of everything dirty.
+    pub fn bounds(&self) -> Rect {
+        self.rects().iter().fold(Rect::default(), |bounds, rect| bounds.union(rect))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
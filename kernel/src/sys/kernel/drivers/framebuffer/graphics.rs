@@ -0,0 +1,306 @@
+//! 2D drawing primitives
+//!
+//! Everything draws through a [`Canvas`], which clips to a rectangle and
+//! works on any [`Surface`]: the framebuffer or a plain block of memory.
+//! Coordinates are signed so shapes may hang off the edges. Colours are
+//! `0xRRGGBB`, bitmaps carry alpha as `0xAARRGGBB`.
+//!
+//! Drawing to the framebuffer goes into its back buffer when there is one,
+//! call [`render::flush`](super::render::flush) to show the result.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::damage::Rect;
+use super::render::FramebufferWriter;
+
+/// Something pixels can be drawn on. Coordinates passed in are always
+/// inside the surface.
+pub trait Surface {
+    fn width(&self) -> usize;
+    fn height(&self) -> usize;
+    fn put_pixel(&mut self, x: usize, y: usize, color: u32);
+    fn get_pixel(&mut self, x: usize, y: usize) -> u32;
+
+    /// Set `len` pixels of row `y` starting at `x`.
+    fn fill_span(&mut self, x: usize, y: usize, len: usize, color: u32) {
+        for x in x..x + len {
+            self.put_pixel(x, y, color);
+        }
+    }
+}
+
+impl Surface for FramebufferWriter<'_> {
+    fn width(&self) -> usize {
+        FramebufferWriter::width(self) as usize
+    }
+
+    fn height(&self) -> usize {
+        FramebufferWriter::height(self) as usize
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: u32) {
+        self.write_pixel(x, y, color);
+    }
+
+    fn get_pixel(&mut self, x: usize, y: usize) -> u32 {
+        self.read_pixel(x, y).unwrap_or(0)
+    }
+
+    fn fill_span(&mut self, x: usize, y: usize, len: usize, color: u32) {
+        self.fill_rect(x, y, len, 1, color);
+    }
+}
+
+/// A surface kept in RAM, for off screen drawing and tests.
+pub struct MemorySurface {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+}
+
+impl MemorySurface {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Row by row, `0xRRGGBB`.
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u32 {
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl Surface for MemorySurface {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: u32) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    fn get_pixel(&mut self, x: usize, y: usize) -> u32 {
+        self.pixel(x, y)
+    }
+
+    fn fill_span(&mut self, x: usize, y: usize, len: usize, color: u32) {
+        let start = y * self.width + x;
+        self.pixels[start..start + len].fill(color);
+    }
+}
+
+/// An image to blit, `0xAARRGGBB` row by row.
+#[derive(Debug, Clone, Copy)]
+pub struct Bitmap<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [u32],
+}
+
+impl<'a> Bitmap<'a> {
+    pub fn new(width: usize, height: usize, pixels: &'a [u32]) -> Self {
+        assert!(pixels.len() >= width * height, "bitmap is missing pixels");
+        Self { width, height, pixels }
+    }
+}
+
+/// Mix `0xAARRGGBB` over `0xRRGGBB`.
+pub fn blend(src: u32, dst: u32) -> u32 {
+    let alpha = src >> 24;
+    match alpha {
+        0 => dst,
+        255 => src & 0xffffff,
+        _ => [16, 8, 0].into_iter().fold(0, |color, shift| {
+            let (s, d) = ((src >> shift) & 0xff, (dst >> shift) & 0xff);
+            color | ((s * alpha + d * (255 - alpha) + 127) / 255) << shift
+        }),
+    }
+}
+
+pub struct Canvas<'s, S: Surface> {
+    surface: &'s mut S,
+    clip: Rect,
+}
+
+impl<'s, S: Surface> Canvas<'s, S> {
+    pub fn new(surface: &'s mut S) -> Self {
+        let clip = Rect::new(0, 0, surface.width(), surface.height());
+        Self { surface, clip }
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.surface.width(), self.surface.height())
+    }
+
+    /// Only draw inside `clip` from now on, it is kept within the surface.
+    pub fn set_clip(&mut self, clip: Rect) {
+        self.clip = clip.intersection(&self.bounds());
+    }
+
+    pub fn reset_clip(&mut self) {
+        self.clip = self.bounds();
+    }
+
+    pub fn clip(&self) -> Rect {
+        self.clip
+    }
+
+    /// The part of the given rectangle that is inside the clip, in surface
+    /// coordinates.
+    fn clipped(&self, x: i32, y: i32, width: u32, height: u32) -> Rect {
+        let (left, top) = (x.max(self.clip.x as i32), y.max(self.clip.y as i32));
+        let right = (x as i64 + width as i64).min(self.clip.right() as i64);
+        let bottom = (y as i64 + height as i64).min(self.clip.bottom() as i64);
+
+        if right <= left as i64 || bottom <= top as i64 {
+            return Rect::default();
+        }
+        Rect::new(left as usize, top as usize, (right - left as i64) as usize, (bottom - top as i64) as usize)
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.clip.x as i32 && y >= self.clip.y as i32 && (x as i64) < self.clip.right() as i64 && (y as i64) < self.clip.bottom() as i64
+    }
+
+    pub fn pixel(&mut self, x: i32, y: i32, color: u32) {
+        if self.contains(x, y) {
+            self.surface.put_pixel(x as usize, y as usize, color);
+        }
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        let clip = self.clip;
+        for y in clip.y..clip.bottom() {
+            self.surface.fill_span(clip.x, y, clip.width, color);
+        }
+    }
+
+    fn hline(&mut self, x: i32, y: i32, len: u32, color: u32) {
+        let span = self.clipped(x, y, len, 1);
+        if !span.is_empty() {
+            self.surface.fill_span(span.x, span.y, span.width, color);
+        }
+    }
+
+    /// A line from `x0`, `y0` to `x1`, `y1`, both ends included.
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        if y0 == y1 {
+            let (start, end) = (x0.min(x1), x0.max(x1));
+            self.hline(start, y0, (end - start) as u32 + 1, color);
+            return;
+        }
+
+        // Bresenham, stepping one pixel at a time along both axes
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (step_x, step_y) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+        let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+
+        loop {
+            self.pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Outline of a `width` by `height` rectangle.
+    pub fn rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        let (right, bottom) = (x + width as i32 - 1, y + height as i32 - 1);
+        self.hline(x, y, width, color);
+        self.hline(x, bottom, width, color);
+        for y in y + 1..bottom {
+            self.pixel(x, y, color);
+            self.pixel(right, y, color);
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: u32) {
+        let area = self.clipped(x, y, width, height);
+        for y in area.y..area.bottom() {
+            self.surface.fill_span(area.x, y, area.width, color);
+        }
+    }
+
+    /// Outline of a circle around `cx`, `cy`.
+    pub fn circle(&mut self, cx: i32, cy: i32, radius: u32, color: u32) {
+        self.midpoint_circle(radius, |canvas, x, y| {
+            for (px, py) in [(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+                canvas.pixel(cx + px, cy + py, color);
+            }
+        });
+    }
+
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: u32, color: u32) {
+        self.midpoint_circle(radius, |canvas, x, y| {
+            for (half, row) in [(x, y), (x, -y), (y, x), (y, -x)] {
+                canvas.hline(cx - half, cy + row, 2 * half as u32 + 1, color);
+            }
+        });
+    }
+
+    /// Walk one octant of a circle, `plot` mirrors each point into the rest.
+    fn midpoint_circle(&mut self, radius: u32, mut plot: impl FnMut(&mut Self, i32, i32)) {
+        let (mut x, mut y) = (radius as i32, 0);
+        let mut error = 1 - x;
+
+        while x >= y {
+            plot(self, x, y);
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Draw `bitmap` with its top left corner at `x`, `y`, blending by its
+    /// alpha channel.
+    pub fn blit(&mut self, bitmap: &Bitmap, x: i32, y: i32) {
+        let area = self.clipped(x, y, bitmap.width as u32, bitmap.height as u32);
+
+        for dst_y in area.y..area.bottom() {
+            let src_y = (dst_y as i64 - y as i64) as usize;
+            for dst_x in area.x..area.right() {
+                let src_x = (dst_x as i64 - x as i64) as usize;
+                let src = bitmap.pixels[src_y * bitmap.width + src_x];
+
+                match src >> 24 {
+                    0 => {}
+                    255 => self.surface.put_pixel(dst_x, dst_y, src & 0xffffff),
+                    _ => {
+                        let dst = self.surface.get_pixel(dst_x, dst_y);
+                        self.surface.put_pixel(dst_x, dst_y, blend(src, dst));
+                    }
+                }
+            }
+        }
+    }
+}
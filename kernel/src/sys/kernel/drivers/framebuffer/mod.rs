@@ -1,7 +1,8 @@
 pub mod textwriter;
 pub mod render;
-
-mod font;
-
-
-//
\ No newline at end of file
+pub mod pixel;
+pub mod graphics;
+pub mod damage;
+pub mod ansi;
+pub mod psf;
+pub mod font;
@@ -0,0 +1,126 @@
+//! Pixel formats
+//!
+//! Colours are passed around as `0xRRGGBB` everywhere in the kernel and only
+//! turned into whatever layout the framebuffer uses right before they are
+//! written.
+
+use limine::framebuffer::Framebuffer;
+
+/// Where one colour channel lives inside a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channel {
+    /// Width in bits.
+    pub size: u8,
+    /// Position of the lowest bit.
+    pub shift: u8,
+}
+
+impl Channel {
+    pub const fn new(size: u8, shift: u8) -> Self {
+        Self { size, shift }
+    }
+
+    fn mask(&self) -> u32 {
+        ((1u64 << self.size) - 1) as u32
+    }
+
+    /// Place an 8 bit channel value.
+    fn encode(&self, value: u32) -> u32 {
+        let value = match self.size {
+            0 => return 0,
+            1..=8 => value >> (8 - self.size),
+            _ => value << (self.size - 8),
+        };
+        (value & self.mask()) << self.shift
+    }
+
+    /// Pull the channel out of `pixel`, scaled back to 8 bits.
+    fn decode(&self, pixel: u32) -> u32 {
+        let value = (pixel >> self.shift) & self.mask();
+        match self.size {
+            0 => 0,
+            // scale so full intensity is 0xff and zero stays zero
+            1..=7 => value * 0xff / self.mask(),
+            8 => value,
+            _ => value >> (self.size - 8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormat {
+    pub bytes_per_pixel: usize,
+    pub red: Channel,
+    pub green: Channel,
+    pub blue: Channel,
+}
+
+impl PixelFormat {
+    /// 32 bit `0x00RRGGBB`, what almost every firmware hands out.
+    pub const XRGB8888: Self = Self::new(4, Channel::new(8, 16), Channel::new(8, 8), Channel::new(8, 0));
+    /// 24 bit, blue in the first byte.
+    pub const RGB888: Self = Self::new(3, Channel::new(8, 16), Channel::new(8, 8), Channel::new(8, 0));
+    pub const RGB565: Self = Self::new(2, Channel::new(5, 11), Channel::new(6, 5), Channel::new(5, 0));
+
+    pub const fn new(bytes_per_pixel: usize, red: Channel, green: Channel, blue: Channel) -> Self {
+        Self {
+            bytes_per_pixel,
+            red,
+            green,
+            blue,
+        }
+    }
+
+    pub fn from_framebuffer(framebuffer: &Framebuffer) -> Self {
+        Self::new(
+            framebuffer.bpp().div_ceil(8) as usize,
+            Channel::new(framebuffer.red_mask_size(), framebuffer.red_mask_shift()),
+            Channel::new(framebuffer.green_mask_size(), framebuffer.green_mask_shift()),
+            Channel::new(framebuffer.blue_mask_size(), framebuffer.blue_mask_shift()),
+        )
+    }
+
+    /// Turn `0xRRGGBB` into a pixel value in this format.
+    pub fn encode(&self, rgb: u32) -> u32 {
+        self.red.encode((rgb >> 16) & 0xff) | self.green.encode((rgb >> 8) & 0xff) | self.blue.encode(rgb & 0xff)
+    }
+
+    /// Turn a pixel value in this format back into `0xRRGGBB`.
+    pub fn decode(&self, pixel: u32) -> u32 {
+        self.red.decode(pixel) << 16 | self.green.decode(pixel) << 8 | self.blue.decode(pixel)
+    }
+
+    /// Store an encoded pixel, only the low `bytes_per_pixel` bytes are written.
+    ///
+    /// # Safety
+    /// `dst` must be valid for `bytes_per_pixel` bytes of writes.
+    pub unsafe fn write(&self, dst: *mut u8, pixel: u32) {
+        match self.bytes_per_pixel {
+            4 => (dst as *mut u32).write_unaligned(pixel),
+            2 => (dst as *mut u16).write_unaligned(pixel as u16),
+            bytes => {
+                for (index, byte) in pixel.to_le_bytes().into_iter().take(bytes).enumerate() {
+                    dst.add(index).write(byte);
+                }
+            }
+        }
+    }
+
+    /// Load an encoded pixel.
+    ///
+    /// # Safety
+    /// `src` must be valid for `bytes_per_pixel` bytes of reads.
+    pub unsafe fn read(&self, src: *const u8) -> u32 {
+        match self.bytes_per_pixel {
+            4 => (src as *const u32).read_unaligned(),
+            2 => (src as *const u16).read_unaligned() as u32,
+            bytes => {
+                let mut pixel = [0; 4];
+                for (index, byte) in pixel.iter_mut().take(bytes).enumerate() {
+                    *byte = src.add(index).read();
+                }
+                u32::from_le_bytes(pixel)
+            }
+        }
+    }
+}
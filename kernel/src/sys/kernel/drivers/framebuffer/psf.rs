@@ -0,0 +1,280 @@
+//! PC Screen Font parser
+//!
+//! Reads version 1 and 2 PSF files, the bitmap fonts the Linux console uses.
+//! Each glyph row is padded to a whole number of bytes, most significant bit
+//! first. If the file has a Unicode table it decides which glyph a character
+//! gets, otherwise the glyph index is the code point.
+
+use alloc::collections::BTreeMap;
+
+use spin::Once;
+
+use crate::sys::kernel::memory::heap;
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_LENGTH: usize = 4;
+const PSF1_MODE_512: u8 = 0x01;
+const PSF1_MODE_HAS_TABLE: u8 = 0x02;
+const PSF1_MODE_HAS_SEQUENCES: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xffff;
+const PSF1_START_SEQUENCE: u16 = 0xfffe;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+const PSF2_HEADER_LENGTH: usize = 32;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_START_SEQUENCE: u8 = 0xfe;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsfError {
+    /// Neither the PSF1 nor the PSF2 magic.
+    UnknownFormat,
+    InvalidHeader,
+    /// The file ends before the glyphs do.
+    Truncated,
+}
+
+/// The bitmap of one character.
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph<'a> {
+    bytes: &'a [u8],
+    bytes_per_row: usize,
+}
+
+impl Glyph<'_> {
+    /// Whether the pixel at `x`, `y` is part of the character.
+    pub fn is_set(&self, x: u32, y: u32) -> bool {
+        let byte = self.bytes[y as usize * self.bytes_per_row + x as usize / 8];
+        byte & (0x80 >> (x % 8)) != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Psf1,
+    Psf2,
+}
+
+pub struct Font<'a> {
+    version: Version,
+    width: u32,
+    height: u32,
+    bytes_per_glyph: usize,
+    glyph_count: usize,
+    glyphs: &'a [u8],
+    /// The Unicode table as stored in the file, empty if there is none.
+    table: &'a [u8],
+    /// `table` as a map, built on first use once the heap is up.
+    unicode: Once<BTreeMap<char, usize>>,
+    /// Drawn for characters the font has no glyph for.
+    replacement: usize,
+}
+
+impl<'a> Font<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, PsfError> {
+        let mut font = if bytes.starts_with(&PSF2_MAGIC) {
+            Self::parse_psf2(bytes)?
+        } else if bytes.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(bytes)?
+        } else {
+            return Err(PsfError::UnknownFormat);
+        };
+
+        font.replacement = ['\u{fffd}', '?']
+            .into_iter()
+            .find_map(|c| font.glyph_index(c))
+            .unwrap_or(0);
+
+        Ok(font)
+    }
+
+    fn parse_psf1(bytes: &'a [u8]) -> Result<Self, PsfError> {
+        if bytes.len() < PSF1_HEADER_LENGTH {
+            return Err(PsfError::Truncated);
+        }
+
+        let (mode, height) = (bytes[2], bytes[3] as usize);
+        if height == 0 {
+            return Err(PsfError::InvalidHeader);
+        }
+
+        let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let end = PSF1_HEADER_LENGTH + glyph_count * height;
+        let glyphs = bytes.get(PSF1_HEADER_LENGTH..end).ok_or(PsfError::Truncated)?;
+
+        let table = match mode & (PSF1_MODE_HAS_TABLE | PSF1_MODE_HAS_SEQUENCES) {
+            0 => &[],
+            _ => &bytes[end..],
+        };
+
+        Ok(Self {
+            version: Version::Psf1,
+            width: 8,
+            height: height as u32,
+            bytes_per_glyph: height,
+            glyph_count,
+            glyphs,
+            table,
+            unicode: Once::new(),
+            replacement: 0,
+        })
+    }
+
+    fn parse_psf2(bytes: &'a [u8]) -> Result<Self, PsfError> {
+        let field = |index: usize| {
+            let offset = 4 + index * 4;
+            bytes
+                .get(offset..offset + 4)
+                .map(|field| u32::from_le_bytes(field.try_into().unwrap()) as usize)
+                .ok_or(PsfError::Truncated)
+        };
+
+        let header_length = field(1)?;
+        let flags = field(2)? as u32;
+        let glyph_count = field(3)?;
+        let bytes_per_glyph = field(4)?;
+        let (height, width) = (field(5)?, field(6)?);
+
+        if header_length < PSF2_HEADER_LENGTH
+            || width == 0
+            || height == 0
+            || bytes_per_glyph < width.div_ceil(8) * height
+        {
+            return Err(PsfError::InvalidHeader);
+        }
+
+        let end = glyph_count
+            .checked_mul(bytes_per_glyph)
+            .and_then(|length| length.checked_add(header_length))
+            .ok_or(PsfError::InvalidHeader)?;
+        let glyphs = bytes.get(header_length..end).ok_or(PsfError::Truncated)?;
+
+        let table = match flags & PSF2_HAS_UNICODE_TABLE {
+            0 => &[],
+            _ => &bytes[end..],
+        };
+
+        Ok(Self {
+            version: Version::Psf2,
+            width: width as u32,
+            height: height as u32,
+            bytes_per_glyph,
+            glyph_count,
+            glyphs,
+            table,
+            unicode: Once::new(),
+            replacement: 0,
+        })
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    pub fn has_unicode_table(&self) -> bool {
+        !self.table.is_empty()
+    }
+
+    /// Index of the glyph for `c`, if the font has one.
+    pub fn glyph_index(&self, c: char) -> Option<usize> {
+        if self.table.is_empty() {
+            return Some(c as usize).filter(|&index| index < self.glyph_count);
+        }
+
+        match self.unicode_map() {
+            Some(map) => map.get(&c).copied(),
+            // no heap yet, search the table itself
+            None => self.mappings().find(|&(mapped, _)| mapped == c).map(|(_, glyph)| glyph),
+        }
+    }
+
+    /// The glyph for `c`, or the replacement glyph if there is none.
+    pub fn glyph(&self, c: char) -> Glyph<'a> {
+        let index = self.glyph_index(c).unwrap_or(self.replacement);
+        let start = index * self.bytes_per_glyph;
+
+        Glyph {
+            bytes: &self.glyphs[start..start + self.bytes_per_glyph],
+            bytes_per_row: self.width.div_ceil(8) as usize,
+        }
+    }
+
+    fn unicode_map(&self) -> Option<&BTreeMap<char, usize>> {
+        if let Some(map) = self.unicode.get() {
+            return Some(map);
+        }
+        if !heap::is_ready() {
+            return None;
+        }
+
+        // the first glyph listed for a character wins
+        Some(self.unicode.call_once(|| {
+            let mut map = BTreeMap::new();
+            for (c, glyph) in self.mappings() {
+                map.entry(c).or_insert(glyph);
+            }
+            map
+        }))
+    }
+
+    /// Every character and its glyph index listed in the Unicode table.
+    /// Multi character sequences are skipped, they only matter for
+    /// combining characters.
+    fn mappings(&self) -> impl Iterator<Item = (char, usize)> + 'a {
+        let glyph_count = self.glyph_count;
+
+        // UCS-2, one line per glyph ending in 0xffff
+        let psf1 = (self.version == Version::Psf1).then(|| {
+            self.table
+                .as_chunks::<2>()
+                .0
+                .iter()
+                .map(|&entry| u16::from_le_bytes(entry))
+                .scan((0, false), |(glyph, in_sequence), entry| {
+                    let mapping = match entry {
+                        PSF1_SEPARATOR => {
+                            (*glyph, *in_sequence) = (*glyph + 1, false);
+                            None
+                        }
+                        PSF1_START_SEQUENCE => {
+                            *in_sequence = true;
+                            None
+                        }
+                        _ if *in_sequence => None,
+                        _ => char::from_u32(entry as u32).map(|c| (c, *glyph)),
+                    };
+                    Some(mapping)
+                })
+                .flatten()
+        });
+
+        // UTF-8, one line per glyph ending in 0xff
+        let psf2 = (self.version == Version::Psf2).then(|| {
+            self.table
+                .split(|&byte| byte == PSF2_SEPARATOR)
+                .enumerate()
+                .flat_map(|(glyph, line)| {
+                    let singles = line.split(|&byte| byte == PSF2_START_SEQUENCE).next().unwrap_or(&[]);
+                    let chars = core::str::from_utf8(singles).unwrap_or("");
+                    chars.chars().map(move |c| (c, glyph))
+                })
+        });
+
+        psf1.into_iter()
+            .flatten()
+            .chain(psf2.into_iter().flatten())
+            .filter(move |&(_, glyph)| glyph < glyph_count)
+    }
+}
@@ -1,24 +1,141 @@
-use core::panic;
+use alloc::vec::Vec;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
 use lazy_static::lazy_static;
 use limine::framebuffer::Framebuffer;
 use spin::Mutex;
 use limine::request::FramebufferRequest;
 
-static FRAMEBUFFER_REQUEST: FramebufferRequest = FramebufferRequest::new();
+use crate::sys::kernel::cmdline;
+use crate::sys::kernel::cpu::x86_64::interrupts;
+
+use super::damage::{Damage, Rect};
+use super::pixel::PixelFormat;
+
+// revision 1 makes Limine list the video modes of each display
+static FRAMEBUFFER_REQUEST: FramebufferRequest = FramebufferRequest::with_revision(1);
+
+/// Displays beyond this many are ignored.
+pub const MAX_FRAMEBUFFERS: usize = 4;
+
+/// The display the console goes on when there are several, picked by its
+/// current mode: `resolution=1280x800` on the kernel command line. Without
+/// one the console stays on the first display Limine lists.
+pub fn preferred_resolution() -> Option<(u32, u32)> {
+    cmdline::option("resolution").and_then(parse_resolution)
+}
+
+/// `WIDTHxHEIGHT`, as in `1280x800`.
+pub fn parse_resolution(text: &str) -> Option<(u32, u32)> {
+    let (width, height) = text.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+type Slot = Mutex<Option<FramebufferWriter<'static>>>;
 
 lazy_static! {
-    pub static ref FRAMEBUFFER_WRITER: Mutex<Option<FramebufferWriter<'static>>> = Mutex::new({
-        if let Some(framebuffer_response) = FRAMEBUFFER_REQUEST.get_response() {
-            let framebuffer = framebuffer_response.framebuffers().next().unwrap();
-            Some(FramebufferWriter::new(framebuffer))
-        } else {
-            panic!("Framebuffer request failed");
+    /// A writer for every display Limine set up, the primary one first.
+    /// All `None` when booting headless.
+    pub static ref FRAMEBUFFERS: [Slot; MAX_FRAMEBUFFERS] = {
+        let mut writers: [Option<FramebufferWriter<'static>>; MAX_FRAMEBUFFERS] = Default::default();
+
+        if let Some(response) = FRAMEBUFFER_REQUEST.get_response() {
+            for (slot, framebuffer) in writers.iter_mut().zip(response.framebuffers()) {
+                *slot = Some(FramebufferWriter::new(framebuffer));
+            }
         }
-    });
+
+        let preferred = preferred_resolution().and_then(|resolution| {
+            writers.iter().position(|writer| {
+                writer.as_ref().is_some_and(|writer| (writer.width(), writer.height()) == resolution)
+            })
+        });
+        if let Some(preferred) = preferred {
+            writers.swap(0, preferred);
+        }
+
+        writers.map(Mutex::new)
+    };
+
+    /// The primary display, the one the console draws on.
+    pub static ref FRAMEBUFFER_WRITER: &'static Slot = &FRAMEBUFFERS[0];
+}
+
+/// Copy the primary display to all the others on every flush.
+static MIRROR: AtomicBool = AtomicBool::new(true);
+
+/// One video mode a display supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mode {
+    pub width: u32,
+    pub height: u32,
+    pub pitch: u32,
+    /// Bits per pixel.
+    pub bpp: u16,
+}
+
+/// A display as Limine reported it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Display {
+    /// Position in [`FRAMEBUFFERS`].
+    pub index: usize,
+    /// The mode it is in now.
+    pub mode: Mode,
+    pub format: PixelFormat,
+    /// Every mode it supports, empty if the bootloader didn't say.
+    pub modes: Vec<Mode>,
 }
 
+/// Every display there is, primary first. Needs the heap.
+pub fn displays() -> Vec<Display> {
+    FRAMEBUFFERS
+        .iter()
+        .enumerate()
+        .filter_map(|(index, slot)| {
+            interrupts::without(|| slot.lock().as_ref().map(|writer| writer.display(index)))
+        })
+        .collect()
+}
+
+/// Whether there is no display at all and output only goes to serial.
+pub fn is_headless() -> bool {
+    interrupts::without(|| FRAMEBUFFER_WRITER.lock().is_none())
+}
+
+/// Mirror the primary display onto the others, or leave them to be drawn
+/// on separately.
+pub fn set_mirroring(enabled: bool) {
+    MIRROR.store(enabled, Ordering::Relaxed);
+    if enabled {
+        interrupts::without(|| {
+            if let Some(writer) = FRAMEBUFFER_WRITER.lock().as_mut() {
+                writer.mark(writer.bounds());
+            }
+        });
+        flush();
+    }
+}
+
+pub fn is_mirroring() -> bool {
+    MIRROR.load(Ordering::Relaxed)
+}
+
+/// Draws into the framebuffer. Colours are always `0xRRGGBB` and get
+/// converted to the framebuffer's pixel format.
+///
+/// With a back buffer enabled everything is drawn into RAM and only reaches
+/// the screen on [`flush`](Self::flush), which copies just the parts that
+/// changed. Reading video memory is very slow, so this also makes scrolling
+/// much cheaper.
 pub struct FramebufferWriter<'a> {
     framebuffer: Framebuffer<'a>,
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    pitch: usize,
+    /// Same layout as video memory.
+    back_buffer: Option<Vec<u8>>,
+    damage: Damage,
 }
 
 unsafe impl<'a> Send for FramebufferWriter<'a> {}
@@ -27,37 +144,286 @@ unsafe impl<'a> Sync for FramebufferWriter<'a> {}
 impl<'a> FramebufferWriter<'a> {
     pub fn new(framebuffer: Framebuffer<'a>) -> Self {
         Self {
+            format: PixelFormat::from_framebuffer(&framebuffer),
+            width: framebuffer.width() as usize,
+            height: framebuffer.height() as usize,
+            pitch: framebuffer.pitch() as usize,
             framebuffer,
+            back_buffer: None,
+            damage: Damage::new(),
         }
     }
 
-    pub fn write_pixel(&self, x: usize, y: usize, color: u32) {
-        let pitch = self.framebuffer.pitch() as usize;
-        let bpp = (self.framebuffer.bpp() / 8) as usize;
-        let pixel_offset = y * pitch + x * bpp;
+    /// Where drawing goes, the back buffer if there is one.
+    fn target(&mut self) -> *mut u8 {
+        match self.back_buffer.as_mut() {
+            Some(buffer) => buffer.as_mut_ptr(),
+            None => self.framebuffer.addr(),
+        }
+    }
 
-        unsafe {
-            *(self.framebuffer.addr().add(pixel_offset) as *mut u32) = color;
+    fn mark(&mut self, rect: Rect) {
+        // without a back buffer everything is on screen already
+        if self.back_buffer.is_some() {
+            self.damage.add(rect);
+        }
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+
+    /// Write one pixel, anything off screen is ignored.
+    pub fn write_pixel(&mut self, x: usize, y: usize, color: u32) {
+        if x >= self.width || y >= self.height {
+            return;
         }
+
+        let pixel = self.format.encode(color);
+        let offset = y * self.pitch + x * self.format.bytes_per_pixel;
+        let target = self.target();
+        unsafe { self.format.write(target.add(offset), pixel) };
+
+        self.mark(Rect::new(x, y, 1, 1));
+    }
+
+    /// Colour of the pixel at `x`, `y` as `0xRRGGBB`.
+    pub fn read_pixel(&mut self, x: usize, y: usize) -> Option<u32> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let offset = y * self.pitch + x * self.format.bytes_per_pixel;
+        let target = self.target();
+        Some(self.format.decode(unsafe { self.format.read(target.add(offset)) }))
+    }
+
+    /// Draw a bitmap the size of `rect` into it. Pixels `is_set` says are on
+    /// get `fg`, the rest `bg`.
+    pub fn draw_bitmap(&mut self, rect: Rect, is_set: impl Fn(usize, usize) -> bool, fg: u32, bg: u32) {
+        let area = rect.intersection(&self.bounds());
+        let (fg, bg) = (self.format.encode(fg), self.format.encode(bg));
+        let (bpp, pitch, target) = (self.format.bytes_per_pixel, self.pitch, self.target());
+
+        for row in area.y..area.bottom() {
+            for col in area.x..area.right() {
+                let pixel = if is_set(col - rect.x, row - rect.y) { fg } else { bg };
+                unsafe { self.format.write(target.add(row * pitch + col * bpp), pixel) };
+            }
+        }
+
+        self.mark(area);
     }
 
     pub fn width(&self) -> u32 {
-        self.framebuffer.width() as u32
+        self.width as u32
     }
 
     pub fn height(&self) -> u32 {
-        self.framebuffer.height() as u32
+        self.height as u32
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    fn display(&self, index: usize) -> Display {
+        let modes = self.framebuffer.modes().unwrap_or_default().iter().map(|mode| Mode {
+            width: mode.width as u32,
+            height: mode.height as u32,
+            pitch: mode.pitch as u32,
+            bpp: mode.bpp,
+        });
+
+        Display {
+            index,
+            mode: Mode {
+                width: self.width as u32,
+                height: self.height as u32,
+                pitch: self.pitch as u32,
+                bpp: self.framebuffer.bpp(),
+            },
+            format: self.format,
+            modes: modes.collect(),
+        }
+    }
+
+    /// Fill a rectangle with `color`, clipped to the screen.
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: u32) {
+        let area = Rect::new(x, y, width, height).intersection(&self.bounds());
+        if area.is_empty() {
+            return;
+        }
+
+        let pixel = self.format.encode(color);
+        let bpp = self.format.bytes_per_pixel;
+        let target = self.target();
+
+        // build one row, then copy it down
+        let first = unsafe { target.add(area.y * self.pitch + area.x * bpp) };
+        for col in 0..area.width {
+            unsafe { self.format.write(first.add(col * bpp), pixel) };
+        }
+        for row in 1..area.height {
+            unsafe { ptr::copy_nonoverlapping(first, first.add(row * self.pitch), area.width * bpp) };
+        }
+
+        self.mark(area);
+    }
+
+    /// Move everything up by `rows` pixel rows and fill the rows that
+    /// come free at the bottom with `color`.
+    pub fn scroll_up(&mut self, rows: usize, color: u32) {
+        let rows = rows.min(self.height);
+
+        unsafe {
+            let base = self.target();
+            ptr::copy(base.add(rows * self.pitch), base, (self.height - rows) * self.pitch);
+        }
+
+        self.mark(self.bounds());
+        self.fill_rect(0, self.height - rows, self.width, rows, color);
     }
 
-    pub fn clear(&self) {
-        let width = self.framebuffer.width() as usize;
-        let height = self.framebuffer.height() as usize;
+    pub fn clear(&mut self) {
+        if self.format.encode(0x000000) == 0 {
+            // black is all zero bytes in every sane format
+            unsafe { ptr::write_bytes(self.target(), 0, self.height * self.pitch) };
+            self.mark(self.bounds());
+        } else {
+            self.fill_rect(0, 0, self.width, self.height, 0x000000);
+        }
+    }
+
+    pub fn has_back_buffer(&self) -> bool {
+        self.back_buffer.is_some()
+    }
 
-        for y in 0..height {
-            for x in 0..width {
-                self.write_pixel(x, y, 0x000000);
+    /// Start drawing into RAM, seeded with what is on screen now. Needs the
+    /// heap.
+    pub fn enable_back_buffer(&mut self) {
+        if self.back_buffer.is_none() {
+            let len = self.height * self.pitch;
+            let mut buffer = Vec::with_capacity(len);
+            unsafe {
+                ptr::copy_nonoverlapping(self.framebuffer.addr(), buffer.as_mut_ptr(), len);
+                buffer.set_len(len);
             }
+            self.back_buffer = Some(buffer);
         }
     }
+
+    /// Go back to drawing straight into video memory.
+    pub fn disable_back_buffer(&mut self) {
+        self.flush();
+        self.back_buffer = None;
+    }
+
+    /// Copy the damaged parts of the back buffer to the screen.
+    pub fn flush(&mut self) {
+        if let Some(buffer) = self.back_buffer.as_ref() {
+            let front = self.framebuffer.addr();
+            let bpp = self.format.bytes_per_pixel;
+
+            for rect in self.damage.rects() {
+                for row in rect.y..rect.bottom() {
+                    let offset = row * self.pitch + rect.x * bpp;
+                    unsafe {
+                        ptr::copy_nonoverlapping(buffer.as_ptr().add(offset), front.add(offset), rect.width * bpp);
+                    }
+                }
+            }
+        }
+
+        self.damage.clear();
+    }
+
+    /// Copy `rect` of what this writer shows onto `other`, converting the
+    /// pixel format if the two differ. Anything outside either is skipped.
+    pub fn copy_to(&self, other: &mut FramebufferWriter, rect: Rect) {
+        let area = rect.intersection(&self.bounds()).intersection(&other.bounds());
+        if area.is_empty() {
+            return;
+        }
+
+        let source = match self.back_buffer.as_ref() {
+            Some(buffer) => buffer.as_ptr(),
+            None => self.framebuffer.addr() as *const u8,
+        };
+        let (from, to) = (self.format, other.format);
+        let target = other.target();
+
+        for row in area.y..area.bottom() {
+            let src = unsafe { source.add(row * self.pitch + area.x * from.bytes_per_pixel) };
+            let dst = unsafe { target.add(row * other.pitch + area.x * to.bytes_per_pixel) };
+
+            if from == to {
+                unsafe { ptr::copy_nonoverlapping(src, dst, area.width * from.bytes_per_pixel) };
+                continue;
+            }
+            for col in 0..area.width {
+                unsafe {
+                    let color = from.decode(from.read(src.add(col * from.bytes_per_pixel)));
+                    to.write(dst.add(col * to.bytes_per_pixel), to.encode(color));
+                }
+            }
+        }
+
+        other.mark(area);
+    }
 }
 
+/// Draw into RAM from now on, see [`FramebufferWriter::enable_back_buffer`].
+pub fn enable_back_buffer() {
+    interrupts::without(|| {
+        if let Some(writer) = FRAMEBUFFER_WRITER.lock().as_mut() {
+            writer.enable_back_buffer();
+            // the other displays haven't seen anything yet
+            writer.mark(writer.bounds());
+        }
+    });
+    flush();
+}
+
+/// Push pending changes to the screen, and to the other displays while
+/// mirroring.
+pub fn flush() {
+    interrupts::without(|| {
+        let mut primary = FRAMEBUFFER_WRITER.lock();
+        let Some(writer) = primary.as_mut() else {
+            return;
+        };
+
+        if is_mirroring() {
+            for slot in &FRAMEBUFFERS[1..] {
+                if let Some(other) = slot.lock().as_mut() {
+                    for &rect in writer.damage.rects() {
+                        writer.copy_to(other, rect);
+                    }
+                    other.flush();
+                }
+            }
+        }
+
+        writer.flush();
+    });
+}
+
+/// Release every display lock whoever holds it, for the panic path.
+///
+/// # Safety
+/// Nothing else may be drawing, i.e. the kernel is going down.
+pub unsafe fn force_unlock() {
+    for slot in FRAMEBUFFERS.iter() {
+        if slot.is_locked() {
+            slot.force_unlock();
+        }
+    }
+}
+
+/// Run `draw` on display `index`, e.g. to show something else there while
+/// not mirroring. `None` if there is no such display.
+pub fn with_display<R>(index: usize, draw: impl FnOnce(&mut FramebufferWriter<'static>) -> R) -> Option<R> {
+    let slot = FRAMEBUFFERS.get(index)?;
+    interrupts::without(|| slot.lock().as_mut().map(draw))
+}
@@ -1,86 +1,424 @@
-
+use alloc::{collections::VecDeque, vec, vec::Vec};
 use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use lazy_static::lazy_static;
 use spin::Mutex;
 
 use crate::sys::kernel::cpu::x86_64::interrupts;
+use crate::sys::kernel::memory::heap;
+use crate::sys::std::utf8::Utf8Decoder;
+
+use super::ansi::{self, Action, Csi, Parser};
+use super::psf::Font;
+use super::damage::Rect;
+use super::{font, render, render::FRAMEBUFFER_WRITER};
+
+/// Lines kept after they scroll off the top, unless changed with
+/// [`set_scrollback_size`].
+pub const DEFAULT_SCROLLBACK: usize = 1000;
 
-use super::{font::FONT, render::FRAMEBUFFER_WRITER};
+const DEFAULT_FG: u32 = 0xFFFFFF;
+const DEFAULT_BG: u32 = 0x000000;
+/// What `printerr!` writes in.
+const ERROR_FG: u32 = 0xFF8080;
 
-static FONT_WIDTH: u32 = 8;
-static FONT_HEIGHT: u32 = 16;
+/// How many virtual consoles there are, one for each of Alt+F1..F6.
+pub const CONSOLE_COUNT: usize = 6;
 
 lazy_static!{
-    static ref TEXT_WRITER: Mutex<TextWriter> = Mutex::new(TextWriter::new());
+    /// One writer per [`Console`]. All `None` when there is no framebuffer,
+    /// output goes to serial then.
+    static ref CONSOLES: [Mutex<Option<TextWriter>>; CONSOLE_COUNT] = {
+        let consoles: [_; CONSOLE_COUNT] = core::array::from_fn(|_| Mutex::new(TextWriter::new()));
+        if let Some(writer) = consoles[ACTIVE.load(Ordering::Relaxed)].lock().as_mut() {
+            writer.active = true;
+        }
+        consoles
+    };
+}
+
+/// Index of the console on screen.
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// A virtual console. Each keeps its own screen, scrollback, cursor and
+/// colours, only the active one is drawn to the framebuffer.
+///
+/// It is also a device to write to, `write!(Console::Tty3, ...)` works
+/// whether or not the console is on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Console {
+    Tty1 = 0,
+    Tty2 = 1,
+    Tty3 = 2,
+    Tty4 = 3,
+    Tty5 = 4,
+    Tty6 = 5,
+}
+
+impl Console {
+    pub const ALL: [Console; CONSOLE_COUNT] = [
+        Console::Tty1,
+        Console::Tty2,
+        Console::Tty3,
+        Console::Tty4,
+        Console::Tty5,
+        Console::Tty6,
+    ];
+
+    /// Where `print!` goes, the one on screen at boot.
+    pub const SHELL: Console = Console::Tty1;
+    /// Where the kernel log goes, so it doesn't interleave with the shell.
+    pub const LOG: Console = Console::Tty2;
+
+    pub fn from_index(index: usize) -> Option<Console> {
+        Self::ALL.get(index).copied()
+    }
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_to(*self, format_args!("{}", s));
+        Ok(())
+    }
 }
 
+/// One character position on the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub fg: u32,
+    pub bg: u32,
+}
+
+impl Cell {
+    const fn blank(bg: u32) -> Self {
+        Self { c: ' ', fg: DEFAULT_FG, bg }
+    }
+}
 
 pub struct TextWriter {
     // these are measured in chars NOT pixels
     screen_width: u32,
     screen_height: u32,
 
-    text_line: u32, // 16 pixels tall
-    text_col: u32,  // 8 pixels wide
+    text_line: u32, // font height pixels tall
+    text_col: u32,  // font width pixels wide
+
+    font: &'static Font<'static>,
 
     fg_color: u32,
-    bg_color: u32
+    bg_color: u32,
+    // what SGR 0/39/49 go back to, the colours the current print started with
+    base_fg: u32,
+    base_bg: u32,
+    bold: bool,
+    reverse: bool,
+
+    /// Whether this console is the one on screen, the others only keep
+    /// their cells up to date.
+    active: bool,
+
+    /// What is on screen, row by row. Empty until the heap is up.
+    cells: Vec<Cell>,
+    /// Lines that scrolled off the top, oldest first.
+    scrollback: VecDeque<Vec<Cell>>,
+    scrollback_size: usize,
+    /// How many lines the view is scrolled back into history, 0 is live.
+    view_offset: usize,
+
+    parser: Parser,
+    utf8: Utf8Decoder,
+    /// First and last line that scroll, inclusive.
+    scroll_top: u32,
+    scroll_bottom: u32,
+    /// Cursor and colours stored by `ESC 7` / `CSI s`.
+    saved: (u32, u32, u32, u32),
 }
 
 impl TextWriter {
 
-    pub fn new() -> Self {
-        if let Some(writer) = FRAMEBUFFER_WRITER.lock().as_mut() {
+    /// A console on the primary display, `None` when booting headless.
+    pub fn new() -> Option<Self> {
+        let font = font::font();
+
+        FRAMEBUFFER_WRITER.lock().as_ref().map(|writer| {
+            let screen_width = writer.width() / font.width();
+            let screen_height = writer.height() / font.height();
+
             Self {
-                screen_width: writer.width() as u32 / 8,
-                screen_height: writer.height() as u32 / 16,
+                screen_width,
+                screen_height,
                 text_line: 0,
                 text_col: 0,
-                fg_color: 0xFFFFFF,
-                bg_color: 0x000000
+                font,
+                fg_color: DEFAULT_FG,
+                bg_color: DEFAULT_BG,
+                base_fg: DEFAULT_FG,
+                base_bg: DEFAULT_BG,
+                bold: false,
+                reverse: false,
+                active: false,
+                cells: Vec::new(),
+                scrollback: VecDeque::new(),
+                scrollback_size: DEFAULT_SCROLLBACK,
+                view_offset: 0,
+                parser: Parser::new(),
+                utf8: Utf8Decoder::new(),
+                scroll_top: 0,
+                scroll_bottom: screen_height - 1,
+                saved: (0, 0, DEFAULT_FG, DEFAULT_BG),
             }
-        } else {
-            panic!("Framebuffer writer not initialized");
+        })
+    }
+
+    pub fn write_char(&mut self, c: u8) {
+        // the first messages are printed before there is a heap to keep them in
+        if self.cells.is_empty() && heap::is_ready() {
+            self.cells = vec![Cell::blank(DEFAULT_BG); self.cell_count()];
+        }
+
+        // new output always shows up live
+        if self.view_offset != 0 {
+            self.view_offset = 0;
+            self.redraw();
+        }
+
+        match self.parser.advance(c) {
+            Some(Action::Print(byte)) => self.put_byte(byte),
+            Some(Action::Execute(c)) => self.execute(c),
+            Some(Action::Escape(c)) => self.escape(c),
+            Some(Action::Csi(csi)) => self.csi(&csi),
+            None => {}
         }
     }
 
-    pub fn write_char(&mut self, mut c: u8) {
-        if c == b'\n' {
+    fn put_byte(&mut self, byte: u8) {
+        if let Some(c) = self.utf8.push(byte) {
+            self.put_char(c);
+        }
+    }
+
+    fn put_char(&mut self, c: char) {
+        let cell = match self.reverse {
+            false => Cell { c, fg: self.fg_color, bg: self.bg_color },
+            true => Cell { c, fg: self.bg_color, bg: self.fg_color },
+        };
+        let index = self.index(self.text_col, self.text_line);
+        if let Some(slot) = self.cells.get_mut(index) {
+            *slot = cell;
+        }
+        self.draw_cell(self.text_col, self.text_line, cell);
+
+        // go to next position
+        if self.text_col + 1 >= self.screen_width {
             self.newline();
+        } else {
+            self.text_col += 1;
+        }
+    }
+
+    fn execute(&mut self, c: u8) {
+        match c {
+            b'\n' => self.newline(),
+            b'\r' => self.text_col = 0,
+            b'\t' => self.text_col = ((self.text_col / 8 + 1) * 8).min(self.screen_width - 1),
+            // backspace, rub out the previous character on this line
+            0x08 if self.text_col > 0 => {
+                self.text_col -= 1;
+                self.put_char(' ');
+                self.text_col -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn escape(&mut self, c: u8) {
+        match c {
+            b'7' => self.save_cursor(),
+            b'8' => self.restore_cursor(),
+            // index, move down scrolling if needed
+            b'D' => self.line_feed(),
+            b'E' => self.newline(),
+            // reverse index, move up scrolling if needed
+            b'M' => {
+                if self.text_line == self.scroll_top {
+                    self.scroll_down(1);
+                } else if self.text_line > 0 {
+                    self.text_line -= 1;
+                }
+            }
+            b'c' => self.reset(),
+            _ => {}
+        }
+    }
+
+    fn csi(&mut self, csi: &Csi) {
+        // nothing we handle takes private or intermediate bytes, e.g. `?25h`
+        if csi.private != 0 || csi.intermediate != 0 {
             return;
         }
 
-        if c < 32 || c > 126 {
-            c = '?' as u8;
+        let n = csi.param(0, 1) as u32;
+        let (max_col, max_line) = (self.screen_width - 1, self.screen_height - 1);
+
+        match csi.action {
+            b'A' => self.text_line = self.text_line.saturating_sub(n),
+            b'B' => self.text_line = (self.text_line + n).min(max_line),
+            b'C' => self.text_col = (self.text_col + n).min(max_col),
+            b'D' => self.text_col = self.text_col.saturating_sub(n),
+            b'E' => (self.text_line, self.text_col) = ((self.text_line + n).min(max_line), 0),
+            b'F' => (self.text_line, self.text_col) = (self.text_line.saturating_sub(n), 0),
+            b'G' => self.text_col = (n - 1).min(max_col),
+            b'd' => self.text_line = (n - 1).min(max_line),
+            b'H' | b'f' => {
+                self.text_line = (csi.param(0, 1) as u32 - 1).min(max_line);
+                self.text_col = (csi.param(1, 1) as u32 - 1).min(max_col);
+            }
+            b'J' => self.erase_display(csi.param(0, 0)),
+            b'K' => self.erase_line(csi.param(0, 0)),
+            b'S' => self.scroll_up(n),
+            b'T' => self.scroll_down(n),
+            b'm' => self.select_graphic_rendition(csi.params()),
+            b'r' => {
+                let top = csi.param(0, 1) as u32 - 1;
+                let bottom = (csi.param(1, self.screen_height as u16) as u32 - 1).min(max_line);
+                if top < bottom {
+                    (self.scroll_top, self.scroll_bottom) = (top, bottom);
+                    (self.text_line, self.text_col) = (0, 0);
+                }
+            }
+            b's' => self.save_cursor(),
+            b'u' => self.restore_cursor(),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        // `CSI m` is the same as `CSI 0 m`
+        if params.is_empty() {
+            self.select_graphic_rendition(&[0]);
+            return;
         }
 
-        // get the character data from the font array. -- each byte is a row of pixels
-        let data: &[u8] = &FONT[c as usize * 16..(c as usize + 1) * 16];
+        let mut params = params.iter().copied();
 
-        if let Some(writer) = FRAMEBUFFER_WRITER.lock().as_mut() {
-            for row in 0..16 {
-                let line: u8 = data[row];
-                for col in 0..8 {
-                    let pixel_x: u32 = self.text_col * FONT_WIDTH + col;
-                    let pixel_y: u32 = self.text_line * FONT_HEIGHT + row as u32;
-    
-                    if line & (0x80 >> col) != 0 {
-                        // write the foreground color
-                        writer.write_pixel(pixel_x as usize, pixel_y as usize, self.fg_color);
-                    } else {
-                        // write the background color
-                        writer.write_pixel(pixel_x as usize, pixel_y as usize, self.bg_color);
+        while let Some(param) = params.next() {
+            match param {
+                0 => {
+                    (self.fg_color, self.bg_color) = (self.base_fg, self.base_bg);
+                    (self.bold, self.reverse) = (false, false);
+                }
+                1 => self.bold = true,
+                22 => self.bold = false,
+                7 => self.reverse = true,
+                27 => self.reverse = false,
+                30..=37 => self.fg_color = ansi::COLOURS[(param - 30) as usize + if self.bold { 8 } else { 0 }],
+                39 => self.fg_color = self.base_fg,
+                40..=47 => self.bg_color = ansi::COLOURS[(param - 40) as usize],
+                49 => self.bg_color = self.base_bg,
+                90..=97 => self.fg_color = ansi::COLOURS[(param - 90) as usize + 8],
+                100..=107 => self.bg_color = ansi::COLOURS[(param - 100) as usize + 8],
+                38 | 48 => {
+                    let colour = match params.next() {
+                        Some(5) => params.next().map(|index| ansi::colour_256(index as u8)),
+                        Some(2) => {
+                            let mut channel = || params.next().unwrap_or(0).min(255) as u32;
+                            Some(channel() << 16 | channel() << 8 | channel())
+                        }
+                        _ => None,
+                    };
+
+                    match (param, colour) {
+                        (38, Some(colour)) => self.fg_color = colour,
+                        (48, Some(colour)) => self.bg_color = colour,
+                        _ => {}
                     }
                 }
+                _ => {}
             }
-        }   
+        }
+    }
 
-        // go to next position
-        if self.text_col + 1 >= self.screen_width {
-            self.newline();
-        } else {
-            self.text_col += 1;
+    fn save_cursor(&mut self) {
+        self.saved = (self.text_col, self.text_line, self.fg_color, self.bg_color);
+    }
+
+    fn restore_cursor(&mut self) {
+        (self.text_col, self.text_line, self.fg_color, self.bg_color) = self.saved;
+    }
+
+    /// Back to how the console started, with an empty screen.
+    fn reset(&mut self) {
+        self.parser = Parser::new();
+        (self.scroll_top, self.scroll_bottom) = (0, self.screen_height - 1);
+        self.select_graphic_rendition(&[0]);
+        self.erase_display(2);
+        (self.text_col, self.text_line) = (0, 0);
+        self.save_cursor();
+    }
+
+    fn erase_display(&mut self, mode: u16) {
+        let cursor = self.index(self.text_col, self.text_line);
+        match mode {
+            0 => self.erase(cursor, self.cell_count()),
+            1 => self.erase(0, cursor + 1),
+            2 => self.erase(0, self.cell_count()),
+            3 => {
+                self.scrollback.clear();
+                self.erase(0, self.cell_count());
+            }
+            _ => {}
+        }
+    }
+
+    fn erase_line(&mut self, mode: u16) {
+        let start = self.index(0, self.text_line);
+        let cursor = self.index(self.text_col, self.text_line);
+        let end = start + self.screen_width as usize;
+        match mode {
+            0 => self.erase(cursor, end),
+            1 => self.erase(start, cursor + 1),
+            2 => self.erase(start, end),
+            _ => {}
+        }
+    }
+
+    /// Blank the cells from `start` up to `end` in the current background.
+    fn erase(&mut self, start: usize, end: usize) {
+        let blank = Cell::blank(self.bg_color);
+        for index in start..end {
+            if let Some(slot) = self.cells.get_mut(index) {
+                *slot = blank;
+            }
+            let (col, line) = (index as u32 % self.screen_width, index as u32 / self.screen_width);
+            self.draw_cell(col, line, blank);
+        }
+    }
+
+    fn cell_count(&self) -> usize {
+        (self.screen_width * self.screen_height) as usize
+    }
+
+    fn index(&self, col: u32, line: u32) -> usize {
+        (line * self.screen_width + col) as usize
+    }
+
+    fn draw_cell(&self, col: u32, line: u32, cell: Cell) {
+        if !self.active {
+            return;
+        }
+
+        let glyph = self.font.glyph(cell.c);
+        let (width, height) = (self.font.width() as usize, self.font.height() as usize);
+        let rect = Rect::new(col as usize * width, line as usize * height, width, height);
+
+        if let Some(writer) = FRAMEBUFFER_WRITER.lock().as_mut() {
+            writer.draw_bitmap(rect, |x, y| glyph.is_set(x as u32, y as u32), cell.fg, cell.bg);
         }
     }
 
@@ -90,28 +428,193 @@ impl TextWriter {
 
     pub fn newline(&mut self) {
         self.text_col = 0;
+        self.line_feed();
+    }
 
-        if self.text_line + 1 >= self.screen_height {
-            self.text_line = 0;
-        } else {
+    /// Move down a line, scrolling when at the bottom of the scroll region.
+    fn line_feed(&mut self) {
+        if self.text_line == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.text_line + 1 < self.screen_height {
             self.text_line += 1;
         }
     }
 
+    /// Move the scroll region up by `lines`. Lines leaving the top of the
+    /// whole screen go into the scrollback.
+    fn scroll_up(&mut self, lines: u32) {
+        let lines = lines.min(self.scroll_bottom - self.scroll_top + 1);
+        let width = self.screen_width as usize;
+        let whole_screen = self.scroll_top == 0 && self.scroll_bottom + 1 == self.screen_height;
+
+        let start = self.index(0, self.scroll_top);
+        let end = self.index(0, self.scroll_bottom + 1);
+
+        if let Some(region) = self.cells.get_mut(start..end) {
+            region.rotate_left(lines as usize * width);
+
+            let freed = region.len() - lines as usize * width;
+            if whole_screen && self.scrollback_size > 0 {
+                for line in region[freed..].chunks(width) {
+                    if self.scrollback.len() >= self.scrollback_size {
+                        self.scrollback.pop_front();
+                    }
+                    self.scrollback.push_back(line.to_vec());
+                }
+            }
+            region[freed..].fill(Cell::blank(self.bg_color));
+        }
+
+        if whole_screen && self.active {
+            if let Some(writer) = FRAMEBUFFER_WRITER.lock().as_mut() {
+                writer.scroll_up((lines * self.font.height()) as usize, self.bg_color);
+            }
+        } else if !whole_screen {
+            self.redraw_lines(self.scroll_top, self.scroll_bottom);
+        }
+    }
+
+    /// Move the scroll region down by `lines`, blank lines come in at the top.
+    fn scroll_down(&mut self, lines: u32) {
+        let lines = lines.min(self.scroll_bottom - self.scroll_top + 1) as usize;
+        let width = self.screen_width as usize;
+
+        let start = self.index(0, self.scroll_top);
+        let end = self.index(0, self.scroll_bottom + 1);
+        if let Some(region) = self.cells.get_mut(start..end) {
+            region.rotate_right(lines * width);
+            region[..lines * width].fill(Cell::blank(self.bg_color));
+        }
+
+        self.redraw_lines(self.scroll_top, self.scroll_bottom);
+    }
+
+    /// The line shown at screen row `line` for the current view.
+    fn visible_line(&self, line: u32) -> &[Cell] {
+        let width = self.screen_width as usize;
+        let line = line as usize;
+
+        // rows above the live screen come from the end of the history
+        match (line + self.scrollback.len()).checked_sub(self.view_offset) {
+            Some(row) if row >= self.scrollback.len() => {
+                let start = (row - self.scrollback.len()) * width;
+                self.cells.get(start..start + width).unwrap_or(&[])
+            }
+            Some(row) => &self.scrollback[row],
+            None => &[],
+        }
+    }
+
+    /// Draw every cell of the current view again.
+    fn redraw(&self) {
+        self.redraw_lines(0, self.screen_height - 1);
+    }
+
+    fn redraw_lines(&self, top: u32, bottom: u32) {
+        for line in top..=bottom {
+            let cells = self.visible_line(line);
+            for col in 0..self.screen_width {
+                let cell = cells.get(col as usize).copied().unwrap_or(Cell::blank(DEFAULT_BG));
+                self.draw_cell(col, line, cell);
+            }
+        }
+    }
+
+    /// Look `lines` further back into the scrollback.
+    pub fn scroll_view_up(&mut self, lines: usize) {
+        let offset = (self.view_offset + lines).min(self.scrollback.len());
+        if offset != self.view_offset {
+            self.view_offset = offset;
+            self.redraw();
+        }
+    }
+
+    /// Move the view `lines` back towards the live screen.
+    pub fn scroll_view_down(&mut self, lines: usize) {
+        let offset = self.view_offset.saturating_sub(lines);
+        if offset != self.view_offset {
+            self.view_offset = offset;
+            self.redraw();
+        }
+    }
+
+    pub fn set_scrollback_size(&mut self, lines: usize) {
+        self.scrollback_size = lines;
+        while self.scrollback.len() > lines {
+            self.scrollback.pop_front();
+        }
+        if self.view_offset > self.scrollback.len() {
+            self.view_offset = self.scrollback.len();
+            self.redraw();
+        }
+    }
+
+    /// Put this console on screen or take it off. Coming on screen draws
+    /// it in full.
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+        if active {
+            self.redraw();
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Cursor position as (column, line).
+    pub fn cursor(&self) -> (u32, u32) {
+        (self.text_col, self.text_line)
+    }
+
+    /// Screen size in characters.
+    pub fn size(&self) -> (u32, u32) {
+        (self.screen_width, self.screen_height)
+    }
+
+    /// Blank the screen and put the cursor top left.
+    pub fn clear(&mut self) {
+        (self.text_line, self.text_col) = (0, 0);
+        self.view_offset = 0;
+        self.cells.fill(Cell::blank(DEFAULT_BG));
+
+        if self.active {
+            if let Some(writer) = FRAMEBUFFER_WRITER.lock().as_mut() {
+                writer.clear();
+            }
+        }
+    }
+
     pub fn write_string(&mut self, s: &str) {
-        for c in s.chars() {
-            self.write_char(c as u8);
+        for byte in s.bytes() {
+            self.write_char(byte);
         }
     }
 
     pub fn set_colour(&mut self, col: (u32, u32)) {
-        self.fg_color = col.0;
-        self.bg_color = col.1;
+        (self.fg_color, self.bg_color) = col;
+        (self.base_fg, self.base_bg) = col;
     }
 
     pub fn reset_colour(&mut self) {
-        self.fg_color = 0xFFFFFF;
-        self.bg_color = 0x000000;
+        self.set_colour((DEFAULT_FG, DEFAULT_BG));
+        (self.bold, self.reverse) = (false, false);
+    }
+
+    /// Foreground and background text is written in now.
+    pub fn colour(&self) -> (u32, u32) {
+        (self.fg_color, self.bg_color)
+    }
+
+    /// Write `args` in `col`, then go back to the colours from before.
+    /// Bold and reverse are left as they are.
+    pub fn write_in_colour(&mut self, col: (u32, u32), args: fmt::Arguments) {
+        use core::fmt::Write;
+
+        let saved = (self.fg_color, self.bg_color, self.base_fg, self.base_bg);
+        self.set_colour(col);
+        self.write_fmt(args).unwrap();
+        (self.fg_color, self.bg_color, self.base_fg, self.base_bg) = saved;
     }
 }
 
@@ -122,50 +625,118 @@ impl core::fmt::Write for TextWriter {
     }
 }
 
-fn write(args: fmt::Arguments, fg_color: u32, bg_color: u32) {
+/// Run `func` on the writer behind `console`, `None` when headless.
+pub fn with_console<R>(console: Console, func: impl FnOnce(&mut TextWriter) -> R) -> Option<R> {
+    x86_64::instructions::interrupts::without_interrupts(|| CONSOLES[console.index()].lock().as_mut().map(func))
+}
+
+/// Write to `console` in `fg_color` on `bg_color`, leaving the colours
+/// escape sequences set as they were. False when headless and nothing was
+/// written.
+pub fn write_coloured(console: Console, args: fmt::Arguments, fg_color: u32, bg_color: u32) -> bool {
+    write_console(console, args, Some((fg_color, bg_color)))
+}
+
+// plain writes carry on in whatever colours escape sequences left behind
+fn write_console(console: Console, args: fmt::Arguments, colour: Option<(u32, u32)>) -> bool {
     use core::fmt::Write;
 
-    interrupts::without(|| {
-        let mut writer = TEXT_WRITER.lock();
-        writer.set_colour((fg_color, bg_color));
-        writer.write_fmt(args).unwrap();
-        writer.reset_colour();
+    let written = with_console(console, |writer| match colour {
+        Some(colour) => writer.write_in_colour(colour, args),
+        None => writer.write_fmt(args).unwrap(),
     });
+
+    if written.is_some() {
+        render::flush();
+    }
+    written.is_some()
+}
+
+fn write(console: Console, args: fmt::Arguments, colour: Option<(u32, u32)>) {
+    if !write_console(console, args, colour) {
+        crate::_serial_write(args);
+    }
+}
+
+/// Write to `console`, on screen or not.
+pub fn write_to(console: Console, args: fmt::Arguments) {
+    write(console, args, None);
 }
 
 pub fn _print(args: fmt::Arguments) {
-    write(args, 0xFFFFFF, 0x000000);
+    write(Console::SHELL, args, None);
 }
 
 pub fn _printerr(args: fmt::Arguments) {
-    write(args, 0xFF8080, 0x000000);
+    write(Console::SHELL, args, Some((ERROR_FG, DEFAULT_BG)));
 }
 
-pub fn _log(args: fmt::Arguments) {
-    write(args, 0xFFFF00, 0x000000);
+/// The console on screen.
+pub fn active() -> Console {
+    Console::from_index(ACTIVE.load(Ordering::Relaxed)).unwrap_or(Console::SHELL)
 }
 
-pub fn clear_screen() {
+/// Put `console` on screen, e.g. for Alt+F1..F6.
+pub fn switch_to(console: Console) {
     interrupts::without(|| {
-        let mut writer = TEXT_WRITER.lock();
-        writer.text_line = 0;
-        writer.text_col = 0;
-    
-        if let Some(writer) = FRAMEBUFFER_WRITER.lock().as_mut() {
-            writer.clear();
+        let previous = ACTIVE.swap(console.index(), Ordering::Relaxed);
+        if previous == console.index() {
+            return;
+        }
+
+        if let Some(writer) = CONSOLES[previous].lock().as_mut() {
+            writer.set_active(false);
         }
-    });    
+        if let Some(writer) = CONSOLES[console.index()].lock().as_mut() {
+            writer.set_active(true);
+        }
+        render::flush();
+    });
 }
 
-#[macro_export]
-macro_rules! println_log {
-	() => ($crate::print_log!("\n"));
-	($($arg:tt)*) => ($crate::print_log!("{}\n", format_args!($($arg)*)));
+/// Clear the shell console.
+pub fn clear_screen() {
+    with_console(Console::SHELL, TextWriter::clear);
+    render::flush();
 }
 
-#[macro_export]
-macro_rules! print_log {
-	($($arg:tt)*) => ($crate::_log(format_args!($($arg)*)));
+/// Page the console on screen back through its scrollback, e.g. for
+/// Shift+PgUp.
+pub fn scroll_page_up() {
+    with_console(active(), |writer| {
+        let page = writer.screen_height as usize - 1;
+        writer.scroll_view_up(page);
+    });
+    render::flush();
+}
+
+/// Page the view forward again, e.g. for Shift+PgDn.
+pub fn scroll_page_down() {
+    with_console(active(), |writer| {
+        let page = writer.screen_height as usize - 1;
+        writer.scroll_view_down(page);
+    });
+    render::flush();
+}
+
+/// Keep at most `lines` lines of history on every console, 0 turns the
+/// scrollback off.
+pub fn set_scrollback_size(lines: usize) {
+    for console in Console::ALL {
+        with_console(console, |writer| writer.set_scrollback_size(lines));
+    }
+    render::flush();
+}
+
+/// Lines currently held in the shell console's scrollback.
+pub fn scrollback_len() -> usize {
+    with_console(Console::SHELL, |writer| writer.scrollback.len()).unwrap_or(0)
+}
+
+/// Cursor position on the shell console as (column, line) and the screen
+/// size in characters, all zero when headless.
+pub fn cursor() -> ((u32, u32), (u32, u32)) {
+    with_console(Console::SHELL, |writer| (writer.cursor(), writer.size())).unwrap_or(((0, 0), (0, 0)))
 }
 
 #[macro_export]
@@ -188,4 +759,4 @@ macro_rules! printlnerr {
 #[macro_export]
 macro_rules! printerr {
     ($($arg:tt)*) => ($crate::_printerr(format_args!($($arg)*)));
-}
\ No newline at end of file
+}
@@ -1,3 +1,5 @@
 pub mod framebuffer;
 pub mod serial;
-pub mod ahci;
\ No newline at end of file
+pub mod ahci;
+pub mod rtc;
+pub mod ps2;
\ No newline at end of file
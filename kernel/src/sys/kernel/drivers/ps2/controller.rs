@@ -0,0 +1,216 @@
+//! Intel 8042 PS/2 controller
+
+use core::time::Duration;
+
+use crate::sys::kernel::cpu::{inb, outb};
+use crate::sys::kernel::time;
+
+const DATA: u16 = 0x60;
+const STATUS: u16 = 0x64;
+const COMMAND: u16 = 0x64;
+
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// The byte in the output buffer came from the second port.
+pub const STATUS_AUX_DATA: u8 = 1 << 5;
+
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_PORT2: u8 = 0xa7;
+const CMD_ENABLE_PORT2: u8 = 0xa8;
+const CMD_TEST_PORT2: u8 = 0xa9;
+const CMD_SELF_TEST: u8 = 0xaa;
+const CMD_TEST_PORT1: u8 = 0xab;
+const CMD_DISABLE_PORT1: u8 = 0xad;
+const CMD_ENABLE_PORT1: u8 = 0xae;
+const CMD_WRITE_PORT2: u8 = 0xd4;
+
+const CONFIG_PORT1_IRQ: u8 = 1 << 0;
+const CONFIG_PORT2_IRQ: u8 = 1 << 1;
+const CONFIG_PORT2_CLOCK_DISABLED: u8 = 1 << 5;
+/// The controller translates set 2 scancodes from the first port to set 1.
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+pub const DEVICE_ACK: u8 = 0xfa;
+pub const DEVICE_RESEND: u8 = 0xfe;
+
+/// How long to wait for the controller or a device before giving up.
+pub const TIMEOUT: Duration = Duration::from_millis(50);
+const RETRIES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    First,
+    Second,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    NoController,
+    Timeout,
+    SelfTestFailed(u8),
+    PortTestFailed(Port, u8),
+    /// The device kept asking for the byte to be sent again.
+    Resend,
+    UnexpectedResponse(u8),
+}
+
+/// What the controller found during [`init`].
+#[derive(Debug, Clone, Copy)]
+pub struct Ports {
+    pub first: bool,
+    pub second: bool,
+    pub translation: bool,
+}
+
+fn status() -> u8 {
+    unsafe { inb(STATUS) }
+}
+
+fn wait(ready: impl Fn(u8) -> bool, timeout: Duration) -> Result<(), Ps2Error> {
+    let deadline = time::uptime() + timeout;
+    while !ready(status()) {
+        if time::uptime() >= deadline {
+            return Err(Ps2Error::Timeout);
+        }
+        core::hint::spin_loop();
+    }
+    Ok(())
+}
+
+/// Wait for a byte from either port.
+pub fn read_timeout(timeout: Duration) -> Result<u8, Ps2Error> {
+    wait(|status| status & STATUS_OUTPUT_FULL != 0, timeout)?;
+    Ok(unsafe { inb(DATA) })
+}
+
+pub fn read() -> Result<u8, Ps2Error> {
+    read_timeout(TIMEOUT)
+}
+
+/// Read whatever is in the output buffer without waiting, along with the
+/// status register it came with.
+pub fn try_read() -> Option<(u8, u8)> {
+    let status = status();
+    (status & STATUS_OUTPUT_FULL != 0).then(|| (unsafe { inb(DATA) }, status))
+}
+
+fn write_data(byte: u8) -> Result<(), Ps2Error> {
+    wait(|status| status & STATUS_INPUT_FULL == 0, TIMEOUT)?;
+    unsafe { outb(DATA, byte) };
+    Ok(())
+}
+
+pub fn command(command: u8) -> Result<(), Ps2Error> {
+    wait(|status| status & STATUS_INPUT_FULL == 0, TIMEOUT)?;
+    unsafe { outb(COMMAND, command) };
+    Ok(())
+}
+
+fn read_config() -> Result<u8, Ps2Error> {
+    command(CMD_READ_CONFIG)?;
+    read()
+}
+
+fn write_config(config: u8) -> Result<(), Ps2Error> {
+    command(CMD_WRITE_CONFIG)?;
+    write_data(config)
+}
+
+/// Send a byte to the device on `port` without waiting for an answer.
+pub fn write(port: Port, byte: u8) -> Result<(), Ps2Error> {
+    if port == Port::Second {
+        command(CMD_WRITE_PORT2)?;
+    }
+    write_data(byte)
+}
+
+/// Send a byte to the device on `port` and wait for it to be acknowledged,
+/// resending it if the device asks.
+///
+/// Only usable while the port's interrupt is off, otherwise the handler eats the reply.
+pub fn send(port: Port, byte: u8) -> Result<(), Ps2Error> {
+    for _ in 0..RETRIES {
+        write(port, byte)?;
+        match read()? {
+            DEVICE_ACK => return Ok(()),
+            DEVICE_RESEND => continue,
+            other => return Err(Ps2Error::UnexpectedResponse(other)),
+        }
+    }
+    Err(Ps2Error::Resend)
+}
+
+/// Turn the interrupt of `port` on or off.
+pub fn set_interrupt(port: Port, enabled: bool) -> Result<(), Ps2Error> {
+    let bit = match port {
+        Port::First => CONFIG_PORT1_IRQ,
+        Port::Second => CONFIG_PORT2_IRQ,
+    };
+
+    let config = read_config()?;
+    write_config(if enabled { config | bit } else { config & !bit })
+}
+
+/// Reset the controller and test both ports. Every port is left enabled but
+/// with its interrupt off, for the device drivers to set up.
+pub fn init() -> Result<Ports, Ps2Error> {
+    // nothing answers on a machine without an 8042
+    if status() == 0xff {
+        return Err(Ps2Error::NoController);
+    }
+
+    command(CMD_DISABLE_PORT1)?;
+    command(CMD_DISABLE_PORT2)?;
+
+    // throw away anything a device sent before we got here
+    while try_read().is_some() {}
+
+    let mut config = read_config()? & !(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ);
+    write_config(config)?;
+
+    command(CMD_SELF_TEST)?;
+    match read_timeout(TIMEOUT * 10)? {
+        SELF_TEST_PASSED => {}
+        other => return Err(Ps2Error::SelfTestFailed(other)),
+    }
+    // some controllers reset themselves during the self test
+    write_config(config)?;
+
+    // the second clock only comes on if there is a second port
+    command(CMD_ENABLE_PORT2)?;
+    config = read_config()?;
+    let dual = config & CONFIG_PORT2_CLOCK_DISABLED == 0;
+    command(CMD_DISABLE_PORT2)?;
+
+    let first = test_port(Port::First).is_ok();
+    let second = dual && test_port(Port::Second).is_ok();
+
+    if first {
+        command(CMD_ENABLE_PORT1)?;
+    }
+    if second {
+        command(CMD_ENABLE_PORT2)?;
+    }
+
+    Ok(Ports {
+        first,
+        second,
+        translation: config & CONFIG_TRANSLATION != 0,
+    })
+}
+
+fn test_port(port: Port) -> Result<(), Ps2Error> {
+    command(match port {
+        Port::First => CMD_TEST_PORT1,
+        Port::Second => CMD_TEST_PORT2,
+    })?;
+
+    match read()? {
+        PORT_TEST_PASSED => Ok(()),
+        other => Err(Ps2Error::PortTestFailed(port, other)),
+    }
+}
@@ -0,0 +1,403 @@
+//! PS/2 keyboard
+//!
+//! The interrupt handler decodes scancodes, tracks modifier and lock state
+//! and queues [`KeyEvent`]s, translated through the current [`Layout`], in
+//! a ring buffer. Everything else reads from that buffer.
+
+use alloc::string::String;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use bitflags::bitflags;
+use spin::Mutex;
+use x86_64::instructions::{hlt, interrupts::without_interrupts};
+
+use crate::sys::std::ring::RingBuffer;
+use crate::sys::kernel::drivers::framebuffer::textwriter::{self, Console};
+use crate::{print, println, println_log};
+
+use super::controller::{self, Port, Ps2Error, DEVICE_ACK, DEVICE_RESEND};
+use super::keymap::Layout;
+use super::scancode::{Decoder, ScancodeSet};
+
+const CMD_SET_LEDS: u8 = 0xed;
+const CMD_SCANCODE_SET: u8 = 0xf0;
+const CMD_ENABLE_SCANNING: u8 = 0xf4;
+const CMD_RESET: u8 = 0xff;
+
+const RESET_PASSED: u8 = 0xaa;
+
+const LED_SCROLL_LOCK: u8 = 1 << 0;
+const LED_NUM_LOCK: u8 = 1 << 1;
+const LED_CAPS_LOCK: u8 = 1 << 2;
+
+const BUFFER_SIZE: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    A, B, C, D, E, F, G, H, I, J, K, L, M,
+    N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+    F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Escape, Backspace, Tab, Enter, Space,
+    Minus, Equals, LeftBracket, RightBracket, Backslash, NonUsBackslash,
+    Semicolon, Quote, Backtick, Comma, Period, Slash,
+    LeftShift, RightShift, LeftCtrl, RightCtrl, LeftAlt, RightAlt, LeftGui, RightGui, Menu,
+    CapsLock, NumLock, ScrollLock,
+    Up, Down, Left, Right, Home, End, PageUp, PageDown, Insert, Delete,
+    PrintScreen, Pause,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadPeriod, NumpadEnter, NumpadPlus, NumpadMinus, NumpadStar, NumpadSlash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Down,
+    Up,
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Modifiers: u16 {
+        const LEFT_SHIFT = 1 << 0;
+        const RIGHT_SHIFT = 1 << 1;
+        const LEFT_CTRL = 1 << 2;
+        const RIGHT_CTRL = 1 << 3;
+        const LEFT_ALT = 1 << 4;
+        const RIGHT_ALT = 1 << 5;
+        const LEFT_GUI = 1 << 6;
+        const RIGHT_GUI = 1 << 7;
+        const CAPS_LOCK = 1 << 8;
+        const NUM_LOCK = 1 << 9;
+        const SCROLL_LOCK = 1 << 10;
+    }
+}
+
+impl Modifiers {
+    pub fn shift(self) -> bool {
+        self.intersects(Self::LEFT_SHIFT | Self::RIGHT_SHIFT)
+    }
+
+    pub fn ctrl(self) -> bool {
+        self.intersects(Self::LEFT_CTRL | Self::RIGHT_CTRL)
+    }
+
+    pub fn alt(self) -> bool {
+        self.contains(Self::LEFT_ALT)
+    }
+
+    /// The right Alt key, which picks third level symbols on non-US layouts.
+    pub fn altgr(self) -> bool {
+        self.contains(Self::RIGHT_ALT)
+    }
+
+    fn held(code: KeyCode) -> Option<Self> {
+        Some(match code {
+            KeyCode::LeftShift => Self::LEFT_SHIFT,
+            KeyCode::RightShift => Self::RIGHT_SHIFT,
+            KeyCode::LeftCtrl => Self::LEFT_CTRL,
+            KeyCode::RightCtrl => Self::RIGHT_CTRL,
+            KeyCode::LeftAlt => Self::LEFT_ALT,
+            KeyCode::RightAlt => Self::RIGHT_ALT,
+            KeyCode::LeftGui => Self::LEFT_GUI,
+            KeyCode::RightGui => Self::RIGHT_GUI,
+            _ => return None,
+        })
+    }
+
+    fn lock(code: KeyCode) -> Option<Self> {
+        Some(match code {
+            KeyCode::CapsLock => Self::CAPS_LOCK,
+            KeyCode::NumLock => Self::NUM_LOCK,
+            KeyCode::ScrollLock => Self::SCROLL_LOCK,
+            _ => return None,
+        })
+    }
+
+    /// LED byte for the set LEDs command.
+    fn leds(self) -> u8 {
+        let mut leds = 0;
+        if self.contains(Self::SCROLL_LOCK) {
+            leds |= LED_SCROLL_LOCK;
+        }
+        if self.contains(Self::NUM_LOCK) {
+            leds |= LED_NUM_LOCK;
+        }
+        if self.contains(Self::CAPS_LOCK) {
+            leds |= LED_CAPS_LOCK;
+        }
+        leds
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// Modifiers after this event was applied.
+    pub modifiers: Modifiers,
+    /// What the key types in the current layout.
+    pub ch: Option<char>,
+}
+
+/// Progress of a set LEDs command, which is sent from the interrupt handler
+/// and acknowledged through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LedUpdate {
+    Idle,
+    AwaitCommandAck(u8),
+    AwaitValueAck(u8),
+}
+
+pub struct Keyboard {
+    decoder: Decoder,
+    modifiers: Modifiers,
+    led: LedUpdate,
+    /// Whether there is a real keyboard to send LED commands to.
+    attached: bool,
+}
+
+impl Keyboard {
+    pub fn new(set: ScancodeSet) -> Self {
+        Self {
+            decoder: Decoder::new(set),
+            modifiers: Modifiers::NUM_LOCK,
+            led: LedUpdate::Idle,
+            attached: false,
+        }
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers
+    }
+
+    /// Handle a byte from the keyboard, returns the key event it completes.
+    pub fn feed(&mut self, byte: u8, layout: Layout) -> Option<KeyEvent> {
+        match byte {
+            DEVICE_ACK => {
+                self.led_acked();
+                return None;
+            }
+            DEVICE_RESEND => {
+                self.led_resend();
+                return None;
+            }
+            _ => {}
+        }
+
+        let (code, state) = self.decoder.advance(byte)?;
+
+        if let Some(held) = Modifiers::held(code) {
+            self.modifiers.set(held, state == KeyState::Down);
+        }
+
+        if let Some(lock) = Modifiers::lock(code) {
+            // typematic repeat sends more presses while the key is held, the
+            // lock should only toggle once so ignore repeats
+            if state == KeyState::Down {
+                self.modifiers.toggle(lock);
+                self.update_leds();
+            }
+        }
+
+        let ch = match state {
+            KeyState::Down => layout.translate(code, self.modifiers),
+            KeyState::Up => None,
+        };
+
+        Some(KeyEvent {
+            code,
+            state,
+            modifiers: self.modifiers,
+            ch,
+        })
+    }
+
+    fn update_leds(&mut self) {
+        if self.attached && self.led == LedUpdate::Idle {
+            let leds = self.modifiers.leds();
+            if controller::write(Port::First, CMD_SET_LEDS).is_ok() {
+                self.led = LedUpdate::AwaitCommandAck(leds);
+            }
+        }
+    }
+
+    fn led_acked(&mut self) {
+        match self.led {
+            LedUpdate::AwaitCommandAck(leds) => {
+                let _ = controller::write(Port::First, leds);
+                self.led = LedUpdate::AwaitValueAck(leds);
+            }
+            LedUpdate::AwaitValueAck(leds) => {
+                self.led = LedUpdate::Idle;
+                // a lock key was toggled while the update was in flight
+                if leds != self.modifiers.leds() {
+                    self.update_leds();
+                }
+            }
+            LedUpdate::Idle => {}
+        }
+    }
+
+    fn led_resend(&mut self) {
+        let byte = match self.led {
+            LedUpdate::AwaitCommandAck(_) => CMD_SET_LEDS,
+            LedUpdate::AwaitValueAck(leds) => leds,
+            LedUpdate::Idle => return,
+        };
+        let _ = controller::write(Port::First, byte);
+    }
+}
+
+static KEYBOARD: Mutex<Option<Keyboard>> = Mutex::new(None);
+static EVENTS: RingBuffer<KeyEvent, BUFFER_SIZE> = RingBuffer::new();
+static LAYOUT: AtomicU8 = AtomicU8::new(Layout::Us as u8);
+
+/// Reset the keyboard on the first port and start taking scancodes.
+/// `translation` says whether the controller turns set 2 into set 1.
+pub fn init(translation: bool) -> Result<(), Ps2Error> {
+    controller::send(Port::First, CMD_RESET)?;
+    // the self test after a reset takes a while
+    match controller::read_timeout(controller::TIMEOUT * 20)? {
+        RESET_PASSED => {}
+        other => return Err(Ps2Error::UnexpectedResponse(other)),
+    }
+
+    let set = if translation { ScancodeSet::Set1 } else { scancode_set()? };
+
+    let mut keyboard = Keyboard::new(set);
+    keyboard.attached = true;
+
+    controller::send(Port::First, CMD_SET_LEDS)?;
+    controller::send(Port::First, keyboard.modifiers.leds())?;
+    controller::send(Port::First, CMD_ENABLE_SCANNING)?;
+
+    without_interrupts(|| *KEYBOARD.lock() = Some(keyboard));
+    controller::set_interrupt(Port::First, true)?;
+
+    println_log!("PS/2 keyboard ready ({:?})...", set);
+    Ok(())
+}
+
+/// Ask the keyboard which set it speaks, switching to set 2 if it's neither
+/// of the ones we decode.
+fn scancode_set() -> Result<ScancodeSet, Ps2Error> {
+    controller::send(Port::First, CMD_SCANCODE_SET)?;
+    controller::send(Port::First, 0)?;
+
+    match controller::read()? {
+        1 => Ok(ScancodeSet::Set1),
+        2 => Ok(ScancodeSet::Set2),
+        _ => {
+            controller::send(Port::First, CMD_SCANCODE_SET)?;
+            controller::send(Port::First, 2)?;
+            Ok(ScancodeSet::Set2)
+        }
+    }
+}
+
+/// Called from the IRQ1 handler with the byte read from the data port.
+pub fn handle_byte(byte: u8) {
+    let layout = layout();
+
+    let event = match KEYBOARD.lock().as_mut() {
+        Some(keyboard) => keyboard.feed(byte, layout),
+        None => None,
+    };
+
+    if let Some(event) = event {
+        if hotkey(&event) {
+            return;
+        }
+
+        // drop keys nobody is reading rather than block in the handler
+        let _ = EVENTS.push(event);
+    }
+}
+
+/// Keys the console handles itself. Returns true if `event` was used up.
+fn hotkey(event: &KeyEvent) -> bool {
+    if event.state != KeyState::Down {
+        return false;
+    }
+
+    let (shift, alt) = (event.modifiers.shift(), event.modifiers.alt());
+    match event.code {
+        KeyCode::PageUp if shift => textwriter::scroll_page_up(),
+        KeyCode::PageDown if shift => textwriter::scroll_page_down(),
+        KeyCode::F1 if alt => textwriter::switch_to(Console::Tty1),
+        KeyCode::F2 if alt => textwriter::switch_to(Console::Tty2),
+        KeyCode::F3 if alt => textwriter::switch_to(Console::Tty3),
+        KeyCode::F4 if alt => textwriter::switch_to(Console::Tty4),
+        KeyCode::F5 if alt => textwriter::switch_to(Console::Tty5),
+        KeyCode::F6 if alt => textwriter::switch_to(Console::Tty6),
+        _ => return false,
+    }
+    true
+}
+
+pub fn layout() -> Layout {
+    Layout::from_u8(LAYOUT.load(Ordering::Relaxed)).unwrap_or(Layout::Us)
+}
+
+pub fn set_layout(layout: Layout) {
+    LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+pub fn modifiers() -> Modifiers {
+    without_interrupts(|| KEYBOARD.lock().as_ref().map_or(Modifiers::empty(), Keyboard::modifiers))
+}
+
+/// Take the next key event, pressed or released, if there is one.
+pub fn try_read_event() -> Option<KeyEvent> {
+    EVENTS.pop()
+}
+
+/// Wait for the next key event.
+pub fn read_event() -> KeyEvent {
+    loop {
+        if let Some(event) = EVENTS.pop() {
+            return event;
+        }
+        hlt();
+    }
+}
+
+/// Wait for the next key press.
+pub fn read_key() -> KeyEvent {
+    loop {
+        let event = read_event();
+        if event.state == KeyState::Down {
+            return event;
+        }
+    }
+}
+
+/// Read a line of typed text, echoing it to the screen. Backspace edits the
+/// line and Enter finishes it, the newline isn't included.
+pub fn read_line() -> String {
+    let mut line = String::new();
+
+    loop {
+        let event = read_key();
+        if event.modifiers.ctrl() {
+            continue;
+        }
+
+        match event.ch {
+            Some('\n') => {
+                println!();
+                return line;
+            }
+            Some('\x08') => {
+                if line.pop().is_some() {
+                    print!("\x08");
+                }
+            }
+            Some(c) if !c.is_control() => {
+                line.push(c);
+                print!("{}", c);
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Keyboard layouts
+//!
+//! Layouts map physical keys to characters. Keys are named after their
+//! position on a US keyboard, so a layout only has to describe where it
+//! differs from that.
+
+use super::keyboard::{KeyCode, Modifiers};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Layout {
+    Us = 0,
+    Uk = 1,
+}
+
+impl Layout {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Layout::Us),
+            1 => Some(Layout::Uk),
+            _ => None,
+        }
+    }
+
+    /// The character `code` types with `modifiers` held, if any.
+    pub fn translate(self, code: KeyCode, modifiers: Modifiers) -> Option<char> {
+        let shift = modifiers.shift();
+
+        if let Some(letter) = letter(code) {
+            // caps lock only affects letters
+            return Some(match shift != modifiers.contains(Modifiers::CAPS_LOCK) {
+                true => letter.to_ascii_uppercase(),
+                false => letter,
+            });
+        }
+
+        if let Some(c) = numpad(code, modifiers.contains(Modifiers::NUM_LOCK)) {
+            return c;
+        }
+
+        let layout = match self {
+            Layout::Us => None,
+            Layout::Uk => uk(code, shift, modifiers.altgr()),
+        };
+
+        layout.or_else(|| us(code, shift))
+    }
+}
+
+fn letter(code: KeyCode) -> Option<char> {
+    use KeyCode::*;
+
+    let letters = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+    letters
+        .iter()
+        .position(|&letter| letter == code)
+        .map(|index| (b'a' + index as u8) as char)
+}
+
+/// `Some(None)` for numpad keys that don't type anything right now.
+fn numpad(code: KeyCode, num_lock: bool) -> Option<Option<char>> {
+    use KeyCode::*;
+
+    let digit = |c| Some(num_lock.then_some(c));
+
+    match code {
+        Numpad0 => digit('0'),
+        Numpad1 => digit('1'),
+        Numpad2 => digit('2'),
+        Numpad3 => digit('3'),
+        Numpad4 => digit('4'),
+        Numpad5 => digit('5'),
+        Numpad6 => digit('6'),
+        Numpad7 => digit('7'),
+        Numpad8 => digit('8'),
+        Numpad9 => digit('9'),
+        NumpadPeriod => digit('.'),
+        NumpadSlash => Some(Some('/')),
+        NumpadStar => Some(Some('*')),
+        NumpadMinus => Some(Some('-')),
+        NumpadPlus => Some(Some('+')),
+        NumpadEnter => Some(Some('\n')),
+        _ => None,
+    }
+}
+
+fn us(code: KeyCode, shift: bool) -> Option<char> {
+    use KeyCode::*;
+
+    let (normal, shifted) = match code {
+        Key1 => ('1', '!'),
+        Key2 => ('2', '@'),
+        Key3 => ('3', '#'),
+        Key4 => ('4', '$'),
+        Key5 => ('5', '%'),
+        Key6 => ('6', '^'),
+        Key7 => ('7', '&'),
+        Key8 => ('8', '*'),
+        Key9 => ('9', '('),
+        Key0 => ('0', ')'),
+        Minus => ('-', '_'),
+        Equals => ('=', '+'),
+        LeftBracket => ('[', '{'),
+        RightBracket => (']', '}'),
+        Backslash => ('\\', '|'),
+        NonUsBackslash => ('\\', '|'),
+        Semicolon => (';', ':'),
+        Quote => ('\'', '"'),
+        Backtick => ('`', '~'),
+        Comma => (',', '<'),
+        Period => ('.', '>'),
+        Slash => ('/', '?'),
+        Space => (' ', ' '),
+        Tab => ('\t', '\t'),
+        Enter => ('\n', '\n'),
+        Backspace => ('\x08', '\x08'),
+        Escape => ('\x1b', '\x1b'),
+        Delete => ('\x7f', '\x7f'),
+        _ => return None,
+    };
+
+    Some(if shift { shifted } else { normal })
+}
+
+fn uk(code: KeyCode, shift: bool, altgr: bool) -> Option<char> {
+    use KeyCode::*;
+
+    if altgr {
+        return match code {
+            Key4 => Some('€'),
+            Backtick => Some('¦'),
+            _ => None,
+        };
+    }
+
+    let (normal, shifted) = match code {
+        Key2 => ('2', '"'),
+        Key3 => ('3', '£'),
+        Quote => ('\'', '@'),
+        Backtick => ('`', '¬'),
+        // the key left of Enter
+        Backslash => ('#', '~'),
+        _ => return None,
+    };
+
+    Some(if shift { shifted } else { normal })
+}
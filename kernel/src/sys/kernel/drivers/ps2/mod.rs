@@ -0,0 +1,31 @@
+//! PS/2 devices behind the 8042 controller
+
+pub mod controller;
+pub mod keyboard;
+pub mod keymap;
+pub mod mouse;
+pub mod scancode;
+
+use crate::println_log;
+
+pub fn init() {
+    let ports = match controller::init() {
+        Ok(ports) => ports,
+        Err(err) => {
+            println_log!("PS/2: controller init failed ({:?})", err);
+            return;
+        }
+    };
+
+    if ports.first {
+        if let Err(err) = keyboard::init(ports.translation) {
+            println_log!("PS/2: no keyboard ({:?})", err);
+        }
+    }
+
+    if ports.second {
+        if let Err(err) = mouse::init() {
+            println_log!("PS/2: no mouse ({:?})", err);
+        }
+    }
+}
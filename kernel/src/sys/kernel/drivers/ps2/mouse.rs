@@ -0,0 +1,291 @@
+//! PS/2 mouse
+//!
+//! Decodes the 3 byte standard packets and the 4 byte IntelliMouse packets
+//! with a scroll wheel (and, on 5 button mice, the side buttons). Every
+//! packet becomes a [`MouseEvent`] that is queued for [`read_event`] and
+//! handed to every subscriber.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use bitflags::bitflags;
+use spin::Mutex;
+use x86_64::instructions::{hlt, interrupts::without_interrupts};
+
+use crate::println_log;
+use crate::sys::kernel::cpu::x86_64::interrupts::{self, InterruptIndex};
+use crate::sys::std::ring::RingBuffer;
+
+use super::controller::{self, Port, Ps2Error};
+
+const CMD_GET_ID: u8 = 0xf2;
+const CMD_SAMPLE_RATE: u8 = 0xf3;
+const CMD_ENABLE_REPORTING: u8 = 0xf4;
+const CMD_SET_DEFAULTS: u8 = 0xf6;
+const CMD_RESET: u8 = 0xff;
+
+const RESET_PASSED: u8 = 0xaa;
+
+const ID_STANDARD: u8 = 0x00;
+const ID_WHEEL: u8 = 0x03;
+const ID_FIVE_BUTTONS: u8 = 0x04;
+
+// first packet byte
+const PACKET_LEFT: u8 = 1 << 0;
+const PACKET_RIGHT: u8 = 1 << 1;
+const PACKET_MIDDLE: u8 = 1 << 2;
+/// Always set, used to find the start of a packet.
+const PACKET_ALWAYS_ONE: u8 = 1 << 3;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_X_OVERFLOW: u8 = 1 << 6;
+const PACKET_Y_OVERFLOW: u8 = 1 << 7;
+
+// fourth packet byte of a 5 button mouse
+const PACKET_BUTTON_4: u8 = 1 << 4;
+const PACKET_BUTTON_5: u8 = 1 << 5;
+
+const SAMPLE_RATE: u8 = 100;
+const BUFFER_SIZE: usize = 256;
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+    pub struct MouseButtons: u8 {
+        const LEFT = 1 << 0;
+        const RIGHT = 1 << 1;
+        const MIDDLE = 1 << 2;
+        const BUTTON_4 = 1 << 3;
+        const BUTTON_5 = 1 << 4;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseKind {
+    Standard,
+    /// IntelliMouse with a scroll wheel.
+    Wheel,
+    /// IntelliMouse Explorer, wheel and two side buttons.
+    FiveButtons,
+}
+
+impl MouseKind {
+    fn packet_size(self) -> usize {
+        match self {
+            MouseKind::Standard => 3,
+            _ => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Movement to the right.
+    pub dx: i16,
+    /// Movement up, the same direction the mouse reports.
+    pub dy: i16,
+    /// Wheel clicks, positive is towards the user.
+    pub wheel: i8,
+    /// Buttons held after this packet.
+    pub buttons: MouseButtons,
+    pub pressed: MouseButtons,
+    pub released: MouseButtons,
+}
+
+/// Packet assembly for one mouse.
+pub struct Mouse {
+    kind: MouseKind,
+    packet: [u8; 4],
+    index: usize,
+    buttons: MouseButtons,
+}
+
+impl Mouse {
+    pub fn new(kind: MouseKind) -> Self {
+        Self {
+            kind,
+            packet: [0; 4],
+            index: 0,
+            buttons: MouseButtons::empty(),
+        }
+    }
+
+    pub fn kind(&self) -> MouseKind {
+        self.kind
+    }
+
+    /// Handle a byte from the mouse, returns the event once a packet is complete.
+    pub fn feed(&mut self, byte: u8) -> Option<MouseEvent> {
+        // a lost byte shifts every packet after it, wait for something that
+        // looks like a first byte again
+        if self.index == 0 && byte & PACKET_ALWAYS_ONE == 0 {
+            return None;
+        }
+
+        self.packet[self.index] = byte;
+        self.index += 1;
+
+        if self.index < self.kind.packet_size() {
+            return None;
+        }
+        self.index = 0;
+
+        Some(self.decode())
+    }
+
+    fn decode(&mut self) -> MouseEvent {
+        let [flags, x, y, extra] = self.packet;
+
+        let movement = |value: u8, sign: u8, overflow: u8| -> i16 {
+            match (flags & overflow != 0, flags & sign != 0) {
+                // the real value is unknown, better not to move at all
+                (true, _) => 0,
+                (false, true) => value as i16 - 0x100,
+                (false, false) => value as i16,
+            }
+        };
+
+        let mut buttons = MouseButtons::empty();
+        buttons.set(MouseButtons::LEFT, flags & PACKET_LEFT != 0);
+        buttons.set(MouseButtons::RIGHT, flags & PACKET_RIGHT != 0);
+        buttons.set(MouseButtons::MIDDLE, flags & PACKET_MIDDLE != 0);
+
+        let wheel = match self.kind {
+            MouseKind::Standard => 0,
+            MouseKind::Wheel => extra as i8,
+            MouseKind::FiveButtons => {
+                buttons.set(MouseButtons::BUTTON_4, extra & PACKET_BUTTON_4 != 0);
+                buttons.set(MouseButtons::BUTTON_5, extra & PACKET_BUTTON_5 != 0);
+                // the wheel is only the low nibble here, sign extend it
+                ((extra << 4) as i8) >> 4
+            }
+        };
+
+        let previous = core::mem::replace(&mut self.buttons, buttons);
+
+        MouseEvent {
+            dx: movement(x, PACKET_X_SIGN, PACKET_X_OVERFLOW),
+            dy: movement(y, PACKET_Y_SIGN, PACKET_Y_OVERFLOW),
+            wheel,
+            buttons,
+            pressed: buttons & !previous,
+            released: previous & !buttons,
+        }
+    }
+}
+
+pub type Subscriber = Box<dyn Fn(&MouseEvent) + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriberId(u64);
+
+static MOUSE: Mutex<Option<Mouse>> = Mutex::new(None);
+static EVENTS: RingBuffer<MouseEvent, BUFFER_SIZE> = RingBuffer::new();
+static SUBSCRIBERS: Mutex<Vec<(SubscriberId, Subscriber)>> = Mutex::new(Vec::new());
+static NEXT_SUBSCRIBER: AtomicU64 = AtomicU64::new(1);
+
+/// Reset the mouse on the second port, turn on the wheel if it has one and
+/// start reporting.
+pub fn init() -> Result<(), Ps2Error> {
+    controller::send(Port::Second, CMD_RESET)?;
+    match controller::read_timeout(controller::TIMEOUT * 20)? {
+        RESET_PASSED => {}
+        other => return Err(Ps2Error::UnexpectedResponse(other)),
+    }
+    // a mouse follows the self test result with its ID
+    let _ = controller::read();
+
+    controller::send(Port::Second, CMD_SET_DEFAULTS)?;
+
+    let kind = detect_kind()?;
+
+    set_sample_rate(SAMPLE_RATE)?;
+    controller::send(Port::Second, CMD_ENABLE_REPORTING)?;
+
+    without_interrupts(|| *MOUSE.lock() = Some(Mouse::new(kind)));
+    controller::set_interrupt(Port::Second, true)?;
+    interrupts::enable_irq(InterruptIndex::Mouse);
+
+    println_log!("PS/2 mouse ready ({:?})...", kind);
+    Ok(())
+}
+
+fn set_sample_rate(rate: u8) -> Result<(), Ps2Error> {
+    controller::send(Port::Second, CMD_SAMPLE_RATE)?;
+    controller::send(Port::Second, rate)
+}
+
+fn id() -> Result<u8, Ps2Error> {
+    controller::send(Port::Second, CMD_GET_ID)?;
+    controller::read()
+}
+
+/// The IntelliMouse extensions are unlocked by magic sample rate sequences,
+/// the ID afterwards says whether it worked.
+fn detect_kind() -> Result<MouseKind, Ps2Error> {
+    for rate in [200, 100, 80] {
+        set_sample_rate(rate)?;
+    }
+    if id()? != ID_WHEEL {
+        return Ok(MouseKind::Standard);
+    }
+
+    for rate in [200, 200, 80] {
+        set_sample_rate(rate)?;
+    }
+    match id()? {
+        ID_FIVE_BUTTONS => Ok(MouseKind::FiveButtons),
+        ID_STANDARD => Ok(MouseKind::Standard),
+        _ => Ok(MouseKind::Wheel),
+    }
+}
+
+/// Called from the IRQ12 handler with the byte read from the data port.
+pub fn handle_byte(byte: u8) {
+    let event = match MOUSE.lock().as_mut() {
+        Some(mouse) => mouse.feed(byte),
+        None => None,
+    };
+
+    let Some(event) = event else {
+        return;
+    };
+
+    let _ = EVENTS.push(event);
+
+    for (_, subscriber) in SUBSCRIBERS.lock().iter() {
+        subscriber(&event);
+    }
+}
+
+/// Call `subscriber` for every mouse event.
+///
+/// Subscribers run in the interrupt handler, so they have to be quick and
+/// must not subscribe or unsubscribe themselves.
+pub fn subscribe(subscriber: impl Fn(&MouseEvent) + Send + 'static) -> SubscriberId {
+    let id = SubscriberId(NEXT_SUBSCRIBER.fetch_add(1, Ordering::Relaxed));
+    without_interrupts(|| SUBSCRIBERS.lock().push((id, Box::new(subscriber))));
+    id
+}
+
+pub fn unsubscribe(id: SubscriberId) -> bool {
+    without_interrupts(|| {
+        let mut subscribers = SUBSCRIBERS.lock();
+        let before = subscribers.len();
+        subscribers.retain(|(subscriber, _)| *subscriber != id);
+        subscribers.len() != before
+    })
+}
+
+pub fn try_read_event() -> Option<MouseEvent> {
+    EVENTS.pop()
+}
+
+/// Wait for the next mouse event.
+pub fn read_event() -> MouseEvent {
+    loop {
+        if let Some(event) = EVENTS.pop() {
+            return event;
+        }
+        hlt();
+    }
+}
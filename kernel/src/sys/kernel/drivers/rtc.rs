@@ -0,0 +1,291 @@
+//! CMOS real time clock
+//!
+//! The RTC keeps calendar time in the CMOS, in BCD or binary and in 12 or 24
+//! hour format depending on how the firmware set it up. Reads can race with
+//! the once-a-second update, so [`read`] keeps reading until it sees the same
+//! time twice with no update in progress.
+
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::println_log;
+use crate::sys::kernel::acpi;
+use crate::sys::kernel::cpu::{inb, outb};
+use crate::sys::kernel::cpu::x86_64::interrupts::{self, InterruptIndex};
+
+const CMOS_INDEX: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+/// Set in the index port to keep NMIs disabled while we access the CMOS.
+const NMI_DISABLE: u8 = 0x80;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0a;
+const REG_STATUS_B: u8 = 0x0b;
+const REG_STATUS_C: u8 = 0x0c;
+/// Read-only, selected after each access so the index port is left with
+/// NMIs enabled.
+const REG_STATUS_D: u8 = 0x0d;
+
+const STATUS_A_UPDATE_IN_PROGRESS: u8 = 1 << 7;
+pub const STATUS_B_24_HOUR: u8 = 1 << 1;
+pub const STATUS_B_BINARY: u8 = 1 << 2;
+const STATUS_B_UPDATE_INTERRUPT: u8 = 1 << 4;
+const STATUS_C_UPDATE_ENDED: u8 = 1 << 4;
+
+/// In 12 hour mode the top bit of the hour register means PM.
+const HOUR_PM: u8 = 0x80;
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+// CMOS index of the century register from the FADT, 0 if there is none
+static CENTURY: AtomicU8 = AtomicU8::new(0);
+
+static UPDATES_ENABLED: AtomicBool = AtomicBool::new(false);
+// unix time as of the last update interrupt
+static LAST_UPDATE: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Seconds since 1970-01-01 00:00:00 UTC.
+    pub fn unix_timestamp(&self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        days as u64 * SECONDS_PER_DAY + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64
+    }
+
+    pub fn from_unix_timestamp(timestamp: u64) -> Self {
+        let (year, month, day) = civil_from_days((timestamp / SECONDS_PER_DAY) as i64);
+        let seconds = timestamp % SECONDS_PER_DAY;
+
+        Self {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+// https://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Register values as the CMOS holds them, before any format conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawTime {
+    pub second: u8,
+    pub minute: u8,
+    pub hour: u8,
+    pub day: u8,
+    pub month: u8,
+    pub year: u8,
+    /// Only there if the FADT names a century register.
+    pub century: Option<u8>,
+}
+
+impl RawTime {
+    /// Convert from the format described by status register B.
+    pub fn decode(&self, status_b: u8) -> DateTime {
+        let binary = status_b & STATUS_B_BINARY != 0;
+        let convert = |value: u8| if binary { value } else { bcd_to_binary(value) };
+
+        let pm = self.hour & HOUR_PM != 0;
+        let mut hour = convert(self.hour & !HOUR_PM);
+        if status_b & STATUS_B_24_HOUR == 0 {
+            // 12 AM is midnight and 12 PM is noon
+            hour = match (hour, pm) {
+                (12, false) => 0,
+                (12, true) => 12,
+                (hour, true) => hour + 12,
+                (hour, false) => hour,
+            };
+        }
+
+        let year = convert(self.year) as u16;
+        let year = match self.century {
+            Some(century) => convert(century) as u16 * 100 + year,
+            None => 2000 + year,
+        };
+
+        DateTime {
+            year,
+            month: convert(self.month),
+            day: convert(self.day),
+            hour,
+            minute: convert(self.minute),
+            second: convert(self.second),
+        }
+    }
+}
+
+pub fn bcd_to_binary(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+fn read_register(reg: u8) -> u8 {
+    unsafe {
+        outb(CMOS_INDEX, NMI_DISABLE | reg);
+        let value = inb(CMOS_DATA);
+        outb(CMOS_INDEX, REG_STATUS_D);
+        value
+    }
+}
+
+fn write_register(reg: u8, value: u8) {
+    unsafe {
+        outb(CMOS_INDEX, NMI_DISABLE | reg);
+        outb(CMOS_DATA, value);
+        outb(CMOS_INDEX, REG_STATUS_D);
+    }
+}
+
+fn update_in_progress() -> bool {
+    read_register(REG_STATUS_A) & STATUS_A_UPDATE_IN_PROGRESS != 0
+}
+
+fn read_raw(century_register: Option<u8>) -> RawTime {
+    while update_in_progress() {
+        core::hint::spin_loop();
+    }
+
+    RawTime {
+        second: read_register(REG_SECONDS),
+        minute: read_register(REG_MINUTES),
+        hour: read_register(REG_HOURS),
+        day: read_register(REG_DAY),
+        month: read_register(REG_MONTH),
+        year: read_register(REG_YEAR),
+        century: century_register.map(read_register),
+    }
+}
+
+pub fn init() {
+    if let Some(fadt) = acpi::fadt() {
+        CENTURY.store(fadt.century, Ordering::SeqCst);
+    }
+
+    println_log!("RTC: {} UTC...", read());
+}
+
+fn century_register() -> Option<u8> {
+    match CENTURY.load(Ordering::Relaxed) {
+        0 => None,
+        reg => Some(reg),
+    }
+}
+
+/// Read the current date and time from the CMOS.
+pub fn read() -> DateTime {
+    let century_register = century_register();
+
+    without_interrupts(|| {
+        // an update can start right after the UIP check, so only trust a
+        // value that reads the same twice in a row
+        let mut last = read_raw(century_register);
+        loop {
+            let raw = read_raw(century_register);
+            if raw == last {
+                break;
+            }
+            last = raw;
+        }
+
+        last.decode(read_register(REG_STATUS_B))
+    })
+}
+
+/// Seconds since the Unix epoch. Served from the last update interrupt when
+/// those are enabled, otherwise read from the CMOS.
+pub fn unix_time() -> u64 {
+    match UPDATES_ENABLED.load(Ordering::Relaxed) {
+        true => LAST_UPDATE.load(Ordering::Relaxed),
+        false => read().unix_timestamp(),
+    }
+}
+
+pub fn now() -> DateTime {
+    DateTime::from_unix_timestamp(unix_time())
+}
+
+/// Have the RTC raise IRQ8 after every update, once a second.
+pub fn enable_update_interrupts() {
+    LAST_UPDATE.store(read().unix_timestamp(), Ordering::SeqCst);
+
+    without_interrupts(|| {
+        let status_b = read_register(REG_STATUS_B);
+        write_register(REG_STATUS_B, status_b | STATUS_B_UPDATE_INTERRUPT);
+        // a pending flag would keep the line asserted
+        read_register(REG_STATUS_C);
+    });
+
+    UPDATES_ENABLED.store(true, Ordering::SeqCst);
+    interrupts::enable_irq(InterruptIndex::Rtc);
+}
+
+pub fn disable_update_interrupts() {
+    interrupts::disable_irq(InterruptIndex::Rtc);
+    UPDATES_ENABLED.store(false, Ordering::SeqCst);
+
+    without_interrupts(|| {
+        let status_b = read_register(REG_STATUS_B);
+        write_register(REG_STATUS_B, status_b & !STATUS_B_UPDATE_INTERRUPT);
+        read_register(REG_STATUS_C);
+    });
+}
+
+/// Called from the IRQ8 handler.
+pub fn handle_interrupt() {
+    // reading status C acknowledges the interrupt, the RTC won't raise
+    // another one until it has been read
+    if read_register(REG_STATUS_C) & STATUS_C_UPDATE_ENDED != 0 {
+        // the next update is almost a second away, no need to race it
+        let time = read_raw(century_register()).decode(read_register(REG_STATUS_B));
+        LAST_UPDATE.store(time.unix_timestamp(), Ordering::Relaxed);
+    }
+}
@@ -2,5 +2,9 @@ pub mod serial;
 
 pub use serial::{
     _serial_write,
-    serial_read
+    serial_read,
+    ComPort,
+    SerialConfig,
+    SerialError,
+    SerialPort
 };
@@ -1,95 +1,474 @@
-use core::{fmt, sync::atomic::{AtomicUsize, Ordering}};
+use alloc::string::String;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use spin::Mutex;
 use lazy_static::lazy_static;
 
 use crate::sys::kernel::cpu::{inb, outb};
-use crate::sys::kernel::cpu::x86_64::interrupts;
+use crate::sys::kernel::cpu::x86_64::interrupts::{self, InterruptIndex};
+use crate::sys::std::ring::RingBuffer;
+use crate::sys::std::utf8::Utf8Decoder;
 
-static PORT: u16 = 0x3f8;
-static mut BUFFER: [u8; 256] = [0; 256];
-static BUFFER_LEN: AtomicUsize = AtomicUsize::new(0);
+// register offsets from the base port
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const DIVISOR_LOW: u16 = 0;
+const DIVISOR_HIGH: u16 = 1;
+const INTERRUPT_ID: u16 = 2;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+const SCRATCH: u16 = 7;
 
-lazy_static!{
-    static ref SERIAL_WRITER: Mutex<SerialWriter> = Mutex::new(SerialWriter::new());
+const IER_RECEIVED_DATA: u8 = 1 << 0;
+const IER_TRANSMIT_EMPTY: u8 = 1 << 1;
+
+const IIR_NO_INTERRUPT: u8 = 1 << 0;
+
+// enable and clear both FIFOs, interrupt at 14 bytes
+const FCR_ENABLE_CLEAR_14: u8 = 0xc7;
+
+const LCR_DLAB: u8 = 1 << 7;
+
+// DTR, RTS and OUT2, which gates the IRQ line
+const MCR_NORMAL: u8 = 0x0b;
+const MCR_LOOPBACK: u8 = 0x1e;
+
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// The UART divides this clock by the divisor latch to get the baud rate.
+const BASE_BAUD: u32 = 115_200;
+
+/// Bytes the transmit FIFO takes once it reports empty.
+const FIFO_SIZE: usize = 16;
+const BUFFER_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ComPort {
+    Com1 = 0,
+    Com2 = 1,
+    Com3 = 2,
+    Com4 = 3,
+}
+
+impl ComPort {
+    pub const ALL: [ComPort; 4] = [ComPort::Com1, ComPort::Com2, ComPort::Com3, ComPort::Com4];
+
+    pub fn base(self) -> u16 {
+        match self {
+            ComPort::Com1 => 0x3f8,
+            ComPort::Com2 => 0x2f8,
+            ComPort::Com3 => 0x3e8,
+            ComPort::Com4 => 0x2e8,
+        }
+    }
+
+    /// COM1 and COM3 share IRQ4, COM2 and COM4 share IRQ3.
+    pub fn interrupt(self) -> InterruptIndex {
+        match self {
+            ComPort::Com1 | ComPort::Com3 => InterruptIndex::Com1,
+            ComPort::Com2 | ComPort::Com4 => InterruptIndex::Com2,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
 }
 
-struct SerialWriter;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataBits {
+    Five = 0b00,
+    Six = 0b01,
+    Seven = 0b10,
+    Eight = 0b11,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None = 0b000,
+    Odd = 0b001,
+    Even = 0b011,
+    Mark = 0b101,
+    Space = 0b111,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One = 0,
+    /// 1.5 stop bits with five data bits.
+    Two = 1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud: 38_400,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+        }
+    }
+}
+
+impl SerialConfig {
+    fn divisor(&self) -> Result<u16, SerialError> {
+        if self.baud == 0 || !BASE_BAUD.is_multiple_of(self.baud) {
+            return Err(SerialError::InvalidBaud(self.baud));
+        }
+        Ok((BASE_BAUD / self.baud) as u16)
+    }
+
+    fn line_control(&self) -> u8 {
+        self.data_bits as u8 | (self.stop_bits as u8) << 2 | (self.parity as u8) << 3
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    /// Nothing answers at the port's address.
+    NotPresent(ComPort),
+    /// The UART didn't echo a byte back in loopback mode.
+    LoopbackFailed(ComPort),
+    /// The baud rate doesn't divide the 115200 Hz base clock.
+    InvalidBaud(u32),
+}
 
-impl fmt::Write for SerialWriter {
+struct Buffers {
+    // filled by the interrupt handler, drained by readers
+    rx: RingBuffer<u8, BUFFER_SIZE>,
+    // filled by writers, drained by the interrupt handler
+    tx: RingBuffer<u8, BUFFER_SIZE>,
+    // set once the port's IRQ is routed, until then everything is polled
+    interrupt_driven: AtomicBool,
+}
+
+impl Buffers {
+    const fn new() -> Self {
+        Self {
+            rx: RingBuffer::new(),
+            tx: RingBuffer::new(),
+            interrupt_driven: AtomicBool::new(false),
+        }
+    }
+}
+
+static BUFFERS: [Buffers; 4] = [const { Buffers::new() }; 4];
+
+/// A 16550 compatible UART.
+pub struct SerialPort {
+    com: ComPort,
+    base: u16,
+    config: SerialConfig,
+}
+
+impl fmt::Write for SerialPort {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            self.write(c as u8);
+        for byte in s.bytes() {
+            self.write_byte(byte);
         }
         Ok(())
     }
 }
 
-impl SerialWriter {
-    pub fn new() -> SerialWriter {
-        // first we make sure that the serial port is setup and working.
+impl SerialPort {
+    /// Probe `com` and set it up with `config`.
+    pub fn new(com: ComPort, config: SerialConfig) -> Result<SerialPort, SerialError> {
+        let base = com.base();
+        let divisor = config.divisor()?;
+
         unsafe {
-            outb(PORT + 1, 0x00);    // Disable all interrupts
-            outb(PORT + 3, 0x80);    // Enable DLAB (set baud rate divisor)
-            outb(PORT + 0, 0x03);    // Set divisor to 3 (lo byte) 38400 baud
-            outb(PORT + 1, 0x00);    //                  (hi byte)
-            outb(PORT + 3, 0x03);    // 8 bits, no parity, one stop bit
-            outb(PORT + 2, 0xC7);    // Enable FIFO, clear them, with 14-bytethreshold
-            outb(PORT + 4, 0x0B);    // IRQs enabled, RTS/DSR set
-            outb(PORT + 4, 0x1E);    // Set in loopback mode, test the serial chip
-            outb(PORT + 0, 0xAE);    // Test serial chip (send byte 0xAE and check if serial returns same byte)
-    
-            if inb(PORT + 0)  != 0xAE {
-                panic!("serial port is not working!");
+            // an absent port floats, the scratch register won't hold a value
+            outb(base + SCRATCH, 0x5a);
+            if inb(base + SCRATCH) != 0x5a {
+                return Err(SerialError::NotPresent(com));
             }
-    
-            outb(PORT + 4, 0x0F);
+
+            let [divisor_low, divisor_high] = divisor.to_le_bytes();
+
+            outb(base + INTERRUPT_ENABLE, 0x00);                // Disable all interrupts
+            outb(base + LINE_CONTROL, LCR_DLAB);                // Enable DLAB (set baud rate divisor)
+            outb(base + DIVISOR_LOW, divisor_low);
+            outb(base + DIVISOR_HIGH, divisor_high);
+            outb(base + LINE_CONTROL, config.line_control());   // data bits, parity and stop bits
+            outb(base + FIFO_CONTROL, FCR_ENABLE_CLEAR_14);
+            outb(base + MODEM_CONTROL, MCR_LOOPBACK);           // Set in loopback mode, test the serial chip
+            outb(base + DATA, 0xae);                            // Test serial chip (send byte 0xAE and check if serial returns same byte)
+
+            if inb(base + DATA) != 0xae {
+                return Err(SerialError::LoopbackFailed(com));
+            }
+
+            outb(base + MODEM_CONTROL, MCR_NORMAL);
         }
 
-        SerialWriter
+        Ok(SerialPort { com, base, config })
+    }
+
+    pub fn com(&self) -> ComPort {
+        self.com
+    }
+
+    pub fn config(&self) -> SerialConfig {
+        self.config
     }
 
-    // returnstrue if there is new data on the serial port
-    unsafe fn serial_recieved(&self) -> bool {
-        inb(PORT + 5) & 1 != 0
+    fn buffers(&self) -> &'static Buffers {
+        &BUFFERS[self.com.index()]
+    }
+
+    /// Whether the port is buffered through its IRQ rather than polled.
+    pub fn interrupt_driven(&self) -> bool {
+        self.buffers().interrupt_driven.load(Ordering::Relaxed)
+    }
+
+    // returns true if there is new data on the serial port
+    unsafe fn received(&self) -> bool {
+        inb(self.base + LINE_STATUS) & LSR_DATA_READY != 0
     }
 
     // returns true if the transmit buffer is empty
-    unsafe fn serial_sent(&self) -> bool {
-        inb(PORT + 5) & 0x20 != 0
+    unsafe fn transmit_empty(&self) -> bool {
+        inb(self.base + LINE_STATUS) & LSR_TRANSMIT_EMPTY != 0
+    }
+
+    pub fn write_byte(&mut self, data: u8) {
+        if !self.interrupt_driven() {
+            self.write_polled(data);
+            return;
+        }
+
+        // make room by pushing the oldest byte out by hand
+        let tx = &self.buffers().tx;
+        while tx.push(data).is_err() {
+            if let Some(byte) = tx.pop() {
+                self.write_polled(byte);
+            }
+        }
     }
 
-    pub fn read(&self) -> u8 { unsafe {
-        while !self.serial_recieved() {};
-        return inb(PORT + 0);
+    fn write_polled(&self, data: u8) { unsafe {
+        while !self.transmit_empty() {};
+        outb(self.base + DATA, data);
     }}
 
-    pub fn read_str_to_buffer(&mut self) { unsafe {
-        while !self.serial_recieved() {};
+    fn fill_fifo(&self) { unsafe {
+        for byte in core::iter::from_fn(|| self.buffers().tx.pop()).take(FIFO_SIZE) {
+            outb(self.base + DATA, byte);
+        }
+    }}
+
+    /// Fill the FIFO from the transmit queue if the UART is idle, the THRE
+    /// interrupt keeps it going from there. Needs interrupts disabled.
+    pub fn start_transmit(&self) {
+        if unsafe { self.transmit_empty() } {
+            self.fill_fifo();
+        }
+    }
+
+    /// Send everything queued by polling, for when interrupts are off.
+    pub fn flush(&self) {
+        while let Some(byte) = self.buffers().tx.pop() {
+            self.write_polled(byte);
+        }
+    }
+
+    /// Bytes queued for sending that haven't reached the UART yet.
+    pub fn pending(&self) -> usize {
+        self.buffers().tx.len()
+    }
+
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        if self.interrupt_driven() {
+            return self.buffers().rx.pop();
+        }
+
+        unsafe { self.received().then(|| inb(self.base + DATA)) }
+    }
 
-        BUFFER_LEN.store(0, Ordering::SeqCst);
+    /// Raise the port's IRQ on received data and an empty transmitter, and
+    /// buffer both directions from then on.
+    pub fn enable_interrupts(&mut self) {
+        unsafe { outb(self.base + INTERRUPT_ENABLE, IER_RECEIVED_DATA | IER_TRANSMIT_EMPTY) };
+        self.buffers().interrupt_driven.store(true, Ordering::SeqCst);
+    }
 
-        while self.serial_recieved() && BUFFER_LEN.load(Ordering::SeqCst) < 256 {
-            let c = inb(PORT + 0);
-            BUFFER[BUFFER_LEN.load(Ordering::SeqCst)] = c;
-            if c == b'\n' {
-                break;
+    /// Move received bytes into the receive queue and queued bytes into the FIFO.
+    fn handle_interrupt(&self) { unsafe {
+        // reading the ID register acknowledges a THRE interrupt
+        while inb(self.base + INTERRUPT_ID) & IIR_NO_INTERRUPT == 0 {
+            while self.received() {
+                // a full buffer means nobody is reading, dropping is all we can do
+                let _ = self.buffers().rx.push(inb(self.base + DATA));
+            }
+
+            if self.transmit_empty() {
+                self.fill_fifo();
             }
-            BUFFER_LEN.fetch_add(1, Ordering::SeqCst);
         }
     }}
+}
 
-    pub fn write(&self, data: u8) { unsafe {
-        while !self.serial_sent() {};
-        outb(PORT + 0, data);
-    }}
+lazy_static!{
+    static ref PORTS: [Mutex<Option<SerialPort>>; 4] =
+        ComPort::ALL.map(|com| Mutex::new(SerialPort::new(com, SerialConfig::default()).ok()));
 }
 
-pub fn _serial_write(args: fmt::Arguments) {
+// where serial_print! goes and serial_read comes from
+static LOG_PORT: AtomicU8 = AtomicU8::new(ComPort::Com1 as u8);
+static CONSOLE_PORT: AtomicU8 = AtomicU8::new(ComPort::Com1 as u8);
+
+fn port(com: ComPort) -> &'static Mutex<Option<SerialPort>> {
+    &PORTS[com.index()]
+}
+
+fn com_port(index: u8) -> ComPort {
+    ComPort::ALL[index as usize % 4]
+}
+
+/// Release every port lock whoever holds it, for the panic path.
+///
+/// # Safety
+/// Nothing else may be using the ports, i.e. the kernel is going down.
+pub unsafe fn force_unlock() {
+    for port in PORTS.iter() {
+        if port.is_locked() {
+            port.force_unlock();
+        }
+    }
+}
+
+/// Run `func` on `com` with interrupts off, if the port exists.
+pub fn with_port<R>(com: ComPort, func: impl FnOnce(&mut SerialPort) -> R) -> Option<R> {
+    x86_64::instructions::interrupts::without_interrupts(|| port(com).lock().as_mut().map(func))
+}
+
+pub fn is_present(com: ComPort) -> bool {
+    with_port(com, |_| ()).is_some()
+}
+
+/// Set up `com` again with a different baud rate or framing.
+/// On failure the port keeps its old settings, or is dropped if it no
+/// longer takes them either.
+pub fn configure(com: ComPort, config: SerialConfig) -> Result<(), SerialError> {
+    // a config that can't work doesn't get to touch the port
+    config.divisor()?;
+
+    let was_interrupt_driven = BUFFERS[com.index()].interrupt_driven.swap(false, Ordering::SeqCst);
+
+    let (result, interrupt_driven) = x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut slot = port(com).lock();
+        let old = slot.as_ref().map(|old| {
+            old.flush();
+            old.config()
+        });
+
+        let result = match SerialPort::new(com, config) {
+            Ok(serial) => {
+                *slot = Some(serial);
+                Ok(())
+            }
+            Err(err) => {
+                // the probe may have left the UART in loopback with the new
+                // divisor, set it up the old way again
+                *slot = old.and_then(|old| SerialPort::new(com, old).ok());
+                Err(err)
+            }
+        };
+
+        match slot.as_mut() {
+            Some(serial) if was_interrupt_driven => {
+                serial.enable_interrupts();
+                (result, true)
+            }
+            _ => (result, false),
+        }
+    });
+
+    if interrupt_driven {
+        interrupts::enable_irq(com.interrupt());
+    }
+    result
+}
+
+/// Send `serial_print!` output to `com`.
+pub fn set_log_port(com: ComPort) -> Result<(), SerialError> {
+    if !is_present(com) {
+        return Err(SerialError::NotPresent(com));
+    }
+    LOG_PORT.store(com as u8, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Read `serial_read` input from `com`.
+pub fn set_console_port(com: ComPort) -> Result<(), SerialError> {
+    if !is_present(com) {
+        return Err(SerialError::NotPresent(com));
+    }
+    CONSOLE_PORT.store(com as u8, Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn log_port() -> ComPort {
+    com_port(LOG_PORT.load(Ordering::Relaxed))
+}
+
+pub fn console_port() -> ComPort {
+    com_port(CONSOLE_PORT.load(Ordering::Relaxed))
+}
+
+/// Switch every port that was found over to interrupts.
+pub fn init() {
+    for com in ComPort::ALL {
+        if with_port(com, SerialPort::enable_interrupts).is_some() {
+            interrupts::enable_irq(com.interrupt());
+        }
+    }
+}
+
+/// Called from the IRQ3 and IRQ4 handlers, every port on the line is checked.
+pub fn handle_interrupt(index: InterruptIndex) {
+    for com in ComPort::ALL.into_iter().filter(|com| com.interrupt() as u8 == index as u8) {
+        // the locks are never held with interrupts enabled
+        if let Some(serial) = port(com).lock().as_ref() {
+            serial.handle_interrupt();
+        }
+    }
+}
+
+/// Format `args` to `com`.
+pub fn write_to(com: ComPort, args: fmt::Arguments) -> Result<(), SerialError> {
     use core::fmt::Write;
 
-    interrupts::without(|| {
-        SERIAL_WRITER.lock().write_fmt(args).unwrap();
-    })    
+    // with interrupts off nothing would drain the queue, so write it out now
+    let interrupts_enabled = interrupts::are_enabled();
+
+    with_port(com, |serial| {
+        serial.write_fmt(args).unwrap();
+
+        if interrupts_enabled {
+            serial.start_transmit();
+        } else {
+            serial.flush();
+        }
+    })
+    .ok_or(SerialError::NotPresent(com))
+}
+
+pub fn _serial_write(args: fmt::Arguments) {
+    // there is nowhere to report a missing log port to
+    let _ = write_to(log_port(), args);
 }
 
 #[macro_use]
@@ -99,19 +478,48 @@ macro_rules! serial_println {
     ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
 }
 
-pub fn serial_read() -> &'static str {
-    serial_println!("getting value!");
+/// Log output queued that hasn't reached the UART yet.
+pub fn serial_pending() -> usize {
+    with_port(log_port(), |serial| serial.pending()).unwrap_or(0)
+}
 
-    interrupts::without(|| {
-        SERIAL_WRITER.lock().read_str_to_buffer();
-    });
+/// Take a byte received on the console port if there is one.
+pub fn serial_try_read_byte() -> Option<u8> {
+    with_port(console_port(), SerialPort::try_read_byte).flatten()
+}
 
-    let i = BUFFER_LEN.load(Ordering::SeqCst);
+/// Wait for the next byte on the console port.
+pub fn serial_read_byte() -> u8 {
+    loop {
+        if let Some(byte) = serial_try_read_byte() {
+            return byte;
+        }
 
-    return unsafe {
-        if i != 0 {
-            core::str::from_utf8(&BUFFER[..i - 1]).unwrap()
-        } else { "" }
+        if interrupts::are_enabled() {
+            x86_64::instructions::hlt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Wait for a line of input on the console port. Ends at `\n` or `\r`,
+/// which isn't included, and the `\n` of a `\r\n` pair is skipped.
+pub fn serial_read() -> String {
+    let mut line = String::new();
+    let mut utf8 = Utf8Decoder::new();
+
+    loop {
+        match serial_read_byte() {
+            b'\r' => {
+                if BUFFERS[console_port().index()].rx.peek() == Some(b'\n') {
+                    serial_try_read_byte();
+                }
+                return line;
+            }
+            b'\n' => return line,
+            byte => line.extend(utf8.push(byte)),
+        }
     }
 }
 
@@ -119,4 +527,3 @@ pub fn serial_read() -> &'static str {
 macro_rules! serial_print {
     ($($arg:tt)*) => ($crate::_serial_write(format_args!($($arg)*)));
 }
-
@@ -0,0 +1,206 @@
+//! Kernel logger
+//!
+//! The backend behind the `log` crate macros. Every record is stamped with
+//! the uptime, its level and its target, then handed to each enabled sink:
+//! the log console on the framebuffer, the serial log port and the kernel
+//! log buffer in [`dmesg`](super::dmesg).
+//!
+//! Targets are module paths without the crate name, e.g. `sys::kernel::time`.
+//! Filters match them by prefix, the longest matching filter wins and
+//! anything unmatched uses the default level.
+
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+use bitflags::bitflags;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use spin::Mutex;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::sys::kernel::drivers::framebuffer::textwriter::{self, Console};
+use crate::sys::kernel::dmesg::{self, Entry, Message};
+use crate::sys::kernel::time;
+
+/// Level used for targets without a filter of their own.
+pub const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// How many per module filters can be set at once.
+pub const MAX_FILTERS: usize = 16;
+
+/// What module paths start with, left off targets.
+const CRATE_PREFIX: &str = concat!(env!("CARGO_CRATE_NAME"), "::");
+
+bitflags! {
+    /// Where records go.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Sinks: u8 {
+        /// The log console, see [`Console::LOG`].
+        const FRAMEBUFFER = 1 << 0;
+        /// The serial log port.
+        const SERIAL = 1 << 1;
+        /// The kernel log buffer, see [`dmesg`](super::dmesg).
+        const RING = 1 << 2;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoggerError {
+    /// All [`MAX_FILTERS`] filters are in use.
+    TooManyFilters,
+}
+
+struct KernelLogger;
+
+static LOGGER: KernelLogger = KernelLogger;
+
+static SINKS: AtomicU8 = AtomicU8::new(Sinks::all().bits());
+static DEFAULT: AtomicUsize = AtomicUsize::new(DEFAULT_LEVEL as usize);
+static FILTERS: Mutex<[Option<(&'static str, LevelFilter)>; MAX_FILTERS]> = Mutex::new([None; MAX_FILTERS]);
+/// Install the logger. Call before anything logs, records from before this
+/// are dropped.
+pub fn init() {
+    // only fails if a logger is set already, which would be this one
+    let _ = log::set_logger(&LOGGER);
+    update_max_level();
+}
+
+/// Drop the crate name from a module path.
+fn short(target: &str) -> &str {
+    target.strip_prefix(CRATE_PREFIX).unwrap_or(target)
+}
+
+fn level_from(value: usize) -> LevelFilter {
+    LevelFilter::iter().nth(value).unwrap_or(DEFAULT_LEVEL)
+}
+
+/// The most verbose level any filter lets through, so `log` can skip the
+/// rest without asking us.
+fn update_max_level() {
+    let filters = without_interrupts(|| *FILTERS.lock());
+    let max = filters.iter().flatten().map(|&(_, level)| level).fold(default_level(), Ord::max);
+    log::set_max_level(max);
+}
+
+pub fn default_level() -> LevelFilter {
+    level_from(DEFAULT.load(Ordering::Relaxed))
+}
+
+/// Level for targets no filter matches.
+pub fn set_default_level(level: LevelFilter) {
+    DEFAULT.store(level as usize, Ordering::Relaxed);
+    update_max_level();
+}
+
+/// Log `target` and everything under it at `level`, e.g.
+/// `set_filter("sys::kernel::time", LevelFilter::Warn)` to quiet the timer.
+pub fn set_filter(target: &'static str, level: LevelFilter) -> Result<(), LoggerError> {
+    without_interrupts(|| {
+        let mut filters = FILTERS.lock();
+
+        let slot = match filters.iter().position(|filter| filter.is_some_and(|(prefix, _)| prefix == target)) {
+            Some(index) => index,
+            None => filters.iter().position(Option::is_none).ok_or(LoggerError::TooManyFilters)?,
+        };
+        filters[slot] = Some((target, level));
+        Ok(())
+    })?;
+
+    update_max_level();
+    Ok(())
+}
+
+/// Drop the filter for exactly `target`, it falls back to the next
+/// broader one.
+pub fn clear_filter(target: &str) {
+    without_interrupts(|| {
+        for filter in FILTERS.lock().iter_mut() {
+            if filter.is_some_and(|(prefix, _)| prefix == target) {
+                *filter = None;
+            }
+        }
+    });
+    update_max_level();
+}
+
+/// The level records from `target` need to reach to be logged.
+pub fn level_for(target: &str) -> LevelFilter {
+    let target = short(target);
+    let filters = without_interrupts(|| *FILTERS.lock());
+
+    filters
+        .iter()
+        .flatten()
+        .filter(|(prefix, _)| {
+            // a prefix only matches whole path segments
+            target.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map_or(default_level(), |&(_, level)| level)
+}
+
+pub fn sinks() -> Sinks {
+    Sinks::from_bits_truncate(SINKS.load(Ordering::Relaxed))
+}
+
+pub fn set_sinks(sinks: Sinks) {
+    SINKS.store(sinks.bits(), Ordering::Relaxed);
+}
+
+fn colour(level: Level) -> u32 {
+    match level {
+        Level::Error => 0xFF5050,
+        Level::Warn => 0xFFB040,
+        Level::Info => 0xFFFF00,
+        Level::Debug => 0xA0A0A0,
+        Level::Trace => 0x707070,
+    }
+}
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut message = Message::new();
+        let _ = write!(message, "{}", record.args());
+
+        let entry = Entry {
+            sequence: 0,
+            timestamp: time::uptime(),
+            level: record.level(),
+            target: short(record.target()),
+            message: message.as_str(),
+        };
+
+        let sinks = sinks();
+        if sinks.contains(Sinks::RING) {
+            dmesg::push(&entry);
+        }
+        if sinks.contains(Sinks::SERIAL) {
+            crate::_serial_write(format_args!("{}\n", entry));
+        }
+        if sinks.contains(Sinks::FRAMEBUFFER) {
+            textwriter::write_coloured(Console::LOG, format_args!("{}\n", entry), colour(entry.level), 0x000000);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Log a line at info level, the same as `log::info!`.
+#[macro_export]
+macro_rules! println_log {
+	() => ($crate::log::info!(""));
+	($($arg:tt)*) => ($crate::log::info!($($arg)*));
+}
+
+/// Records are always whole lines, so this is [`println_log!`].
+#[macro_export]
+macro_rules! print_log {
+	($($arg:tt)*) => ($crate::println_log!($($arg)*));
+}
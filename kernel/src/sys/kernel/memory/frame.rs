@@ -0,0 +1,288 @@
+//! Physical frame allocator
+//!
+//! Frames are tracked in a bitmap built from the Limine memory map, one bit per
+//! 4 KiB frame (set = free, so the bitmap can live in .bss). Only `USABLE`
+//! regions are ever handed out, the bootloader reclaimable regions still hold
+//! the page tables we are running on.
+
+use limine::{memory_map::EntryType, request::MemoryMapRequest};
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts::without_interrupts,
+    structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB},
+    PhysAddr,
+};
+
+use crate::println_log;
+
+static MEMORY_MAP_REQUEST: MemoryMapRequest = MemoryMapRequest::new();
+
+pub const FRAME_SIZE: u64 = 4096;
+
+/// Highest physical address we keep track of. Anything above is ignored.
+pub const MAX_PHYS_MEMORY: u64 = 16 * 1024 * 1024 * 1024;
+
+/// Frames below 1 MiB are never handed out, real mode trampolines and
+/// firmware leftovers live there.
+const LOW_MEMORY_LIMIT: u64 = 0x100000;
+
+const MAX_FRAMES: usize = (MAX_PHYS_MEMORY / FRAME_SIZE) as usize;
+const BITMAP_WORDS: usize = MAX_FRAMES / 64;
+
+pub static FRAME_ALLOCATOR: Mutex<BitmapFrameAllocator> = Mutex::new(BitmapFrameAllocator::new());
+
+/// Usage statistics, all counts are in frames.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameStats {
+    pub total: usize,
+    pub used: usize,
+    pub free: usize,
+}
+
+impl FrameStats {
+    pub fn total_bytes(&self) -> u64 {
+        self.total as u64 * FRAME_SIZE
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.used as u64 * FRAME_SIZE
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.free as u64 * FRAME_SIZE
+    }
+}
+
+pub struct BitmapFrameAllocator {
+    bitmap: [u64; BITMAP_WORDS],
+    // one past the highest usable frame, so scans can stop early
+    frame_limit: usize,
+    // lowest frame that might be free
+    next_free: usize,
+    total: usize,
+    free: usize,
+}
+
+impl BitmapFrameAllocator {
+    const fn new() -> Self {
+        Self {
+            bitmap: [0; BITMAP_WORDS],
+            frame_limit: 0,
+            next_free: 0,
+            total: 0,
+            free: 0,
+        }
+    }
+
+    /// Mark every usable region in the Limine memory map as free.
+    fn init(&mut self) {
+        let Some(response) = MEMORY_MAP_REQUEST.get_response() else {
+            panic!("Memory map request failed");
+        };
+
+        for entry in response.entries() {
+            if entry.entry_type != EntryType::USABLE {
+                continue;
+            }
+
+            let start = align_up(entry.base.max(LOW_MEMORY_LIMIT), FRAME_SIZE);
+            let end = align_down((entry.base + entry.length).min(MAX_PHYS_MEMORY), FRAME_SIZE);
+
+            if start >= end {
+                continue;
+            }
+
+            let first = (start / FRAME_SIZE) as usize;
+            let last = (end / FRAME_SIZE) as usize;
+
+            for frame in first..last {
+                self.mark_free(frame);
+            }
+
+            self.total += last - first;
+            self.free += last - first;
+            self.frame_limit = self.frame_limit.max(last);
+        }
+
+        self.next_free = (LOW_MEMORY_LIMIT / FRAME_SIZE) as usize;
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / 64] & (1 << (frame % 64)) == 0
+    }
+
+    fn mark_used(&mut self, frame: usize) {
+        self.bitmap[frame / 64] &= !(1 << (frame % 64));
+    }
+
+    fn mark_free(&mut self, frame: usize) {
+        self.bitmap[frame / 64] |= 1 << (frame % 64);
+    }
+
+    /// Allocate a single frame.
+    pub fn allocate(&mut self) -> Option<PhysFrame> {
+        let mut index = self.next_free;
+
+        while index < self.frame_limit {
+            let word = self.bitmap[index / 64];
+
+            // skip whole words that are fully used
+            if word == 0 {
+                index = (index / 64 + 1) * 64;
+                continue;
+            }
+
+            if !self.is_used(index) {
+                self.mark_used(index);
+                self.free -= 1;
+                self.next_free = index + 1;
+                return Some(frame_at(index));
+            }
+
+            index += 1;
+        }
+
+        None
+    }
+
+    /// Allocate `count` physically contiguous frames whose first frame is
+    /// aligned to `align` frames. Returns the first frame of the run.
+    pub fn allocate_contiguous(&mut self, count: usize, align: usize) -> Option<PhysFrame> {
+        if count == 0 || self.free < count {
+            return None;
+        }
+
+        let align = align.max(1);
+        let mut start = align_up(self.next_free as u64, align as u64) as usize;
+
+        while start + count <= self.frame_limit {
+            match (start..start + count).rev().find(|&frame| self.is_used(frame)) {
+                // restart right after the used frame we hit
+                Some(used) => start = align_up(used as u64 + 1, align as u64) as usize,
+                None => {
+                    for frame in start..start + count {
+                        self.mark_used(frame);
+                    }
+                    self.free -= count;
+                    if start == self.next_free {
+                        self.next_free = start + count;
+                    }
+                    return Some(frame_at(start));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Return a single frame to the allocator.
+    ///
+    /// # Safety
+    /// The frame must have come from this allocator and must no longer be in use.
+    pub unsafe fn free(&mut self, frame: PhysFrame) {
+        self.free_contiguous(frame, 1);
+    }
+
+    /// Return a run of `count` frames starting at `frame`.
+    ///
+    /// # Safety
+    /// The frames must have come from this allocator and must no longer be in use.
+    pub unsafe fn free_contiguous(&mut self, frame: PhysFrame, count: usize) {
+        let first = (frame.start_address().as_u64() / FRAME_SIZE) as usize;
+
+        for index in first..first + count {
+            if index >= self.frame_limit || !self.is_used(index) {
+                panic!("double free of physical frame {:#x}", index as u64 * FRAME_SIZE);
+            }
+            self.mark_free(index);
+        }
+
+        self.free += count;
+        self.next_free = self.next_free.min(first);
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total: self.total,
+            used: self.total - self.free,
+            free: self.free,
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.allocate()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        self.free(frame);
+    }
+}
+
+/// Handle to the global allocator that can be passed to the `x86_64` paging
+/// APIs without holding the lock for the whole mapping operation.
+pub struct GlobalFrameAllocator;
+
+unsafe impl FrameAllocator<Size4KiB> for GlobalFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        allocate_frame()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for GlobalFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        free_frame(frame);
+    }
+}
+
+fn frame_at(index: usize) -> PhysFrame {
+    PhysFrame::containing_address(PhysAddr::new(index as u64 * FRAME_SIZE))
+}
+
+const fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align) * align
+}
+
+const fn align_down(value: u64, align: u64) -> u64 {
+    value / align * align
+}
+
+pub fn init() {
+    let stats = without_interrupts(|| {
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        allocator.init();
+        allocator.stats()
+    });
+
+    println_log!(
+        "Frame allocator ready: {} MiB usable...",
+        stats.total_bytes() / (1024 * 1024)
+    );
+}
+
+pub fn allocate_frame() -> Option<PhysFrame> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().allocate())
+}
+
+pub fn allocate_contiguous(count: usize, align: usize) -> Option<PhysFrame> {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().allocate_contiguous(count, align))
+}
+
+/// # Safety
+/// See [`BitmapFrameAllocator::free`].
+pub unsafe fn free_frame(frame: PhysFrame) {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().free(frame))
+}
+
+/// # Safety
+/// See [`BitmapFrameAllocator::free_contiguous`].
+pub unsafe fn free_contiguous(frame: PhysFrame, count: usize) {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().free_contiguous(frame, count))
+}
+
+pub fn stats() -> FrameStats {
+    without_interrupts(|| FRAME_ALLOCATOR.lock().stats())
+}
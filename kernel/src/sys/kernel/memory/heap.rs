@@ -0,0 +1,226 @@
+//! Kernel heap
+//!
+//! The heap lives in a fixed virtual window and starts out small. Whenever an
+//! allocation can't be satisfied, more frames are mapped at the top of the
+//! heap and handed to the allocator before trying again.
+//!
+//! The linked list heap only deals in whole pages, small objects are served by
+//! the slab allocator on top of it.
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use linked_list_allocator::Heap;
+use spin::Mutex;
+use x86_64::{
+    instructions::interrupts::without_interrupts,
+    structures::paging::{Page, PageTableFlags, Size4KiB},
+    VirtAddr,
+};
+
+use crate::sys::kernel::cpu::x86_64::paging;
+use crate::{println_log, serial_println};
+
+use super::frame;
+use super::slab::{ClassStats, PageSource, SlabAllocator, SIZE_CLASSES};
+
+pub const HEAP_START: u64 = 0xffff_c000_0000_0000;
+/// The heap never grows past this (1 GiB).
+pub const HEAP_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+pub const HEAP_INITIAL_SIZE: u64 = 1024 * 1024;
+/// Smallest amount the heap grows by, to avoid mapping a page per allocation.
+const HEAP_GROW_STEP: u64 = 256 * 1024;
+
+#[global_allocator]
+static ALLOCATOR: KernelHeap = KernelHeap::new();
+
+static READY: AtomicBool = AtomicBool::new(false);
+
+pub struct KernelHeap {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    pages: PageHeap,
+    slab: SlabAllocator,
+}
+
+/// Page level backing store for the slab allocator.
+struct PageHeap(Heap);
+
+/// Ways growing the heap can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapError {
+    /// The virtual heap window is used up.
+    OutOfAddressSpace,
+    /// The frame allocator has nothing left.
+    OutOfFrames,
+    /// A heap page was already mapped, something else is using the window.
+    MapFailed,
+}
+
+/// Snapshot of the heap, see [`stats`].
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Bytes mapped for the heap.
+    pub size: usize,
+    /// Bytes of the page heap in use, including slabs.
+    pub used: usize,
+    pub classes: [ClassStats; SIZE_CLASSES.len()],
+    pub large: ClassStats,
+}
+
+impl KernelHeap {
+    const fn new() -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                pages: PageHeap(Heap::empty()),
+                slab: SlabAllocator::new(),
+            }),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        without_interrupts(|| {
+            let inner = &mut *self.inner.lock();
+            inner.slab.alloc(layout, &mut inner.pages)
+        })
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        without_interrupts(|| {
+            let inner = &mut *self.inner.lock();
+            inner.slab.dealloc(ptr, layout, &mut inner.pages);
+        })
+    }
+}
+
+impl PageSource for PageHeap {
+    fn alloc_pages(&mut self, layout: Layout) -> *mut u8 {
+        if let Ok(ptr) = self.0.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+
+        // leave room for alignment padding and the allocator's own bookkeeping
+        let needed = (layout.size() + layout.align()) as u64;
+        if grow(&mut self.0, needed).is_err() {
+            return ptr::null_mut();
+        }
+
+        self.0
+            .allocate_first_fit(layout)
+            .map_or(ptr::null_mut(), |ptr| ptr.as_ptr())
+    }
+
+    unsafe fn free_pages(&mut self, ptr: *mut u8, layout: Layout) {
+        self.0.deallocate(NonNull::new_unchecked(ptr), layout);
+    }
+}
+
+/// Map at least `bytes` more memory at the top of the heap.
+fn grow(heap: &mut Heap, bytes: u64) -> Result<(), HeapError> {
+    let bytes = bytes.max(HEAP_GROW_STEP).next_multiple_of(frame::FRAME_SIZE);
+    let top = heap.top() as u64;
+
+    if top + bytes > HEAP_START + HEAP_MAX_SIZE {
+        return Err(HeapError::OutOfAddressSpace);
+    }
+
+    map_range(top, bytes)?;
+
+    unsafe { heap.extend(bytes as usize) };
+    Ok(())
+}
+
+fn map_range(start: u64, bytes: u64) -> Result<(), HeapError> {
+    let first = Page::<Size4KiB>::containing_address(VirtAddr::new(start));
+    let last = Page::<Size4KiB>::containing_address(VirtAddr::new(start + bytes - 1));
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    for page in Page::range_inclusive(first, last) {
+        let frame = frame::allocate_frame().ok_or(HeapError::OutOfFrames)?;
+        unsafe {
+            paging::map(page, frame, flags).map_err(|_| HeapError::MapFailed)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Bytes currently mapped for the heap.
+pub fn size() -> usize {
+    without_interrupts(|| ALLOCATOR.inner.lock().pages.0.size())
+}
+
+/// Bytes of the page heap currently in use, including slabs.
+pub fn used() -> usize {
+    without_interrupts(|| ALLOCATOR.inner.lock().pages.0.used())
+}
+
+pub fn stats() -> HeapStats {
+    without_interrupts(|| {
+        let inner = ALLOCATOR.inner.lock();
+        HeapStats {
+            size: inner.pages.0.size(),
+            used: inner.pages.0.used(),
+            classes: inner.slab.class_stats(),
+            large: inner.slab.large_stats(),
+        }
+    })
+}
+
+/// Print per size class statistics to the kernel log.
+pub fn heap_stats() {
+    let stats = stats();
+
+    println_log!("heap: {:#x} bytes mapped, {:#x} used", stats.size, stats.used);
+    println_log!("{:>8} {:>8} {:>10} {:>10} {:>6}", "class", "live", "bytes", "peak", "slabs");
+
+    for class in stats.classes.iter() {
+        println_log!(
+            "{:>8} {:>8} {:>10} {:>10} {:>6}",
+            class.size, class.live, class.bytes, class.high_water, class.slabs
+        );
+    }
+
+    println_log!(
+        "{:>8} {:>8} {:>10} {:>10} {:>6}",
+        "large", stats.large.live, stats.large.bytes, stats.large.high_water, "-"
+    );
+}
+
+pub fn init() {
+    map_range(HEAP_START, HEAP_INITIAL_SIZE).expect("failed to map the kernel heap");
+
+    without_interrupts(|| unsafe {
+        ALLOCATOR
+            .inner
+            .lock()
+            .pages
+            .0
+            .init(HEAP_START as *mut u8, HEAP_INITIAL_SIZE as usize);
+    });
+    READY.store(true, Ordering::Release);
+
+    println_log!("Heap ready at {:#x}...", HEAP_START);
+}
+
+/// Whether [`init`] has run and allocations can succeed.
+pub fn is_ready() -> bool {
+    READY.load(Ordering::Acquire)
+}
+
+#[alloc_error_handler]
+fn alloc_error_handler(layout: Layout) -> ! {
+    serial_println!(
+        "ALLOCATION ERROR: could not allocate {} bytes (align {}), heap size {:#x}, used {:#x}",
+        layout.size(),
+        layout.align(),
+        size(),
+        used()
+    );
+    panic!("allocation error: {:?}", layout)
+}
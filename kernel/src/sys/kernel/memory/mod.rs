@@ -0,0 +1,8 @@
+pub mod frame;
+pub mod heap;
+pub mod slab;
+
+pub fn init() {
+    frame::init();
+    heap::init();
+}
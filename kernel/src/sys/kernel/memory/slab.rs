@@ -0,0 +1,199 @@
+//! Slab allocator
+//!
+//! Small allocations are served from power-of-two size classes. Each class
+//! carves page sized slabs into equal objects and keeps the free ones on an
+//! intrusive list, so frees never fragment the heap. Anything bigger than the
+//! largest class goes straight to the page level backing allocator.
+
+use core::alloc::Layout;
+use core::ptr::{self, NonNull};
+
+pub const SIZE_CLASSES: [usize; 9] = [8, 16, 32, 64, 128, 256, 512, 1024, 2048];
+pub const SLAB_SIZE: usize = 4096;
+
+const MIN_CLASS_SHIFT: u32 = SIZE_CLASSES[0].trailing_zeros();
+
+/// Where slabs and large allocations get their memory from.
+pub trait PageSource {
+    /// Allocate memory for `layout`, which is always page aligned and a
+    /// multiple of [`SLAB_SIZE`]. Returns null on failure.
+    fn alloc_pages(&mut self, layout: Layout) -> *mut u8;
+
+    /// # Safety
+    /// `ptr` must have come from `alloc_pages` with the same layout.
+    unsafe fn free_pages(&mut self, ptr: *mut u8, layout: Layout);
+}
+
+/// Statistics of a single size class (or of the large allocations).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClassStats {
+    /// Object size of the class, 0 for large allocations.
+    pub size: usize,
+    /// Objects currently allocated.
+    pub live: usize,
+    /// Bytes currently allocated.
+    pub bytes: usize,
+    /// Most bytes ever allocated at once.
+    pub high_water: usize,
+    /// Slabs backing the class.
+    pub slabs: usize,
+}
+
+impl ClassStats {
+    const fn new(size: usize) -> Self {
+        Self {
+            size,
+            live: 0,
+            bytes: 0,
+            high_water: 0,
+            slabs: 0,
+        }
+    }
+
+    fn record_alloc(&mut self, bytes: usize) {
+        self.live += 1;
+        self.bytes += bytes;
+        self.high_water = self.high_water.max(self.bytes);
+    }
+
+    fn record_free(&mut self, bytes: usize) {
+        self.live -= 1;
+        self.bytes -= bytes;
+    }
+}
+
+struct FreeObject {
+    next: Option<NonNull<FreeObject>>,
+}
+
+struct SizeClass {
+    free: Option<NonNull<FreeObject>>,
+    stats: ClassStats,
+}
+
+impl SizeClass {
+    const fn new(size: usize) -> Self {
+        Self {
+            free: None,
+            stats: ClassStats::new(size),
+        }
+    }
+
+    /// Carve a fresh slab into objects and push them on the free list.
+    fn refill(&mut self, pages: &mut impl PageSource) -> bool {
+        let slab = pages.alloc_pages(slab_layout());
+        if slab.is_null() {
+            return false;
+        }
+
+        let size = self.stats.size;
+        for offset in (0..SLAB_SIZE).step_by(size).rev() {
+            let object = unsafe { slab.add(offset) } as *mut FreeObject;
+            unsafe { object.write(FreeObject { next: self.free }) };
+            self.free = NonNull::new(object);
+        }
+
+        self.stats.slabs += 1;
+        true
+    }
+
+    fn alloc(&mut self, pages: &mut impl PageSource) -> *mut u8 {
+        if self.free.is_none() && !self.refill(pages) {
+            return ptr::null_mut();
+        }
+
+        let object = self.free.take().unwrap();
+        self.free = unsafe { object.as_ref().next };
+        self.stats.record_alloc(self.stats.size);
+
+        object.as_ptr() as *mut u8
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8) {
+        let object = ptr as *mut FreeObject;
+        object.write(FreeObject { next: self.free });
+        self.free = NonNull::new(object);
+        self.stats.record_free(self.stats.size);
+    }
+}
+
+pub struct SlabAllocator {
+    classes: [SizeClass; SIZE_CLASSES.len()],
+    large: ClassStats,
+}
+
+// the free lists only ever point into memory owned by the allocator
+unsafe impl Send for SlabAllocator {}
+
+impl SlabAllocator {
+    pub(crate) const fn new() -> Self {
+        Self {
+            classes: [
+                SizeClass::new(SIZE_CLASSES[0]),
+                SizeClass::new(SIZE_CLASSES[1]),
+                SizeClass::new(SIZE_CLASSES[2]),
+                SizeClass::new(SIZE_CLASSES[3]),
+                SizeClass::new(SIZE_CLASSES[4]),
+                SizeClass::new(SIZE_CLASSES[5]),
+                SizeClass::new(SIZE_CLASSES[6]),
+                SizeClass::new(SIZE_CLASSES[7]),
+                SizeClass::new(SIZE_CLASSES[8]),
+            ],
+            large: ClassStats::new(0),
+        }
+    }
+
+    /// Size class serving `layout`, or `None` if it is a large allocation.
+    fn class_index(layout: &Layout) -> Option<usize> {
+        let size = layout.size().max(layout.align()).max(SIZE_CLASSES[0]);
+        let index = (size.next_power_of_two().trailing_zeros() - MIN_CLASS_SHIFT) as usize;
+        (index < SIZE_CLASSES.len()).then_some(index)
+    }
+
+    pub fn alloc(&mut self, layout: Layout, pages: &mut impl PageSource) -> *mut u8 {
+        match Self::class_index(&layout) {
+            Some(index) => self.classes[index].alloc(pages),
+            None => {
+                let ptr = pages.alloc_pages(large_layout(&layout));
+                if !ptr.is_null() {
+                    self.large.record_alloc(large_layout(&layout).size());
+                }
+                ptr
+            }
+        }
+    }
+
+    /// # Safety
+    /// `ptr` must have been returned by [`SlabAllocator::alloc`] with the same
+    /// layout and page source.
+    pub unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout, pages: &mut impl PageSource) {
+        match Self::class_index(&layout) {
+            Some(index) => self.classes[index].dealloc(ptr),
+            None => {
+                pages.free_pages(ptr, large_layout(&layout));
+                self.large.record_free(large_layout(&layout).size());
+            }
+        }
+    }
+
+    pub fn class_stats(&self) -> [ClassStats; SIZE_CLASSES.len()] {
+        self.classes.each_ref().map(|class| class.stats)
+    }
+
+    pub fn large_stats(&self) -> ClassStats {
+        self.large
+    }
+}
+
+fn slab_layout() -> Layout {
+    Layout::from_size_align(SLAB_SIZE, SLAB_SIZE).unwrap()
+}
+
+/// Large allocations are rounded up to whole pages.
+fn large_layout(layout: &Layout) -> Layout {
+    Layout::from_size_align(
+        layout.size().next_multiple_of(SLAB_SIZE),
+        layout.align().max(SLAB_SIZE),
+    )
+    .unwrap()
+}
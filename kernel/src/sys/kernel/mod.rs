@@ -1,2 +1,11 @@
 pub mod drivers;
-pub mod cpu;
\ No newline at end of file
+pub mod cpu;
+pub mod memory;
+pub mod acpi;
+pub mod power;
+pub mod time;
+pub mod logger;
+pub mod dmesg;
+pub mod panic;
+pub mod symbols;
+pub mod cmdline;
@@ -0,0 +1,186 @@
+//! Panic screen
+//!
+//! A panic can happen anywhere, including with the console or a serial port
+//! locked, so this path takes no lock it hasn't forced open first and never
+//! touches the heap. It paints the message, location, registers, backtrace
+//! and the end of the kernel log straight onto the framebuffer with the
+//! console font, and writes the same report to serial. CPU exceptions the
+//! kernel can't survive end up here too, with the state at the fault.
+
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::serial_println;
+use crate::sys::kernel::cpu::{backtrace::{self, Frames}, exceptions::Fault, registers::Registers};
+use crate::sys::kernel::dmesg;
+use crate::sys::kernel::drivers::framebuffer::{damage::Rect, font, psf::Font, render::{self, FramebufferWriter, FRAMEBUFFER_WRITER}};
+use crate::sys::kernel::drivers::serial::serial;
+
+/// Log records shown at the bottom of the report.
+pub const LOG_LINES: usize = 16;
+
+const FOREGROUND: u32 = 0xFFFFFF;
+const BACKGROUND: u32 = 0x700000;
+/// Columns and lines left blank around the edge.
+const MARGIN: u32 = 1;
+
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+/// Show the panic screen and stop. Called by the panic handler.
+pub fn panic(info: &PanicInfo) -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    if !enter() {
+        serial_println!("\nPANIC while panicking: {}", info);
+        crate::hcf();
+    }
+
+    let registers = Registers::capture();
+    show(|out| {
+        writeln!(out, "{}", info.message())?;
+        if let Some(location) = info.location() {
+            writeln!(out, "at {}", location)?;
+        }
+        Ok(())
+    }, &registers, None, Frames::current())
+}
+
+/// Show the panic screen for a CPU exception and stop, with the registers
+/// and call stack where it happened rather than here.
+pub fn fault(fault: &Fault) -> ! {
+    x86_64::instructions::interrupts::disable();
+
+    if !enter() {
+        serial_println!("\n{} while panicking", fault.exception);
+        crate::hcf();
+    }
+
+    let registers = &fault.registers;
+    // the saved rbp is the faulting function's frame
+    let frames = unsafe { Frames::from_rbp(registers.rbp) };
+    show(|out| write!(out, "{}", fault), registers, Some(registers.rip), frames)
+}
+
+// false when already panicking, serial is all that is left to try then
+fn enter() -> bool {
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        unsafe { serial::force_unlock() };
+        return false;
+    }
+
+    // whoever held these isn't coming back
+    unsafe {
+        serial::force_unlock();
+        render::force_unlock();
+        dmesg::force_unlock();
+    }
+    true
+}
+
+fn show(
+    heading: impl FnOnce(&mut Screen) -> fmt::Result,
+    registers: &Registers,
+    rip: Option<u64>,
+    frames: Frames,
+) -> ! {
+    let mut primary = FRAMEBUFFER_WRITER.lock();
+    let mut screen = Screen::new(primary.as_mut());
+    let _ = report(&mut screen, heading, registers, rip, frames);
+    drop(primary);
+
+    // shows it, and puts it on the other displays when mirroring
+    render::flush();
+    crate::hcf()
+}
+
+fn report<'w>(
+    out: &mut Screen<'w>,
+    heading: impl FnOnce(&mut Screen<'w>) -> fmt::Result,
+    registers: &Registers,
+    rip: Option<u64>,
+    frames: Frames,
+) -> fmt::Result {
+    writeln!(out, "KERNEL PANIC")?;
+    writeln!(out)?;
+    heading(out)?;
+
+    writeln!(out)?;
+    writeln!(out, "Registers")?;
+    write!(out, "{}", registers)?;
+
+    writeln!(out)?;
+    writeln!(out, "Backtrace")?;
+    backtrace::write(out, rip, frames)?;
+
+    writeln!(out)?;
+    writeln!(out, "Log")?;
+    dmesg::tail(LOG_LINES, |entry| {
+        let _ = writeln!(out, "{}", entry);
+    });
+    Ok(())
+}
+
+/// Text straight onto the framebuffer, and everything also to serial.
+/// Whatever doesn't fit on screen only goes to serial.
+struct Screen<'w> {
+    writer: Option<&'w mut FramebufferWriter<'static>>,
+    font: &'static Font<'static>,
+    col: u32,
+    line: u32,
+    columns: u32,
+    lines: u32,
+}
+
+impl<'w> Screen<'w> {
+    fn new(mut writer: Option<&'w mut FramebufferWriter<'static>>) -> Self {
+        let font = font::font();
+        let (mut columns, mut lines) = (0, 0);
+
+        if let Some(writer) = writer.as_deref_mut() {
+            writer.fill_rect(0, 0, writer.width() as usize, writer.height() as usize, BACKGROUND);
+            columns = (writer.width() / font.width()).saturating_sub(2 * MARGIN);
+            lines = (writer.height() / font.height()).saturating_sub(2 * MARGIN);
+        }
+
+        Self {
+            writer,
+            font,
+            col: 0,
+            line: 0,
+            columns,
+            lines,
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        if c == '\n' {
+            (self.col, self.line) = (0, self.line + 1);
+            return;
+        }
+        if self.col >= self.columns {
+            (self.col, self.line) = (0, self.line + 1);
+        }
+
+        if let Some(writer) = self.writer.as_deref_mut().filter(|_| self.line < self.lines) {
+            let glyph = self.font.glyph(c);
+            let (width, height) = (self.font.width(), self.font.height());
+            let rect = Rect::new(
+                ((self.col + MARGIN) * width) as usize,
+                ((self.line + MARGIN) * height) as usize,
+                width as usize,
+                height as usize,
+            );
+            writer.draw_bitmap(rect, |x, y| glyph.is_set(x as u32, y as u32), FOREGROUND, BACKGROUND);
+        }
+        self.col += 1;
+    }
+}
+
+impl Write for Screen<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        crate::_serial_write(format_args!("{}", s));
+        s.chars().for_each(|c| self.put(c));
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! Shutdown and reboot
+//!
+//! Power off goes through ACPI: the `\_S5` sleep type from the DSDT is
+//! written to the FADT PM1 control blocks. Reboot tries the FADT reset
+//! register, then the 8042 reset line and finally forces a triple fault.
+
+use x86_64::{instructions::interrupts, structures::idt::InterruptDescriptorTable, PhysAddr};
+
+use crate::sys::kernel::acpi::{self, sdt};
+use crate::sys::kernel::cpu::{inb, inw, outb, outw, x86_64::paging};
+use crate::{hcf, println_log, serial_println};
+
+const SLP_EN: u16 = 1 << 13;
+const SLP_TYP_SHIFT: u16 = 10;
+const SCI_EN: u16 = 1 << 0;
+
+const KBC_STATUS: u16 = 0x64;
+const KBC_COMMAND: u16 = 0x64;
+const KBC_INPUT_FULL: u8 = 1 << 1;
+const KBC_PULSE_RESET: u8 = 0xfe;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerError {
+    /// No FADT, so no PM1 control block to write to.
+    NoFadt,
+    /// The DSDT has no `\_S5` package.
+    NoSleepType,
+    /// The firmware never handed control to ACPI.
+    AcpiEnableFailed,
+    /// The sleep command was written but the machine kept running.
+    StillRunning,
+}
+
+/// Power the machine off, halting if every method failed.
+pub fn shutdown() -> ! {
+    interrupts::disable();
+    println_log!("Shutting down...");
+
+    if let Err(err) = acpi_shutdown() {
+        serial_println!("ACPI shutdown failed: {:?}", err);
+    }
+
+    serial_println!("Shutdown failed, halting");
+    hcf()
+}
+
+/// Restart the machine.
+pub fn reboot() -> ! {
+    interrupts::disable();
+    println_log!("Rebooting...");
+
+    acpi_reset();
+    keyboard_controller_reset();
+    triple_fault()
+}
+
+fn acpi_shutdown() -> Result<(), PowerError> {
+    let fadt = acpi::fadt().ok_or(PowerError::NoFadt)?;
+    let sleep = acpi::sleep_type(5).ok_or(PowerError::NoSleepType)?;
+    let pm1a = fadt.pm1a_control_port().ok_or(PowerError::NoFadt)?;
+
+    enable_acpi(&fadt, pm1a)?;
+
+    unsafe {
+        outw(pm1a, (sleep.a as u16) << SLP_TYP_SHIFT | SLP_EN);
+        if let Some(pm1b) = fadt.pm1b_control_port() {
+            outw(pm1b, (sleep.b as u16) << SLP_TYP_SHIFT | SLP_EN);
+        }
+    }
+
+    // give the chipset a moment before declaring failure
+    for _ in 0..1_000_000 {
+        core::hint::spin_loop();
+    }
+
+    Err(PowerError::StillRunning)
+}
+
+/// Switch from legacy (SMM) to ACPI mode if the firmware hasn't already.
+fn enable_acpi(fadt: &acpi::Fadt, pm1a: u16) -> Result<(), PowerError> {
+    if unsafe { inw(pm1a) } & SCI_EN != 0 {
+        return Ok(());
+    }
+
+    let (smi_command, acpi_enable) = (fadt.smi_command, fadt.acpi_enable);
+    if smi_command == 0 || acpi_enable == 0 {
+        return Err(PowerError::AcpiEnableFailed);
+    }
+
+    unsafe { outb(smi_command as u16, acpi_enable) };
+
+    for _ in 0..1_000_000 {
+        if unsafe { inw(pm1a) } & SCI_EN != 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+
+    Err(PowerError::AcpiEnableFailed)
+}
+
+fn acpi_reset() {
+    let Some(fadt) = acpi::fadt() else {
+        return;
+    };
+    let Some(reset) = fadt.reset_register() else {
+        return;
+    };
+
+    let (address, value) = (reset.address, fadt.reset_value);
+    match reset.address_space {
+        sdt::ADDRESS_SPACE_IO => unsafe { outb(address as u16, value) },
+        sdt::ADDRESS_SPACE_MEMORY => {
+            if let Ok(virt) = paging::map_mmio(PhysAddr::new(address), 1) {
+                unsafe { virt.as_mut_ptr::<u8>().write_volatile(value) };
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Pulse the CPU reset line through the 8042 keyboard controller.
+fn keyboard_controller_reset() {
+    unsafe {
+        for _ in 0..100_000 {
+            if inb(KBC_STATUS) & KBC_INPUT_FULL == 0 {
+                break;
+            }
+        }
+        outb(KBC_COMMAND, KBC_PULSE_RESET);
+    }
+
+    for _ in 0..1_000_000 {
+        core::hint::spin_loop();
+    }
+}
+
+/// Load an empty IDT and raise an exception, the CPU can't deliver it and resets.
+fn triple_fault() -> ! {
+    static EMPTY_IDT: spin::Lazy<InterruptDescriptorTable> = spin::Lazy::new(InterruptDescriptorTable::new);
+
+    EMPTY_IDT.load();
+    x86_64::instructions::interrupts::int3();
+
+    hcf()
+}
@@ -0,0 +1,192 @@
+//! Kernel symbols
+//!
+//! Limine hands us the kernel ELF it booted, symbol table included, so
+//! addresses can be turned into `function+offset` without a separate map
+//! file. Lookups scan the table in place and need no heap, so they work on
+//! the panic path too. Without a symbol table (a stripped kernel) addresses
+//! are shown on their own.
+
+use core::fmt;
+use core::mem::size_of;
+
+use spin::Once;
+
+use super::cmdline;
+
+static TABLE: Once<Option<SymbolTable<'static>>> = Once::new();
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELF_CLASS_64: u8 = 2;
+const SECTION_SYMTAB: u32 = 2;
+const SYMBOL_FUNC: u8 = 2;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    address: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct RawSymbol {
+    name: u32,
+    info: u8,
+    other: u8,
+    section: u16,
+    value: u64,
+    size: u64,
+}
+
+/// A function in the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    /// Mangled, [`Located`] shows it demangled.
+    pub name: &'a str,
+    pub address: u64,
+    pub size: u64,
+}
+
+/// An address and the function it is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Located {
+    pub address: u64,
+    pub symbol: Option<Symbol<'static>>,
+}
+
+impl Located {
+    pub fn offset(&self) -> Option<u64> {
+        self.symbol.map(|symbol| self.address - symbol.address)
+    }
+}
+
+/// `0xffffffff80001234 path::to::function+0x1a`, or just the address.
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#018x}", self.address)?;
+        if let (Some(symbol), Some(offset)) = (self.symbol, self.offset()) {
+            write!(f, " {:#}+{:#x}", rustc_demangle::demangle(symbol.name), offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// The function symbols of an ELF64 image.
+pub struct SymbolTable<'a> {
+    symbols: &'a [u8],
+    strings: &'a [u8],
+}
+
+/// Read a `T` at `offset` in `bytes`, if it fits.
+fn read<T: Copy>(bytes: &[u8], offset: usize) -> Option<T> {
+    let end = offset.checked_add(size_of::<T>())?;
+    let bytes = bytes.get(offset..end)?;
+    Some(unsafe { (bytes.as_ptr() as *const T).read_unaligned() })
+}
+
+impl<'a> SymbolTable<'a> {
+    /// Find the symbol table in an ELF64 file, `None` if it isn't one or
+    /// has been stripped.
+    pub fn parse(elf: &'a [u8]) -> Option<Self> {
+        if elf.get(..4)? != ELF_MAGIC || *elf.get(4)? != ELF_CLASS_64 {
+            return None;
+        }
+
+        let section_offset = read::<u64>(elf, 0x28)? as usize;
+        let section_size = read::<u16>(elf, 0x3a)? as usize;
+        let section_count = read::<u16>(elf, 0x3c)? as usize;
+        let section = |index: usize| read::<SectionHeader>(elf, section_offset + index * section_size);
+
+        let symtab = (0..section_count).filter_map(section).find(|header| header.kind == SECTION_SYMTAB)?;
+        let strtab = section(symtab.link as usize)?;
+
+        Some(Self {
+            symbols: elf.get(symtab.offset as usize..(symtab.offset + symtab.size) as usize)?,
+            strings: elf.get(strtab.offset as usize..(strtab.offset + strtab.size) as usize)?,
+        })
+    }
+
+    fn name(&self, offset: u32) -> &'a str {
+        let bytes = self.strings.get(offset as usize..).unwrap_or(&[]);
+        let len = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+        core::str::from_utf8(&bytes[..len]).unwrap_or("?")
+    }
+
+    /// Every function, in table order.
+    pub fn functions(&self) -> impl Iterator<Item = Symbol<'a>> + '_ {
+        self.symbols
+            .as_chunks::<{ size_of::<RawSymbol>() }>()
+            .0
+            .iter()
+            .filter_map(|raw| read::<RawSymbol>(raw, 0))
+            .filter(|raw| raw.info & 0xf == SYMBOL_FUNC && raw.value != 0)
+            .map(|raw| Symbol {
+                name: self.name(raw.name),
+                address: raw.value,
+                size: raw.size,
+            })
+    }
+
+    /// The function `address` is in. One without a size counts as reaching
+    /// up to the next function.
+    pub fn lookup(&self, address: u64) -> Option<Symbol<'a>> {
+        let mut closest: Option<Symbol> = None;
+
+        for symbol in self.functions().filter(|symbol| symbol.address <= address) {
+            if address < symbol.address + symbol.size {
+                return Some(symbol);
+            }
+            if symbol.size == 0 && closest.is_none_or(|closest| symbol.address > closest.address) {
+                closest = Some(symbol);
+            }
+        }
+        closest
+    }
+}
+
+// the kernel file Limine loaded, it stays mapped for as long as we run
+fn table() -> Option<&'static SymbolTable<'static>> {
+    TABLE
+        .call_once(|| {
+            let file = cmdline::kernel_file()?;
+            let elf = unsafe { core::slice::from_raw_parts(file.addr(), file.size() as usize) };
+            SymbolTable::parse(elf)
+        })
+        .as_ref()
+}
+
+/// Whether there are symbols to look addresses up in.
+pub fn is_available() -> bool {
+    table().is_some()
+}
+
+/// The kernel function `address` is in.
+pub fn lookup(address: u64) -> Option<Symbol<'static>> {
+    table()?.lookup(address)
+}
+
+/// `address` with the function it is in.
+pub fn locate(address: u64) -> Located {
+    Located {
+        address,
+        symbol: lookup(address),
+    }
+}
+
+/// Like [`locate`], for a return address. Those point just past the call,
+/// which may already be the next function if the call was the last thing
+/// in it.
+pub fn locate_return(address: u64) -> Located {
+    Located {
+        address,
+        symbol: lookup(address.saturating_sub(1)),
+    }
+}
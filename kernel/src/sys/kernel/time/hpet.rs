@@ -0,0 +1,153 @@
+//! High Precision Event Timer
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use x86_64::{PhysAddr, VirtAddr};
+
+use crate::sys::kernel::acpi;
+use crate::sys::kernel::cpu::x86_64::{ioapic, paging};
+
+const REG_CAPABILITIES: u64 = 0x000;
+const REG_CONFIG: u64 = 0x010;
+const REG_COUNTER: u64 = 0x0f0;
+
+const fn reg_timer_config(n: u64) -> u64 {
+    0x100 + 0x20 * n
+}
+
+const fn reg_timer_comparator(n: u64) -> u64 {
+    0x108 + 0x20 * n
+}
+
+const CONFIG_ENABLE: u64 = 1 << 0;
+const CONFIG_LEGACY_ROUTE: u64 = 1 << 1;
+
+const TIMER_INT_ENABLE: u64 = 1 << 2;
+const TIMER_PERIODIC: u64 = 1 << 3;
+const TIMER_PERIODIC_CAP: u64 = 1 << 4;
+const TIMER_VALUE_SET: u64 = 1 << 6;
+const TIMER_ROUTE_SHIFT: u64 = 9;
+const TIMER_ROUTE_CAP_SHIFT: u64 = 32;
+
+const FEMTOS_PER_NANO: u64 = 1_000_000;
+const FEMTOS_PER_MILLI: u64 = 1_000_000_000_000;
+
+static BASE: AtomicU64 = AtomicU64::new(0);
+// main counter value when it was started
+static START: AtomicU64 = AtomicU64::new(0);
+// main counter period in femtoseconds
+static PERIOD: AtomicU64 = AtomicU64::new(0);
+
+/// Map the HPET described by ACPI and start its main counter. Returns false
+/// if the machine has none.
+pub fn init() -> bool {
+    if is_available() {
+        return true;
+    }
+
+    let Some(table) = acpi::hpet() else {
+        return false;
+    };
+
+    let Ok(base) = paging::map_mmio(PhysAddr::new(table.address()), 0x400) else {
+        return false;
+    };
+    BASE.store(base.as_u64(), Ordering::SeqCst);
+
+    unsafe {
+        let period = read(REG_CAPABILITIES) >> 32;
+        PERIOD.store(period, Ordering::SeqCst);
+
+        let config = read(REG_CONFIG) & !CONFIG_LEGACY_ROUTE;
+        write(REG_CONFIG, config | CONFIG_ENABLE);
+        START.store(read(REG_COUNTER), Ordering::SeqCst);
+    }
+
+    true
+}
+
+pub fn is_available() -> bool {
+    BASE.load(Ordering::Relaxed) != 0
+}
+
+/// Main counter frequency in Hz.
+pub fn frequency() -> u64 {
+    1_000_000_000_000_000 / PERIOD.load(Ordering::Relaxed).max(1)
+}
+
+unsafe fn read(reg: u64) -> u64 {
+    VirtAddr::new(BASE.load(Ordering::Relaxed) + reg).as_ptr::<u64>().read_volatile()
+}
+
+unsafe fn write(reg: u64, value: u64) {
+    VirtAddr::new(BASE.load(Ordering::Relaxed) + reg).as_mut_ptr::<u64>().write_volatile(value)
+}
+
+pub fn counter() -> u64 {
+    unsafe { read(REG_COUNTER) }
+}
+
+/// Nanoseconds since [`init`].
+pub fn nanos() -> u64 {
+    let elapsed = counter().wrapping_sub(START.load(Ordering::Relaxed));
+    (elapsed as u128 * PERIOD.load(Ordering::Relaxed) as u128 / FEMTOS_PER_NANO as u128) as u64
+}
+
+/// Let `ms` milliseconds pass on the main counter and sample `clock` at
+/// both ends.
+pub fn measure(ms: u64, clock: impl Fn() -> u64) -> (u64, u64) {
+    let ticks = ms * FEMTOS_PER_MILLI / PERIOD.load(Ordering::Relaxed);
+
+    let begin = counter();
+    let start = clock();
+    while counter().wrapping_sub(begin) < ticks {
+        core::hint::spin_loop();
+    }
+    (start, clock())
+}
+
+/// Fire `vector` every `period_ns` nanoseconds from comparator 0. Returns
+/// false if the comparator can't run periodically.
+pub fn start_periodic(period_ns: u64, vector: u8) -> bool {
+    unsafe {
+        let config = read(reg_timer_config(0));
+        let routes = (config >> TIMER_ROUTE_CAP_SHIFT) as u32;
+        if config & TIMER_PERIODIC_CAP == 0 || routes == 0 {
+            return false;
+        }
+
+        // any I/O APIC input the comparator can drive will do
+        let gsi = routes.trailing_zeros();
+        let ticks = period_ns * FEMTOS_PER_NANO / PERIOD.load(Ordering::Relaxed);
+
+        let global = read(REG_CONFIG);
+        write(REG_CONFIG, global & !CONFIG_ENABLE);
+        write(REG_COUNTER, 0);
+
+        write(
+            reg_timer_config(0),
+            TIMER_INT_ENABLE | TIMER_PERIODIC | TIMER_VALUE_SET | (gsi as u64) << TIMER_ROUTE_SHIFT,
+        );
+        // with VALUE_SET the first write sets the deadline, the second the period
+        write(reg_timer_comparator(0), ticks);
+        write(reg_timer_comparator(0), ticks);
+
+        write(REG_CONFIG, global | CONFIG_ENABLE);
+
+        ioapic::route_gsi(gsi, vector, ioapic::Polarity::ActiveHigh, ioapic::TriggerMode::Edge);
+    }
+
+    true
+}
+
+/// Stop comparator 0 from raising interrupts, the main counter keeps running.
+pub fn stop() {
+    if !is_available() {
+        return;
+    }
+
+    unsafe {
+        let config = read(reg_timer_config(0));
+        write(reg_timer_config(0), config & !(TIMER_INT_ENABLE | TIMER_PERIODIC));
+    }
+}
@@ -0,0 +1,74 @@
+//! Local APIC timer
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::sys::kernel::cpu::x86_64::apic;
+
+use super::tsc;
+
+const TIMER_PERIODIC: u32 = 1 << 17;
+/// Divide configuration value for a divisor of 16.
+const DIVIDE_BY_16: u32 = 0b0011;
+
+/// Length of the calibration window.
+const CALIBRATION_NS: u64 = 10_000_000;
+
+// timer counts per second
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Measure how fast the timer counts down, using the already calibrated TSC.
+/// Returns 0 if there is no TSC to measure against.
+pub fn calibrate() -> u64 {
+    if !tsc::is_calibrated() {
+        return 0;
+    }
+
+    let frequency = unsafe {
+        apic::write(apic::REG_TIMER_DIVIDE, DIVIDE_BY_16);
+        apic::write(apic::REG_LVT_TIMER, apic::LVT_MASKED);
+        apic::write(apic::REG_TIMER_INITIAL, u32::MAX);
+
+        tsc::delay_ns(CALIBRATION_NS);
+
+        let elapsed = u32::MAX - apic::read(apic::REG_TIMER_CURRENT);
+        apic::write(apic::REG_TIMER_INITIAL, 0);
+
+        elapsed as u64 * (1_000_000_000 / CALIBRATION_NS)
+    };
+
+    FREQUENCY.store(frequency, Ordering::SeqCst);
+    frequency
+}
+
+/// Fire `vector` every `period_ns` nanoseconds. Returns false if the timer
+/// couldn't be calibrated.
+pub fn start_periodic(period_ns: u64, vector: u8) -> bool {
+    let frequency = match FREQUENCY.load(Ordering::Relaxed) {
+        0 => calibrate(),
+        frequency => frequency,
+    };
+
+    let count = (frequency * period_ns / 1_000_000_000).min(u32::MAX as u64) as u32;
+    if count == 0 {
+        return false;
+    }
+
+    unsafe {
+        apic::write(apic::REG_TIMER_DIVIDE, DIVIDE_BY_16);
+        apic::write(apic::REG_LVT_TIMER, TIMER_PERIODIC | vector as u32);
+        apic::write(apic::REG_TIMER_INITIAL, count);
+    }
+
+    true
+}
+
+pub fn stop() {
+    if !apic::is_enabled() {
+        return;
+    }
+
+    unsafe {
+        apic::write(apic::REG_LVT_TIMER, apic::LVT_MASKED);
+        apic::write(apic::REG_TIMER_INITIAL, 0);
+    }
+}
@@ -0,0 +1,226 @@
+//! Timekeeping
+//!
+//! One of the PIT, HPET or local APIC timer raises the timer interrupt at a
+//! configurable frequency. Each interrupt is a tick: it drives the timer
+//! wheel that runs one-shot and periodic callbacks. The monotonic clock
+//! itself comes from the TSC, so [`uptime`] has nanosecond resolution
+//! independent of the tick rate. Without a usable TSC it falls back to the
+//! HPET counter, and failing that to counting ticks.
+
+pub mod hpet;
+pub mod lapic;
+pub mod pit;
+pub mod tsc;
+pub mod wheel;
+
+use alloc::boxed::Box;
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use core::time::Duration;
+
+use spin::Mutex;
+use x86_64::instructions::{hlt, interrupts::without_interrupts};
+
+use crate::println_log;
+use crate::sys::kernel::cpu::x86_64::interrupts;
+
+use wheel::{TimerId, TimerWheel};
+
+/// Tick rate used at boot, in Hz.
+pub const DEFAULT_FREQUENCY: u32 = 1000;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TimerSource {
+    Pit = 1,
+    Hpet = 2,
+    Apic = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// The source isn't present or can't be routed on this machine.
+    Unsupported(TimerSource),
+    InvalidFrequency(u32),
+}
+
+static SOURCE: AtomicU8 = AtomicU8::new(0);
+static FREQUENCY: AtomicU64 = AtomicU64::new(0);
+static TICKS: AtomicU64 = AtomicU64::new(0);
+static TICK_NANOS: AtomicU64 = AtomicU64::new(0);
+
+static WHEEL: Mutex<TimerWheel> = Mutex::new(TimerWheel::new());
+
+// the callback being run by `tick`, so it can cancel itself
+static RUNNING: AtomicU64 = AtomicU64::new(0);
+static CANCEL_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Calibrate the TSC and start ticking from the best source available.
+pub fn init() {
+    let has_hpet = hpet::init();
+    if has_hpet {
+        println_log!("HPET running at {} Hz...", hpet::frequency());
+    }
+
+    match tsc::calibrate() {
+        Some(khz) => println_log!("TSC running at {}.{:03} MHz...", khz / 1000, khz % 1000),
+        None => println_log!("TSC unusable, keeping time with the {}...", if has_hpet { "HPET" } else { "timer ticks" }),
+    }
+
+    let candidates = [TimerSource::Apic, TimerSource::Hpet, TimerSource::Pit];
+    let source = candidates
+        .into_iter()
+        .find(|&source| start(source, DEFAULT_FREQUENCY).is_ok())
+        .expect("no usable timer");
+
+    println_log!("Timer ticking at {} Hz from the {:?}...", frequency(), source);
+}
+
+/// Switch to `source` ticking at `frequency` Hz, stopping the previous source.
+///
+/// Pending timers keep their deadline in ticks, so changing the frequency
+/// stretches or shrinks what they have left.
+pub fn start(source: TimerSource, frequency: u32) -> Result<(), TimerError> {
+    if frequency == 0 || frequency as u64 > NANOS_PER_SEC {
+        return Err(TimerError::InvalidFrequency(frequency));
+    }
+
+    let apic = interrupts::using_apic();
+    let vector = interrupts::InterruptIndex::Timer.as_u8();
+    let period = NANOS_PER_SEC / frequency as u64;
+
+    without_interrupts(|| {
+        stop();
+
+        let (started, tick_nanos) = match source {
+            TimerSource::Pit => {
+                interrupts::enable_irq(interrupts::InterruptIndex::Timer);
+                pit::start_periodic(frequency);
+                (true, NANOS_PER_SEC / pit::actual_frequency(frequency) as u64)
+            }
+            TimerSource::Hpet => (apic && hpet::is_available() && hpet::start_periodic(period, vector), period),
+            TimerSource::Apic => (apic && lapic::start_periodic(period, vector), period),
+        };
+
+        if !started {
+            return Err(TimerError::Unsupported(source));
+        }
+
+        TICK_NANOS.store(tick_nanos, Ordering::SeqCst);
+        FREQUENCY.store(frequency as u64, Ordering::SeqCst);
+        SOURCE.store(source as u8, Ordering::SeqCst);
+        Ok(())
+    })
+}
+
+/// Silence every tick source.
+fn stop() {
+    SOURCE.store(0, Ordering::SeqCst);
+
+    pit::stop();
+    hpet::stop();
+    lapic::stop();
+
+    interrupts::disable_irq(interrupts::InterruptIndex::Timer);
+}
+
+pub fn source() -> Option<TimerSource> {
+    match SOURCE.load(Ordering::Relaxed) {
+        1 => Some(TimerSource::Pit),
+        2 => Some(TimerSource::Hpet),
+        3 => Some(TimerSource::Apic),
+        _ => None,
+    }
+}
+
+/// Tick rate in Hz.
+pub fn frequency() -> u32 {
+    FREQUENCY.load(Ordering::Relaxed) as u32
+}
+
+/// Ticks since the timer was started.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time since boot, more precisely since the TSC was calibrated.
+pub fn uptime() -> Duration {
+    if tsc::is_calibrated() {
+        Duration::from_nanos(tsc::nanos())
+    } else if hpet::is_available() {
+        Duration::from_nanos(hpet::nanos())
+    } else {
+        Duration::from_nanos(ticks() * TICK_NANOS.load(Ordering::Relaxed))
+    }
+}
+
+/// Block for at least `duration`. Halts between ticks when interrupts are
+/// enabled and busy waits otherwise.
+pub fn sleep(duration: Duration) {
+    let deadline = uptime() + duration;
+
+    while uptime() < deadline {
+        if interrupts::are_enabled() && source().is_some() {
+            hlt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Ticks needed to cover `duration`, rounded up.
+fn to_ticks(duration: Duration) -> u64 {
+    let tick = TICK_NANOS.load(Ordering::Relaxed).max(1);
+    (duration.as_nanos() as u64).div_ceil(tick)
+}
+
+/// Run `callback` once, `delay` from now.
+///
+/// Callbacks run from the timer interrupt with interrupts disabled, so they
+/// must be short and must not sleep.
+pub fn after(delay: Duration, callback: impl FnMut() + Send + 'static) -> TimerId {
+    let delay = to_ticks(delay);
+    without_interrupts(|| WHEEL.lock().schedule(delay, None, Box::new(callback)))
+}
+
+/// Run `callback` every `period`, starting one period from now.
+pub fn every(period: Duration, callback: impl FnMut() + Send + 'static) -> TimerId {
+    let period = to_ticks(period);
+    without_interrupts(|| WHEEL.lock().schedule(period, Some(period), Box::new(callback)))
+}
+
+/// Stop a pending timer. Returns false if it already fired or was cancelled.
+pub fn cancel(id: TimerId) -> bool {
+    without_interrupts(|| {
+        if WHEEL.lock().cancel(id) {
+            true
+        } else if RUNNING.load(Ordering::Relaxed) == id.0 {
+            CANCEL_RUNNING.store(true, Ordering::Relaxed);
+            true
+        } else {
+            false
+        }
+    })
+}
+
+/// Called from the timer interrupt handler.
+pub fn tick() {
+    if source().is_none() {
+        return;
+    }
+
+    let now = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    let due = WHEEL.lock().expire(now);
+
+    // the lock is released while callbacks run so they can add timers
+    for mut timer in due {
+        RUNNING.store(timer.id.0, Ordering::Relaxed);
+        timer.fire();
+        RUNNING.store(0, Ordering::Relaxed);
+
+        if !CANCEL_RUNNING.swap(false, Ordering::Relaxed) {
+            WHEEL.lock().rearm(timer);
+        }
+    }
+}
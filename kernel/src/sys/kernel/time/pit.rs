@@ -0,0 +1,79 @@
+//! 8253/8254 programmable interval timer
+
+use crate::sys::kernel::cpu::{inb, outb};
+
+/// Input clock of the PIT in Hz.
+pub const BASE_FREQUENCY: u32 = 1_193_182;
+
+const CHANNEL0: u16 = 0x40;
+const CHANNEL2: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+/// Keyboard controller port B, gates channel 2 and reads back its output.
+const PORT_B: u16 = 0x61;
+
+const PORT_B_GATE2: u8 = 1 << 0;
+const PORT_B_SPEAKER: u8 = 1 << 1;
+const PORT_B_OUT2: u8 = 1 << 5;
+
+// channel in bits 6-7, lobyte/hibyte access, mode in bits 1-3, binary counting
+const CMD_CHANNEL0_RATE: u8 = 0x34;
+const CMD_CHANNEL0_ONESHOT: u8 = 0x30;
+const CMD_CHANNEL2_ONESHOT: u8 = 0xb0;
+
+/// Reload value for `frequency`, a divisor of 0 means 65536.
+fn divisor(frequency: u32) -> u16 {
+    match BASE_FREQUENCY / frequency.max(1) {
+        0 => 1,
+        d if d > 0xffff => 0,
+        d => d as u16,
+    }
+}
+
+/// The frequency the PIT really runs at when asked for `frequency`.
+pub fn actual_frequency(frequency: u32) -> u32 {
+    match divisor(frequency) {
+        0 => BASE_FREQUENCY / 0x10000,
+        d => BASE_FREQUENCY / d as u32,
+    }
+}
+
+/// Fire IRQ0 periodically at (about) `frequency` Hz.
+pub fn start_periodic(frequency: u32) {
+    let [lo, hi] = divisor(frequency).to_le_bytes();
+    unsafe {
+        outb(COMMAND, CMD_CHANNEL0_RATE);
+        outb(CHANNEL0, lo);
+        outb(CHANNEL0, hi);
+    }
+}
+
+/// Stop channel 0. In one-shot mode the counter doesn't start until a count
+/// is written, so programming the mode alone leaves it idle.
+pub fn stop() {
+    unsafe { outb(COMMAND, CMD_CHANNEL0_ONESHOT) };
+}
+
+/// Run channel 2 for `ms` milliseconds and sample `clock` at both ends.
+pub fn measure(ms: u64, clock: impl Fn() -> u64) -> (u64, u64) {
+    let count = (BASE_FREQUENCY as u64 * ms / 1000).min(0xffff) as u16;
+    let [lo, hi] = count.to_le_bytes();
+
+    unsafe {
+        let port_b = inb(PORT_B);
+        outb(PORT_B, (port_b & !PORT_B_SPEAKER) | PORT_B_GATE2);
+
+        outb(COMMAND, CMD_CHANNEL2_ONESHOT);
+        outb(CHANNEL2, lo);
+        outb(CHANNEL2, hi);
+
+        // counting starts with the high byte, OUT2 goes high at zero
+        let start = clock();
+        while inb(PORT_B) & PORT_B_OUT2 == 0 {
+            core::hint::spin_loop();
+        }
+        let end = clock();
+
+        outb(PORT_B, port_b);
+        (start, end)
+    }
+}
@@ -0,0 +1,66 @@
+//! Time stamp counter
+//!
+//! Every x86_64 CPU has a TSC. Once its frequency is known it is the
+//! cheapest and finest clock we have, so the monotonic clock is built on it.
+
+use core::arch::x86_64::_rdtsc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{hpet, pit};
+
+/// Length of the calibration window.
+const CALIBRATION_MS: u64 = 10;
+
+static KHZ: AtomicU64 = AtomicU64::new(0);
+// nanoseconds per tick as a 32.32 fixed point number
+static SCALE: AtomicU64 = AtomicU64::new(0);
+static BOOT: AtomicU64 = AtomicU64::new(0);
+
+#[inline]
+pub fn read() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+/// Measure the TSC frequency against the HPET if there is one, otherwise
+/// against PIT channel 2. Returns the frequency in kHz, or `None` if the
+/// TSC didn't visibly move and can't be used as a clock.
+pub fn calibrate() -> Option<u64> {
+    let (start, end) = match hpet::is_available() {
+        true => hpet::measure(CALIBRATION_MS, read),
+        false => pit::measure(CALIBRATION_MS, read),
+    };
+
+    let khz = end.saturating_sub(start) / CALIBRATION_MS;
+    if khz == 0 {
+        return None;
+    }
+
+    KHZ.store(khz, Ordering::SeqCst);
+    SCALE.store(((1_000_000u128 << 32) / khz as u128) as u64, Ordering::SeqCst);
+    BOOT.store(start, Ordering::SeqCst);
+
+    Some(khz)
+}
+
+pub fn khz() -> u64 {
+    KHZ.load(Ordering::Relaxed)
+}
+
+pub fn is_calibrated() -> bool {
+    khz() != 0
+}
+
+/// Nanoseconds since calibration.
+pub fn nanos() -> u64 {
+    let delta = read().saturating_sub(BOOT.load(Ordering::Relaxed));
+    ((delta as u128 * SCALE.load(Ordering::Relaxed) as u128) >> 32) as u64
+}
+
+/// Busy wait for `ns` nanoseconds, works with interrupts disabled. Only
+/// once [`is_calibrated`], it never returns before that.
+pub fn delay_ns(ns: u64) {
+    let deadline = nanos() + ns;
+    while nanos() < deadline {
+        core::hint::spin_loop();
+    }
+}
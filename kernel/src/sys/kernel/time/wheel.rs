@@ -0,0 +1,132 @@
+//! Hashed timer wheel
+//!
+//! Timers are hashed into one of [`SLOTS`] buckets by their deadline tick, so
+//! a tick only has to look at a single bucket. Timers further than a full
+//! turn away just sit in their bucket until their round comes up.
+
+use alloc::{boxed::Box, vec::Vec};
+
+pub const SLOTS: usize = 256;
+
+pub type Callback = Box<dyn FnMut() + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId(pub u64);
+
+pub struct Timer {
+    pub id: TimerId,
+    /// Tick the timer fires on.
+    pub deadline: u64,
+    /// Re-arm interval in ticks for periodic timers.
+    pub period: Option<u64>,
+    callback: Callback,
+}
+
+impl Timer {
+    pub fn fire(&mut self) {
+        (self.callback)()
+    }
+}
+
+pub struct TimerWheel {
+    slots: [Vec<Timer>; SLOTS],
+    /// Last tick that was expired.
+    current: u64,
+    next_id: u64,
+}
+
+impl TimerWheel {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { Vec::new() }; SLOTS],
+            current: 0,
+            next_id: 1,
+        }
+    }
+
+    /// Run `callback` `delay` ticks from now, and every `period` ticks after
+    /// that if given. A delay of 0 fires on the next tick.
+    pub fn schedule(&mut self, delay: u64, period: Option<u64>, callback: Callback) -> TimerId {
+        let id = TimerId(self.next_id);
+        self.next_id += 1;
+
+        self.insert(Timer {
+            id,
+            deadline: self.current + delay.max(1),
+            period: period.map(|period| period.max(1)),
+            callback,
+        });
+
+        id
+    }
+
+    fn insert(&mut self, timer: Timer) {
+        self.slots[timer.deadline as usize % SLOTS].push(timer);
+    }
+
+    /// Remove a pending timer, returns false if it already fired or was cancelled.
+    pub fn cancel(&mut self, id: TimerId) -> bool {
+        for slot in self.slots.iter_mut() {
+            if let Some(index) = slot.iter().position(|timer| timer.id == id) {
+                slot.swap_remove(index);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Advance the wheel to tick `now` and take out every timer that is due.
+    pub fn expire(&mut self, now: u64) -> Vec<Timer> {
+        let mut due = Vec::new();
+
+        if now <= self.current {
+            return due;
+        }
+
+        // past a full turn every bucket has to be looked at anyway
+        let ticks = (now - self.current).min(SLOTS as u64);
+        for tick in now + 1 - ticks..=now {
+            let slot = &mut self.slots[tick as usize % SLOTS];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].deadline <= now {
+                    due.push(slot.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        self.current = now;
+        due.sort_unstable_by_key(|timer| (timer.deadline, timer.id));
+        due
+    }
+
+    /// Put a periodic timer that just fired back in for its next period.
+    pub fn rearm(&mut self, mut timer: Timer) {
+        if let Some(period) = timer.period {
+            // skip periods that were missed instead of firing them in a burst
+            timer.deadline = (timer.deadline + period).max(self.current + 1);
+            self.insert(timer);
+        }
+    }
+
+    pub fn current(&self) -> u64 {
+        self.current
+    }
+
+    /// Number of pending timers.
+    pub fn len(&self) -> usize {
+        self.slots.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for TimerWheel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1 +1,4 @@
-// mod file for standard library in kernel;
\ No newline at end of file
+// mod file for standard library in kernel;
+
+pub mod ring;
+pub mod utf8;
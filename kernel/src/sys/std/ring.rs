@@ -0,0 +1,91 @@
+//! Fixed size lock-free ring buffer
+//!
+//! Meant for handing data between an interrupt handler and the rest of the
+//! kernel: one side only pushes and the other only pops, so neither ever has
+//! to take a lock the other could be holding.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A single producer, single consumer queue of `N` elements.
+pub struct RingBuffer<T: Copy, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // both only ever count up, the slot is the index modulo N
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// the head and tail protocol hands every slot to exactly one side at a time
+unsafe impl<T: Copy + Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Append `value`, handing it back if the buffer is full. Producer only.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(self.head.load(Ordering::Acquire)) >= N {
+            return Err(value);
+        }
+
+        unsafe { (*self.slots[tail % N].get()).write(value) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Take the oldest element. Consumer only.
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let value = unsafe { (*self.slots[head % N].get()).assume_init() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Look at the oldest element without taking it. Consumer only.
+    pub fn peek(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        Some(unsafe { (*self.slots[head % N].get()).assume_init() })
+    }
+
+    /// Drop everything queued. Consumer only.
+    pub fn clear(&self) {
+        self.head.store(self.tail.load(Ordering::Acquire), Ordering::Release);
+    }
+
+    pub fn len(&self) -> usize {
+        self.tail.load(Ordering::Acquire).wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= N
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,51 @@
+//! Incremental UTF-8 decoding
+//!
+//! For byte streams that arrive one byte at a time, like console output.
+
+/// Turns bytes back into characters. Invalid input comes out as U+FFFD.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Utf8Decoder {
+    code_point: u32,
+    // continuation bytes still expected
+    remaining: u8,
+}
+
+impl Utf8Decoder {
+    pub const fn new() -> Self {
+        Self {
+            code_point: 0,
+            remaining: 0,
+        }
+    }
+
+    /// Feed one byte, returns a character once one is complete.
+    pub fn push(&mut self, byte: u8) -> Option<char> {
+        match byte {
+            0x00..=0x7f => {
+                // an unfinished sequence is dropped
+                self.remaining = 0;
+                Some(byte as char)
+            }
+            0x80..=0xbf if self.remaining > 0 => {
+                self.code_point = self.code_point << 6 | (byte & 0x3f) as u32;
+                self.remaining -= 1;
+
+                (self.remaining == 0)
+                    .then(|| char::from_u32(self.code_point).unwrap_or(char::REPLACEMENT_CHARACTER))
+            }
+            0xc2..=0xdf => self.start(byte & 0x1f, 1),
+            0xe0..=0xef => self.start(byte & 0x0f, 2),
+            0xf0..=0xf4 => self.start(byte & 0x07, 3),
+            _ => {
+                self.remaining = 0;
+                Some(char::REPLACEMENT_CHARACTER)
+            }
+        }
+    }
+
+    fn start(&mut self, bits: u8, remaining: u8) -> Option<char> {
+        self.code_point = bits as u32;
+        self.remaining = remaining;
+        None
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(test)]
+use crate::sys::kernel::acpi;
+
+#[test_case]
+pub fn test_acpi_checksum() {
+    assert!(acpi::sdt::checksum_valid(&[0x10, 0xf0]));
+    assert!(!acpi::sdt::checksum_valid(&[0x10, 0xf1]));
+}
+
+#[test_case]
+pub fn test_acpi_tables_discovered() {
+    // QEMU always provides these
+    let madt = acpi::madt().expect("no MADT");
+    assert!(!madt.io_apics.is_empty());
+    assert!(madt.processors().count() >= 1);
+
+    let fadt = acpi::fadt().expect("no FADT");
+    assert!(fadt.pm1a_control_port().is_some());
+    assert!(acpi::find(b"DSDT").is_some());
+}
+
+#[test_case]
+pub fn test_acpi_s5_sleep_type() {
+    // needed by power::shutdown
+    assert!(acpi::sleep_type(5).is_some());
+}
+
+#[test_case]
+pub fn test_acpi_sleep_type_prefixes() {
+    use acpi::dsdt::{self, SleepType};
+
+    // Name(_S5, Package(2) { Word(5), DWord(7) })
+    let aml = [0x08, b'_', b'S', b'5', b'_', 0x12, 0x0c, 0x02, 0x0b, 0x05, 0x00, 0x0c, 0x07, 0x00, 0x00, 0x00];
+    assert_eq!(dsdt::sleep_type_in(&aml, 5), Some(SleepType { a: 5, b: 7 }));
+
+    // Package(2) { Zero, One } behind a root prefix
+    let aml = [0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x06, 0x02, 0x00, 0x01];
+    assert_eq!(dsdt::sleep_type_in(&aml, 5), Some(SleepType { a: 0, b: 1 }));
+
+    // a method call isn't a constant
+    let aml = [0x08, b'_', b'S', b'5', b'_', 0x12, 0x06, 0x02, 0x5b, 0x01, 0x00];
+    assert_eq!(dsdt::sleep_type_in(&aml, 5), None);
+
+    // too big for SLP_TYP
+    let aml = [0x08, b'_', b'S', b'5', b'_', 0x12, 0x06, 0x02, 0x0b, 0x00, 0x01, 0x00];
+    assert_eq!(dsdt::sleep_type_in(&aml, 5), None);
+}
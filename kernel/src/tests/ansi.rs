@@ -0,0 +1,87 @@
+#[cfg(test)]
+use alloc::vec::Vec;
+#[cfg(test)]
+use crate::print;
+#[cfg(test)]
+use crate::sys::kernel::drivers::framebuffer::{ansi::{self, Action, Parser}, textwriter};
+
+#[cfg(test)]
+fn actions(bytes: &[u8]) -> Vec<Action> {
+    let mut parser = Parser::new();
+    bytes.iter().filter_map(|&byte| parser.advance(byte)).collect()
+}
+
+#[test_case]
+pub fn test_ansi_plain_text() {
+    let actions = actions(b"a\n\x7f");
+    assert_eq!(actions, [Action::Print(b'a'), Action::Execute(b'\n')]);
+}
+
+#[test_case]
+pub fn test_ansi_csi_params() {
+    let actions = actions(b"\x1b[38;2;255;128;0m\x1b[;5H\x1b[m\x1b[?25l");
+    assert_eq!(actions.len(), 4);
+
+    let Action::Csi(sgr) = actions[0] else { panic!("expected a CSI") };
+    assert_eq!((sgr.action, sgr.params()), (b'm', &[38, 2, 255, 128, 0][..]));
+
+    // a left out parameter falls back to the default
+    let Action::Csi(cup) = actions[1] else { panic!("expected a CSI") };
+    assert_eq!((cup.param(0, 1), cup.param(1, 1)), (1, 5));
+
+    let Action::Csi(reset) = actions[2] else { panic!("expected a CSI") };
+    assert!(reset.params().is_empty());
+
+    let Action::Csi(hide) = actions[3] else { panic!("expected a CSI") };
+    assert_eq!((hide.private, hide.param(0, 0), hide.action), (b'?', 25, b'l'));
+}
+
+#[test_case]
+pub fn test_ansi_escape_and_osc() {
+    // a window title is swallowed, charset selection too
+    let actions = actions(b"\x1b]0;title\x07\x1b(Bx\x1b7");
+    assert_eq!(actions, [Action::Print(b'x'), Action::Escape(b'7')]);
+}
+
+#[test_case]
+pub fn test_ansi_palette() {
+    assert_eq!(ansi::colour_256(1), 0xAA0000);
+    assert_eq!(ansi::colour_256(196), 0xff0000);
+    assert_eq!(ansi::colour_256(232), 0x080808);
+    assert_eq!(ansi::colour_256(255), 0xeeeeee);
+}
+
+#[test_case]
+pub fn test_ansi_cursor_movement() {
+    print!("\x1b[5;10H");
+    assert_eq!(textwriter::cursor().0, (9, 4));
+
+    print!("\x1b[2A\x1b[3C");
+    assert_eq!(textwriter::cursor().0, (12, 2));
+
+    print!("\x1b[s\x1b[H\x1b[31mred\x1b[0m\x1b[u");
+    assert_eq!(textwriter::cursor().0, (12, 2));
+
+    print!("\x1b[2J\x1b[H");
+    assert_eq!(textwriter::cursor().0, (0, 0));
+}
+
+#[test_case]
+pub fn test_ansi_colour_spans_prints() {
+    use crate::printerr;
+    use textwriter::Console;
+
+    let colour = || textwriter::with_console(Console::SHELL, |writer| writer.colour());
+    let Some(default) = colour() else { return };
+
+    print!("\x1b[31m");
+    print!("red");
+    assert_eq!(colour(), Some((ansi::COLOURS[1], default.1)));
+
+    // an error message doesn't end what the program set
+    printerr!("error");
+    assert_eq!(colour(), Some((ansi::COLOURS[1], default.1)));
+
+    print!("\x1b[0m\n");
+    assert_eq!(colour(), Some(default));
+}
@@ -0,0 +1,61 @@
+#[cfg(test)]
+use crate::println;
+#[cfg(test)]
+use crate::sys::kernel::drivers::framebuffer::textwriter::{self, Console};
+
+#[test_case]
+pub fn test_console_scrolls_at_bottom() {
+    let (_, (_, height)) = textwriter::cursor();
+
+    for line in 0..height + 5 {
+        println!("scroll test line {}", line);
+    }
+
+    // the cursor stays on the last row instead of wrapping to the top
+    let ((col, line), _) = textwriter::cursor();
+    assert_eq!((col, line), (0, height - 1));
+    assert!(textwriter::scrollback_len() >= 5);
+}
+
+#[test_case]
+pub fn test_console_scrollback_limit() {
+    textwriter::set_scrollback_size(10);
+
+    let (_, (_, height)) = textwriter::cursor();
+    for line in 0..height + 20 {
+        println!("limit test line {}", line);
+    }
+    assert_eq!(textwriter::scrollback_len(), 10);
+
+    // paging around the history and back doesn't move the cursor
+    textwriter::scroll_page_up();
+    textwriter::scroll_page_down();
+    assert_eq!(textwriter::cursor().0, (0, height - 1));
+
+    textwriter::set_scrollback_size(textwriter::DEFAULT_SCROLLBACK);
+}
+
+#[test_case]
+pub fn test_consoles_are_independent() {
+    use core::fmt::Write;
+
+    let before = textwriter::cursor().0;
+
+    // home the cursor, then write three characters off screen
+    write!(Console::Tty3, "\x1b[Habc").unwrap();
+    assert_eq!(textwriter::with_console(Console::Tty3, |writer| writer.cursor()), Some((3, 0)));
+    assert_eq!(textwriter::cursor().0, before);
+}
+
+#[test_case]
+pub fn test_console_switching() {
+    assert_eq!(textwriter::active(), Console::SHELL);
+
+    textwriter::switch_to(Console::Tty4);
+    assert_eq!(textwriter::active(), Console::Tty4);
+    assert_eq!(textwriter::with_console(Console::Tty4, |writer| writer.is_active()), Some(true));
+    assert_eq!(textwriter::with_console(Console::SHELL, |writer| writer.is_active()), Some(false));
+
+    textwriter::switch_to(Console::SHELL);
+    assert_eq!(textwriter::with_console(Console::SHELL, |writer| writer.is_active()), Some(true));
+}
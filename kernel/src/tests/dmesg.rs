@@ -0,0 +1,77 @@
+#[cfg(test)]
+use core::time::Duration;
+#[cfg(test)]
+use log::Level;
+#[cfg(test)]
+use crate::sys::kernel::dmesg::{Entry, LogBuffer, MAX_MESSAGE};
+
+#[cfg(test)]
+fn entry(message: &str) -> Entry<'_> {
+    Entry {
+        sequence: 0,
+        timestamp: Duration::from_micros(1_500_000),
+        level: Level::Warn,
+        target: "test",
+        message,
+    }
+}
+
+#[test_case]
+pub fn test_log_buffer_sequences() {
+    let mut buffer = LogBuffer::<512>::new();
+    assert_eq!(buffer.push(&entry("first")), 0);
+    assert_eq!(buffer.push(&entry("second")), 1);
+
+    let mut count = 0;
+    let next = buffer.read(0, |entry| {
+        assert_eq!(entry.message, ["first", "second"][entry.sequence as usize]);
+        count += 1;
+    });
+    assert_eq!((next, count), (2, 2));
+
+    // reading from the returned number only shows what came after
+    buffer.push(&entry("third"));
+    let mut newer = 0;
+    buffer.read(next, |entry| {
+        assert_eq!((entry.sequence, entry.message), (2, "third"));
+        assert_eq!((entry.timestamp, entry.level), (Duration::from_micros(1_500_000), Level::Warn));
+        newer += 1;
+    });
+    assert_eq!(newer, 1);
+}
+
+#[test_case]
+pub fn test_log_buffer_wraps() {
+    // 20 byte header, 4 byte target, 6 byte message: 30 bytes a record
+    let mut buffer = LogBuffer::<100>::new();
+    for _ in 0..10 {
+        buffer.push(&entry("record"));
+    }
+
+    // only three fit, the oldest went to make room
+    assert_eq!((buffer.first_sequence(), buffer.next_sequence(), buffer.len()), (7, 10, 3));
+
+    let mut sequences = [0; 3];
+    let mut count = 0;
+    buffer.read(0, |entry| {
+        assert_eq!((entry.target, entry.message), ("test", "record"));
+        sequences[count] = entry.sequence;
+        count += 1;
+    });
+    assert_eq!(sequences, [7, 8, 9]);
+}
+
+#[test_case]
+pub fn test_log_buffer_truncates() {
+    let mut buffer = LogBuffer::<4096>::new();
+
+    // a long message of two byte characters is cut between characters
+    let long = "é".repeat(MAX_MESSAGE);
+    buffer.push(&entry(&long));
+    buffer.read(0, |entry| {
+        assert_eq!(entry.message.len(), MAX_MESSAGE);
+        assert!(entry.message.chars().all(|c| c == 'é'));
+    });
+
+    assert_eq!(alloc::format!("{}", entry("hello")), "[    1.500000] WARN  test: hello");
+}
@@ -0,0 +1,132 @@
+#[cfg(test)]
+use core::arch::asm;
+#[cfg(test)]
+use x86_64::instructions::interrupts::int3;
+#[cfg(test)]
+use x86_64::structures::idt::{PageFaultErrorCode, SelectorErrorCode};
+#[cfg(test)]
+use crate::sys::kernel::cpu::{exceptions::{self, Action, ErrorCode, Exception, Fault, Mode, EXCEPTION_VECTORS}, gdt, registers::Registers};
+
+#[cfg(test)]
+const KERNEL_CS: u64 = 0x08;
+#[cfg(test)]
+const USER_CS: u64 = 0x23;
+
+#[cfg(test)]
+fn fault(vector: u8, error_code: u64, cs: u64) -> Fault {
+    Fault::new(vector, error_code, Registers { cs, cr2: 0xdead_b000, ..Registers::default() })
+}
+
+#[test_case]
+pub fn test_every_exception_vector_installed() {
+    let idt = x86_64::instructions::tables::sidt();
+    let entries = idt.base.as_u64() as *const [u16; 8];
+
+    for vector in 0..EXCEPTION_VECTORS {
+        let options = unsafe { (*entries.add(vector))[2] };
+        assert!(options & (1 << 15) != 0, "vector {} not present", vector);
+
+        // the double fault, NMI and machine check switch to stacks of their own
+        let stack = options & 0b111;
+        let expected = match vector {
+            2 => gdt::NMI_IST_INDEX + 1,
+            8 => gdt::DOUBLE_FAULT_IST_INDEX + 1,
+            18 => gdt::MACHINE_CHECK_IST_INDEX + 1,
+            _ => 0,
+        };
+        assert_eq!(stack, expected, "vector {}", vector);
+    }
+}
+
+#[test_case]
+pub fn test_registers_survive_breakpoint() {
+    let (r10, r11): (u64, u64);
+    // caller-saved, the handler would clobber these if the stub didn't
+    // put them back
+    unsafe {
+        asm!(
+            "mov r10, 0x1234",
+            "mov r11, 0x5678",
+            "int3",
+            "mov {}, r10",
+            "mov {}, r11",
+            out(reg) r10,
+            out(reg) r11,
+            out("r10") _,
+            out("r11") _,
+        );
+    }
+    assert_eq!((r10, r11), (0x1234, 0x5678));
+}
+
+#[test_case]
+pub fn test_resumed_exceptions_counted() {
+    let before = exceptions::resumed(3);
+    int3();
+    int3();
+    assert_eq!(exceptions::resumed(3), before + 2);
+    exceptions::report_resumed();
+}
+
+#[test_case]
+pub fn test_single_step_resumes_once() {
+    let before = exceptions::resumed(1);
+    let rflags: u64;
+    // the trap flag makes the instruction after popfq raise #DB, the
+    // handler has to clear it or every one after would too
+    unsafe {
+        asm!(
+            "pushfq",
+            "or qword ptr [rsp], 0x100",
+            "popfq",
+            "nop",
+            "nop",
+            "pushfq",
+            "pop {}",
+            out(reg) rflags,
+        );
+    }
+    assert_eq!(exceptions::resumed(1), before + 1);
+    assert_eq!(rflags & 0x100, 0);
+}
+
+#[test_case]
+pub fn test_fault_report() {
+    let page = fault(14, 0b10, KERNEL_CS);
+    assert_eq!(page.exception, Exception::PageFault);
+    assert_eq!(page.error, ErrorCode::Page(PageFaultErrorCode::CAUSED_BY_WRITE));
+    assert_eq!(page.mode, Mode::Kernel);
+    assert_eq!(page.address(), Some(0xdead_b000));
+    let shown = alloc::format!("{}", page);
+    assert!(shown.starts_with("Page Fault (#PF), vector 14, in kernel mode"), "{}", shown);
+    assert!(shown.contains("error code 0x2: write to a page not present in kernel mode"), "{}", shown);
+    assert!(shown.contains("address 0x00000000deadb000"), "{}", shown);
+
+    // IDT entry 3
+    let protection = fault(13, 0x1a, USER_CS);
+    assert_eq!(protection.error, ErrorCode::Selector(SelectorErrorCode::new_truncate(0x1a)));
+    assert_eq!(protection.mode, Mode::User);
+    assert_eq!(protection.address(), None);
+    assert!(alloc::format!("{}", protection).contains("error code 0x1a: IDT entry 3"));
+
+    let divide = fault(0, 0, KERNEL_CS);
+    assert_eq!(divide.error, ErrorCode::None);
+    assert!(!alloc::format!("{}", divide).contains("error code"));
+
+    assert_eq!(Exception::from_vector(22), Exception::Reserved(22));
+    assert_eq!(alloc::format!("{}", Exception::from_vector(22)), "Reserved Vector 22");
+    assert_eq!((0..EXCEPTION_VECTORS as u8).map(Exception::from_vector).filter(|e| e.has_error_code()).count(), 10);
+}
+
+#[test_case]
+pub fn test_fault_policy() {
+    assert_eq!(exceptions::action(&fault(14, 0, KERNEL_CS)), Action::Panic);
+    assert_eq!(exceptions::action(&fault(14, 0b100, USER_CS)), Action::KillTask);
+    assert_eq!(exceptions::action(&fault(3, 0, KERNEL_CS)), Action::Resume);
+    assert_eq!(exceptions::action(&fault(3, 0, USER_CS)), Action::KillTask);
+    assert_eq!(exceptions::action(&fault(2, 0, USER_CS)), Action::Resume);
+
+    // nothing survives these, whoever was running
+    assert_eq!(exceptions::action(&fault(8, 0, USER_CS)), Action::Panic);
+    assert_eq!(exceptions::action(&fault(18, 0, USER_CS)), Action::Panic);
+}
@@ -0,0 +1,76 @@
+#[cfg(test)]
+use alloc::vec::Vec;
+#[cfg(test)]
+use x86_64::instructions::interrupts::without_interrupts;
+#[cfg(test)]
+use crate::sys::kernel::drivers::framebuffer::{font, psf::{Font, PsfError}, render::FRAMEBUFFER_WRITER, textwriter};
+#[cfg(test)]
+use crate::sys::std::utf8::Utf8Decoder;
+
+#[test_case]
+pub fn test_font_builtin_unicode() {
+    let font = font::builtin();
+    assert_eq!((font.width(), font.height()), (8, 16));
+    assert!(font.has_unicode_table());
+
+    for c in ['A', 'é', 'Ö', 'ñ', '─', '│', '┌', '╬', '█', '€'] {
+        assert!(font.glyph_index(c).is_some(), "no glyph for {}", c);
+    }
+    assert!(font.glyph_index('\u{4e2d}').is_none());
+}
+
+#[test_case]
+pub fn test_font_psf1() {
+    // 256 glyphs, 2 rows each, glyph 1 maps both 'a' and 'b'
+    let mut bytes: Vec<u8> = Vec::from([0x36, 0x04, 0x02, 2]);
+    for glyph in 0..256 {
+        bytes.extend([glyph as u8, 0x81]);
+    }
+    for glyph in 0..256u16 {
+        match glyph {
+            1 => bytes.extend([b'a', 0, b'b', 0]),
+            2 => bytes.extend([b'?', 0]),
+            _ => {}
+        }
+        bytes.extend([0xff, 0xff]);
+    }
+
+    let font = Font::parse(&bytes).unwrap();
+    assert_eq!((font.width(), font.height(), font.glyph_count()), (8, 2, 256));
+    assert_eq!(font.glyph_index('a'), Some(1));
+    assert_eq!(font.glyph_index('b'), Some(1));
+    assert_eq!(font.glyph_index('c'), None);
+
+    // bit 7 of row 1 and bit 0 of row 1 are set in every glyph
+    let glyph = font.glyph('a');
+    assert!(glyph.is_set(7, 0) && !glyph.is_set(6, 0));
+    assert!(glyph.is_set(0, 1) && glyph.is_set(7, 1));
+
+    // unknown characters get the '?' glyph
+    assert!(font.glyph('z').is_set(6, 0));
+}
+
+#[test_case]
+pub fn test_font_invalid() {
+    assert_eq!(Font::parse(b"nope").err(), Some(PsfError::UnknownFormat));
+    assert_eq!(Font::parse(&[0x36, 0x04, 0x00, 16, 0, 0]).err(), Some(PsfError::Truncated));
+}
+
+#[test_case]
+pub fn test_font_screen_size() {
+    let font = font::font();
+    // interrupt handlers draw through the same writer
+    let size = without_interrupts(|| FRAMEBUFFER_WRITER.lock().as_ref().map(|writer| (writer.width(), writer.height())));
+    let Some((width, height)) = size else { return };
+    assert_eq!(textwriter::cursor().1, (width / font.width(), height / font.height()));
+}
+
+#[test_case]
+pub fn test_utf8_decoder() {
+    let mut decoder = Utf8Decoder::new();
+    let decoded: Vec<char> = "é╔€x".bytes().filter_map(|byte| decoder.push(byte)).collect();
+    assert_eq!(decoded, ['é', '╔', '€', 'x']);
+
+    // a lone continuation byte is invalid
+    assert_eq!(decoder.push(0x80), Some(char::REPLACEMENT_CHARACTER));
+}
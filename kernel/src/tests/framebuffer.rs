@@ -0,0 +1,111 @@
+#[cfg(test)]
+use x86_64::instructions::interrupts::without_interrupts;
+#[cfg(test)]
+use crate::sys::kernel::cmdline;
+#[cfg(test)]
+use crate::sys::kernel::drivers::framebuffer::{
+    damage::{Damage, Rect, MAX_RECTS},
+    pixel::{Channel, PixelFormat},
+    render::{self, FRAMEBUFFER_WRITER, MAX_FRAMEBUFFERS},
+};
+
+#[test_case]
+pub fn test_pixel_format_encode() {
+    assert_eq!(PixelFormat::XRGB8888.encode(0x123456), 0x123456);
+    assert_eq!(PixelFormat::RGB565.encode(0xffffff), 0xffff);
+    assert_eq!(PixelFormat::RGB565.encode(0xff0000), 0xf800);
+    assert_eq!(PixelFormat::RGB565.decode(0xffff), 0xffffff);
+
+    // narrow channels still reach full intensity: blue 3 bits, green 2, red 1
+    let tiny = PixelFormat::new(1, Channel::new(1, 0), Channel::new(2, 1), Channel::new(3, 3));
+    assert_eq!(tiny.decode(0x3f), 0xffffff);
+    assert_eq!(tiny.decode(4 << 3 | 1 << 1), 0x005591);
+
+    // blue and red swapped, as some 24 bit modes do
+    let bgr = PixelFormat::new(3, Channel::new(8, 0), Channel::new(8, 8), Channel::new(8, 16));
+    assert_eq!(bgr.encode(0x112233), 0x332211);
+    assert_eq!(bgr.decode(0x332211), 0x112233);
+}
+
+#[test_case]
+pub fn test_pixel_format_write_24bpp() {
+    let mut bytes = [0xaau8; 4];
+    unsafe {
+        PixelFormat::RGB888.write(bytes.as_mut_ptr(), PixelFormat::RGB888.encode(0x010203));
+        assert_eq!(PixelFormat::RGB888.read(bytes.as_ptr()), 0x010203);
+    }
+    // the byte after the pixel is left alone
+    assert_eq!(bytes, [0x03, 0x02, 0x01, 0xaa]);
+}
+
+#[test_case]
+pub fn test_damage_merges() {
+    let mut damage = Damage::new();
+    damage.add(Rect::new(0, 0, 8, 16));
+    damage.add(Rect::new(8, 0, 8, 16));
+    assert_eq!(damage.rects(), &[Rect::new(0, 0, 16, 16)]);
+
+    damage.add(Rect::new(100, 100, 1, 1));
+    assert_eq!(damage.rects().len(), 2);
+
+    // too many separate pieces collapse into their bounding box
+    for index in 0..MAX_RECTS - 1 {
+        damage.add(Rect::new(200 + index * 10, 0, 1, 1));
+    }
+    assert_eq!(damage.rects().len(), 1);
+    assert_eq!(damage.bounds(), Rect::new(0, 0, 200 + (MAX_RECTS - 2) * 10 + 1, 101));
+
+    damage.clear();
+    assert!(damage.is_empty());
+}
+
+#[test_case]
+pub fn test_framebuffer_pixels() {
+    // interrupt handlers print through the same writer, so hold it with them off
+    without_interrupts(|| {
+        let mut guard = FRAMEBUFFER_WRITER.lock();
+        let writer = guard.as_mut().unwrap();
+
+        writer.write_pixel(0, 0, 0x00ff00);
+        assert_eq!(writer.read_pixel(0, 0), Some(0x00ff00));
+
+        writer.fill_rect(1, 1, 4, 4, 0x0000ff);
+        assert_eq!(writer.read_pixel(4, 4), Some(0x0000ff));
+
+        // off screen writes are dropped rather than scribbling over memory
+        let (width, height) = (writer.width() as usize, writer.height() as usize);
+        writer.write_pixel(width, height, 0xffffff);
+        assert_eq!(writer.read_pixel(width, 0), None);
+
+        writer.fill_rect(0, 0, 5, 5, 0x000000);
+        writer.flush();
+    });
+}
+
+#[test_case]
+pub fn test_displays() {
+    let displays = render::displays();
+    assert!(!render::is_headless());
+    assert_eq!(displays[0].index, 0);
+
+    let size = render::with_display(0, |writer| (writer.width(), writer.height()));
+    assert_eq!(size, Some((displays[0].mode.width, displays[0].mode.height)));
+    assert!(render::with_display(MAX_FRAMEBUFFERS, |_| ()).is_none());
+
+    // when Limine lists the modes the current one is among them
+    if !displays[0].modes.is_empty() {
+        assert!(displays[0].modes.iter().any(|mode| (mode.width, mode.height) == size.unwrap()));
+    }
+}
+
+#[test_case]
+pub fn test_preferred_resolution_option() {
+    let line = "quiet resolution=1024x768 resolution=1280x800";
+    assert_eq!(cmdline::find(line, "resolution"), Some("1280x800"));
+    assert_eq!(cmdline::find(line, "quiet"), Some(""));
+    assert_eq!(cmdline::find(line, "res"), None);
+
+    assert_eq!(render::parse_resolution("1280x800"), Some((1280, 800)));
+    assert_eq!(render::parse_resolution("1280"), None);
+    assert_eq!(render::parse_resolution("widexhigh"), None);
+}
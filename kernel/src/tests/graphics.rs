@@ -0,0 +1,99 @@
+#[cfg(test)]
+use crate::sys::kernel::drivers::framebuffer::{
+    damage::Rect,
+    graphics::{blend, Bitmap, Canvas, MemorySurface},
+};
+
+#[cfg(test)]
+fn count(surface: &MemorySurface, color: u32) -> usize {
+    surface.pixels().iter().filter(|&&pixel| pixel == color).count()
+}
+
+#[test_case]
+pub fn test_graphics_lines() {
+    let mut surface = MemorySurface::new(16, 16);
+    let mut canvas = Canvas::new(&mut surface);
+
+    canvas.line(0, 0, 15, 15, 0xff0000);
+    canvas.line(15, 0, 0, 3, 0x00ff00);
+    canvas.line(2, 10, 2, 12, 0x0000ff);
+
+    for i in 4..16 {
+        assert_eq!(surface.pixel(i, i), 0xff0000);
+    }
+    assert_eq!((surface.pixel(15, 0), surface.pixel(0, 3)), (0x00ff00, 0x00ff00));
+    // a shallow line sets one pixel per column
+    assert_eq!(count(&surface, 0x00ff00), 16);
+    assert_eq!(count(&surface, 0x0000ff), 3);
+}
+
+#[test_case]
+pub fn test_graphics_rects() {
+    let mut surface = MemorySurface::new(20, 20);
+    let mut canvas = Canvas::new(&mut surface);
+
+    canvas.fill_rect(2, 2, 5, 4, 0x111111);
+    canvas.rect(10, 10, 5, 4, 0x222222);
+    // mostly off the surface
+    canvas.fill_rect(-5, 18, 8, 8, 0x333333);
+
+    assert_eq!(count(&surface, 0x111111), 20);
+    assert_eq!(count(&surface, 0x222222), 14);
+    assert_eq!(surface.pixel(12, 12), 0);
+    assert_eq!(count(&surface, 0x333333), 6);
+}
+
+#[test_case]
+pub fn test_graphics_circles() {
+    let mut surface = MemorySurface::new(21, 21);
+    let mut canvas = Canvas::new(&mut surface);
+
+    canvas.circle(10, 10, 5, 0xffffff);
+    for (x, y) in [(15, 10), (5, 10), (10, 15), (10, 5)] {
+        assert_eq!(surface.pixel(x, y), 0xffffff);
+    }
+    assert_eq!(surface.pixel(10, 10), 0);
+
+    let mut canvas = Canvas::new(&mut surface);
+    canvas.fill_circle(10, 10, 3, 0x00ff00);
+    assert_eq!(surface.pixel(10, 10), 0x00ff00);
+    assert_eq!(surface.pixel(13, 10), 0x00ff00);
+    assert_eq!(surface.pixel(13, 13), 0);
+}
+
+#[test_case]
+pub fn test_graphics_clip() {
+    let mut surface = MemorySurface::new(10, 10);
+    let mut canvas = Canvas::new(&mut surface);
+
+    canvas.set_clip(Rect::new(2, 2, 4, 4));
+    canvas.clear(0xabcdef);
+    canvas.line(0, 3, 9, 3, 0x123456);
+    canvas.pixel(8, 8, 0x123456);
+
+    assert_eq!(count(&surface, 0xabcdef) + count(&surface, 0x123456), 16);
+    assert_eq!(count(&surface, 0x123456), 4);
+    assert_eq!(surface.pixel(8, 8), 0);
+}
+
+#[test_case]
+pub fn test_graphics_blit_alpha() {
+    assert_eq!(blend(0x80ff0000, 0x0000ff), 0x80007f);
+    assert_eq!(blend(0x00ff0000, 0x0000ff), 0x0000ff);
+    assert_eq!(blend(0xff00ff00, 0x0000ff), 0x00ff00);
+
+    let mut surface = MemorySurface::new(4, 4);
+    let mut canvas = Canvas::new(&mut surface);
+    canvas.clear(0x0000ff);
+
+    let pixels = [0xffff0000, 0x00ffffff, 0x80ff0000, 0xff00ff00];
+    canvas.blit(&Bitmap::new(2, 2, &pixels), 3, 3);
+    canvas.blit(&Bitmap::new(2, 2, &pixels), 0, 0);
+
+    assert_eq!(surface.pixel(0, 0), 0xff0000);
+    assert_eq!(surface.pixel(1, 0), 0x0000ff);
+    assert_eq!(surface.pixel(0, 1), 0x80007f);
+    assert_eq!(surface.pixel(1, 1), 0x00ff00);
+    // only the top left corner of the second blit fits
+    assert_eq!(surface.pixel(3, 3), 0xff0000);
+}
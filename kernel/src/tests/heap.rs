@@ -0,0 +1,73 @@
+#[cfg(test)]
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+#[cfg(test)]
+use crate::sys::kernel::memory::heap::{self, HEAP_INITIAL_SIZE};
+
+#[test_case]
+pub fn test_heap_simple_allocation() {
+    let a = Box::new(41);
+    let b = Box::new(13);
+    assert_eq!(*a, 41);
+    assert_eq!(*b, 13);
+}
+
+#[test_case]
+pub fn test_heap_large_vec() {
+    // bigger than the initial heap, so this forces the heap to grow
+    let n = 1_000_000u64;
+    let mut vec = Vec::new();
+    for i in 0..n {
+        vec.push(i);
+    }
+    assert_eq!(vec.iter().sum::<u64>(), (n - 1) * n / 2);
+    assert!(heap::size() as u64 > HEAP_INITIAL_SIZE);
+}
+
+#[test_case]
+pub fn test_heap_many_small() {
+    let mut boxes = Vec::new();
+    for i in 0..10_000 {
+        boxes.push(Box::new(i));
+    }
+    for (i, b) in boxes.iter().enumerate() {
+        assert_eq!(**b, i);
+    }
+}
+
+#[test_case]
+pub fn test_heap_reuse() {
+    // freed memory must be reused, otherwise this grows the heap by ~80 MB
+    let size = heap::size();
+    for i in 0..10_000 {
+        let x = Box::new([i as u8; 8192]);
+        assert_eq!(x[8191], i as u8);
+    }
+    assert_eq!(heap::size(), size);
+}
+
+#[test_case]
+pub fn test_heap_collections() {
+    let mut map = BTreeMap::new();
+    for i in 0..1000 {
+        let mut s = String::new();
+        s.push_str("value ");
+        s.push(char::from(b'a' + (i % 26) as u8));
+        map.insert(i, s);
+    }
+    assert_eq!(map.len(), 1000);
+    assert_eq!(map[&27], "value b");
+}
+
+#[test_case]
+pub fn test_heap_size_class_stats() {
+    let class = |stats: &heap::HeapStats| stats.classes.iter().find(|c| c.size == 64).copied().unwrap();
+    let before = heap::stats();
+
+    let boxes: Vec<Box<[u8; 48]>> = (0..100).map(|_| Box::new([0; 48])).collect();
+    let during = heap::stats();
+    assert_eq!(class(&during).live, class(&before).live + 100);
+    assert!(class(&during).high_water >= class(&during).bytes);
+
+    drop(boxes);
+    assert_eq!(class(&heap::stats()).live, class(&before).live);
+}
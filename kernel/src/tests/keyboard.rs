@@ -0,0 +1,116 @@
+#[cfg(test)]
+use alloc::vec::Vec;
+#[cfg(test)]
+use crate::sys::kernel::drivers::ps2::{
+    keyboard::{KeyCode, KeyState, Keyboard, Modifiers},
+    keymap::Layout,
+    scancode::{Decoder, ScancodeSet},
+};
+#[cfg(test)]
+use crate::sys::std::ring::RingBuffer;
+
+#[cfg(test)]
+fn decode(set: ScancodeSet, bytes: &[u8]) -> Vec<(KeyCode, KeyState)> {
+    let mut decoder = Decoder::new(set);
+    bytes.iter().filter_map(|&byte| decoder.advance(byte)).collect()
+}
+
+#[cfg(test)]
+fn typed(set: ScancodeSet, layout: Layout, bytes: &[u8]) -> Vec<char> {
+    let mut keyboard = Keyboard::new(set);
+    bytes
+        .iter()
+        .filter_map(|&byte| keyboard.feed(byte, layout))
+        .filter_map(|event| event.ch)
+        .collect()
+}
+
+#[test_case]
+pub fn test_scancode_set1() {
+    assert_eq!(
+        decode(ScancodeSet::Set1, &[0x1e, 0x9e, 0xe0, 0x48, 0xe0, 0xc8]),
+        [
+            (KeyCode::A, KeyState::Down),
+            (KeyCode::A, KeyState::Up),
+            (KeyCode::Up, KeyState::Down),
+            (KeyCode::Up, KeyState::Up),
+        ]
+    );
+
+    // print screen with its fake shifts, then pause
+    assert_eq!(
+        decode(ScancodeSet::Set1, &[0xe0, 0x2a, 0xe0, 0x37, 0xe1, 0x1d, 0x45, 0xe1, 0x9d, 0xc5]),
+        [(KeyCode::PrintScreen, KeyState::Down), (KeyCode::Pause, KeyState::Down)]
+    );
+}
+
+#[test_case]
+pub fn test_scancode_set2() {
+    assert_eq!(
+        decode(ScancodeSet::Set2, &[0x1c, 0xf0, 0x1c, 0xe0, 0x75, 0xe0, 0xf0, 0x75, 0xe0, 0x11]),
+        [
+            (KeyCode::A, KeyState::Down),
+            (KeyCode::A, KeyState::Up),
+            (KeyCode::Up, KeyState::Down),
+            (KeyCode::Up, KeyState::Up),
+            (KeyCode::RightAlt, KeyState::Down),
+        ]
+    );
+
+    assert_eq!(
+        decode(ScancodeSet::Set2, &[0xe1, 0x14, 0x77, 0xe1, 0xf0, 0x14, 0xf0, 0x77, 0x76]),
+        [(KeyCode::Pause, KeyState::Down), (KeyCode::Escape, KeyState::Down)]
+    );
+}
+
+#[test_case]
+pub fn test_keyboard_modifiers() {
+    // shift + a, a, caps lock, a, shift + a
+    let bytes = [0x2a, 0x1e, 0x9e, 0xaa, 0x1e, 0x9e, 0x3a, 0xba, 0x1e, 0x9e, 0x36, 0x1e, 0x9e, 0xb6];
+    assert_eq!(typed(ScancodeSet::Set1, Layout::Us, &bytes), ['A', 'a', 'A', 'a']);
+
+    let mut keyboard = Keyboard::new(ScancodeSet::Set2);
+    keyboard.feed(0x14, Layout::Us);
+    assert!(keyboard.modifiers().ctrl());
+    keyboard.feed(0xf0, Layout::Us);
+    keyboard.feed(0x14, Layout::Us);
+    assert!(!keyboard.modifiers().ctrl());
+
+    // num lock starts on, so the numpad types digits until it's toggled
+    assert!(keyboard.modifiers().contains(Modifiers::NUM_LOCK));
+    assert_eq!(typed(ScancodeSet::Set1, Layout::Us, &[0x47, 0x45, 0xc5, 0x47]), ['7']);
+}
+
+#[test_case]
+pub fn test_keymap_layouts() {
+    // shift + 2, shift + 3, then the key left of Enter
+    let bytes = [0x2a, 0x03, 0x04, 0xaa, 0x2b];
+    assert_eq!(typed(ScancodeSet::Set1, Layout::Us, &bytes), ['@', '#', '\\']);
+    assert_eq!(typed(ScancodeSet::Set1, Layout::Uk, &bytes), ['"', '£', '#']);
+
+    let shift = Modifiers::LEFT_SHIFT;
+    assert_eq!(Layout::Uk.translate(KeyCode::Quote, shift), Some('@'));
+    assert_eq!(Layout::Uk.translate(KeyCode::NonUsBackslash, Modifiers::empty()), Some('\\'));
+    assert_eq!(Layout::Uk.translate(KeyCode::Key4, Modifiers::RIGHT_ALT), Some('€'));
+    assert_eq!(Layout::Us.translate(KeyCode::F1, Modifiers::empty()), None);
+}
+
+#[test_case]
+pub fn test_ring_buffer() {
+    let ring: RingBuffer<u32, 4> = RingBuffer::new();
+    assert!(ring.is_empty());
+
+    for i in 0..4 {
+        assert!(ring.push(i).is_ok());
+    }
+    assert!(ring.is_full());
+    assert_eq!(ring.push(4), Err(4));
+
+    assert_eq!(ring.pop(), Some(0));
+    assert!(ring.push(4).is_ok());
+    assert_eq!(ring.peek(), Some(1));
+
+    let drained: Vec<u32> = core::iter::from_fn(|| ring.pop()).collect();
+    assert_eq!(drained, [1, 2, 3, 4]);
+    assert_eq!(ring.pop(), None);
+}
@@ -0,0 +1,46 @@
+#[cfg(test)]
+use log::{Level, LevelFilter};
+#[cfg(test)]
+use crate::sys::kernel::{dmesg, logger::{self, Sinks}};
+
+#[test_case]
+pub fn test_logger_filters() {
+    assert_eq!(logger::level_for("sys::kernel::time"), logger::DEFAULT_LEVEL);
+
+    logger::set_filter("sys::kernel::time", LevelFilter::Warn).unwrap();
+    logger::set_filter("sys::kernel::time::hpet", LevelFilter::Trace).unwrap();
+
+    // the longest prefix wins, and only whole path segments match
+    assert_eq!(logger::level_for("GoofyAhhOS::sys::kernel::time"), LevelFilter::Warn);
+    assert_eq!(logger::level_for("sys::kernel::time::wheel"), LevelFilter::Warn);
+    assert_eq!(logger::level_for("sys::kernel::time::hpet"), LevelFilter::Trace);
+    assert_eq!(logger::level_for("sys::kernel::timer"), logger::DEFAULT_LEVEL);
+    assert_eq!(log::max_level(), LevelFilter::Trace);
+
+    logger::clear_filter("sys::kernel::time::hpet");
+    assert_eq!(logger::level_for("sys::kernel::time::hpet"), LevelFilter::Warn);
+    logger::clear_filter("sys::kernel::time");
+    assert_eq!(log::max_level(), logger::DEFAULT_LEVEL);
+}
+
+#[test_case]
+pub fn test_logger_keeps_records() {
+    let from = dmesg::next_sequence();
+    log::info!("dmesg test {}", 42);
+    log::debug!("below the default level");
+
+    let mut seen = 0;
+    dmesg::read(from, |entry| {
+        assert_eq!(entry.sequence, from);
+        assert_eq!((entry.level, entry.target, entry.message), (Level::Info, "tests::logger", "dmesg test 42"));
+        seen += 1;
+    });
+    assert_eq!(seen, 1);
+
+    // with the buffer sink off nothing more is kept
+    let sinks = logger::sinks();
+    logger::set_sinks(sinks - Sinks::RING);
+    log::info!("not kept");
+    assert_eq!(dmesg::next_sequence(), from + 1);
+    logger::set_sinks(sinks);
+}
@@ -0,0 +1,83 @@
+#[cfg(test)]
+use crate::sys::kernel::memory::frame;
+
+#[test_case]
+pub fn test_frame_alloc_free() {
+    let before = frame::stats();
+
+    let a = frame::allocate_frame().expect("out of physical frames");
+    let b = frame::allocate_frame().expect("out of physical frames");
+    assert_ne!(a, b);
+    assert_eq!(frame::stats().free, before.free - 2);
+
+    unsafe {
+        frame::free_frame(a);
+        frame::free_frame(b);
+    }
+    assert_eq!(frame::stats().free, before.free);
+}
+
+#[test_case]
+pub fn test_frame_alloc_contiguous() {
+    let before = frame::stats();
+
+    // 2 MiB worth of frames, aligned so it could back a huge page
+    let start = frame::allocate_contiguous(512, 512).expect("no contiguous run available");
+    assert_eq!(start.start_address().as_u64() % (2 * 1024 * 1024), 0);
+    assert_eq!(frame::stats().free, before.free - 512);
+
+    unsafe { frame::free_contiguous(start, 512) };
+    assert_eq!(frame::stats().free, before.free);
+}
+
+#[test_case]
+pub fn test_paging_map_unmap_huge() {
+    use crate::sys::kernel::cpu::x86_64::paging;
+    use x86_64::{
+        structures::paging::{Page, PageTableFlags, PhysFrame, Size2MiB},
+        VirtAddr,
+    };
+
+    let start = frame::allocate_contiguous(512, 512).expect("no contiguous run available");
+    let frame = PhysFrame::<Size2MiB>::containing_address(start.start_address());
+    let page = Page::<Size2MiB>::containing_address(VirtAddr::new(0xffff_c800_0000_0000));
+
+    unsafe {
+        paging::map(page, frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE).unwrap();
+    }
+    assert_eq!(paging::translate(page.start_address() + 0x1234u64), Some(start.start_address() + 0x1234u64));
+
+    let ptr = page.start_address().as_mut_ptr::<u64>();
+    unsafe {
+        ptr.write_volatile(0xdead_beef);
+        assert_eq!(paging::phys_to_virt(start.start_address()).as_ptr::<u64>().read_volatile(), 0xdead_beef);
+    }
+
+    assert_eq!(paging::unmap(page).unwrap(), frame);
+    assert_eq!(paging::translate(page.start_address()), None);
+
+    unsafe { frame::free_contiguous(start, 512) };
+}
+
+#[test_case]
+pub fn test_mmio_window_failed_request() {
+    use crate::sys::kernel::cpu::x86_64::paging::{self, MmioError, MMIO_SIZE};
+    use x86_64::{
+        structures::paging::{Page, Size4KiB},
+        PhysAddr,
+    };
+
+    let frame = frame::allocate_frame().expect("out of physical frames");
+    let phys = frame.start_address();
+
+    let first = paging::map_mmio(phys, 4096).unwrap();
+    // bigger than the whole window, must not use any of it up
+    assert!(matches!(paging::map_mmio(PhysAddr::new(0), MMIO_SIZE + 4096), Err(MmioError::WindowExhausted)));
+    let second = paging::map_mmio(phys, 4096).unwrap();
+    assert_eq!(second, first + 4096u64);
+
+    for address in [first, second] {
+        paging::unmap(Page::<Size4KiB>::containing_address(address)).unwrap();
+    }
+    unsafe { frame::free_frame(frame) };
+}
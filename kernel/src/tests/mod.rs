@@ -2,6 +2,24 @@ mod runner;
 pub use runner::test_runner;
 
 pub mod kernel;
+pub mod memory;
+pub mod heap;
+pub mod acpi;
+pub mod time;
+pub mod rtc;
+pub mod keyboard;
+pub mod mouse;
+pub mod serial;
+pub mod console;
+pub mod ansi;
+pub mod font;
+pub mod framebuffer;
+pub mod graphics;
+pub mod logger;
+pub mod dmesg;
+pub mod panic;
+pub mod symbols;
+pub mod exceptions;
 
 /// Called on panic
 /// 
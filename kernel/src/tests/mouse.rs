@@ -0,0 +1,46 @@
+#[cfg(test)]
+use alloc::vec::Vec;
+#[cfg(test)]
+use crate::sys::kernel::drivers::ps2::mouse::{Mouse, MouseButtons, MouseEvent, MouseKind};
+
+#[cfg(test)]
+fn events(kind: MouseKind, bytes: &[u8]) -> Vec<MouseEvent> {
+    let mut mouse = Mouse::new(kind);
+    bytes.iter().filter_map(|&byte| mouse.feed(byte)).collect()
+}
+
+#[test_case]
+pub fn test_mouse_standard_packet() {
+    // left button down moving right and up, then left and down with it released
+    let events = events(MouseKind::Standard, &[0x09, 0x05, 0x03, 0x38, 0xfb, 0xfe]);
+    assert_eq!(events.len(), 2);
+
+    assert_eq!((events[0].dx, events[0].dy), (5, 3));
+    assert_eq!(events[0].buttons, MouseButtons::LEFT);
+    assert_eq!(events[0].pressed, MouseButtons::LEFT);
+
+    assert_eq!((events[1].dx, events[1].dy), (-5, -2));
+    assert_eq!(events[1].buttons, MouseButtons::empty());
+    assert_eq!(events[1].released, MouseButtons::LEFT);
+}
+
+#[test_case]
+pub fn test_mouse_overflow_and_resync() {
+    // a stray byte without the always-one bit is skipped, and an overflowed
+    // axis doesn't move
+    let events = events(MouseKind::Standard, &[0x00, 0x48, 0xff, 0x01]);
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].dx, events[0].dy), (0, 1));
+}
+
+#[test_case]
+pub fn test_mouse_wheel_packets() {
+    let wheel = events(MouseKind::Wheel, &[0x08, 0x00, 0x00, 0xff, 0x0c, 0x00, 0x00, 0x01]);
+    assert_eq!(wheel[0].wheel, -1);
+    assert_eq!(wheel[1].wheel, 1);
+    assert_eq!(wheel[1].pressed, MouseButtons::MIDDLE);
+
+    let five = events(MouseKind::FiveButtons, &[0x08, 0x00, 0x00, 0x1f]);
+    assert_eq!(five[0].wheel, -1);
+    assert_eq!(five[0].buttons, MouseButtons::BUTTON_4);
+}
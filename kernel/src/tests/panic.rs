@@ -0,0 +1,29 @@
+#[cfg(test)]
+use x86_64::instructions::segmentation::{Segment, CS};
+#[cfg(test)]
+use crate::sys::kernel::cpu::{backtrace::{Frames, MAX_FRAMES}, registers::Registers};
+
+#[test_case]
+pub fn test_register_capture() {
+    let registers = Registers::capture();
+
+    assert_eq!(registers.cr3, x86_64::registers::control::Cr3::read_raw().0.start_address().as_u64());
+    assert_eq!(registers.cs, CS::get_reg().0 as u64);
+    // paging and protected mode are on
+    assert_eq!(registers.cr0 & 0x8000_0001, 0x8000_0001);
+    assert!(registers.rsp != 0 && registers.rip != 0);
+
+    let report = alloc::format!("{}", registers);
+    assert_eq!(report.lines().count(), 8);
+    assert!(report.starts_with("RAX "));
+}
+
+#[test_case]
+pub fn test_backtrace_walks_callers() {
+    let frames = Frames::current().count();
+    // this test, the runner and kmain at least
+    assert!((3..=MAX_FRAMES).contains(&frames), "{} frames", frames);
+
+    // a null frame pointer ends the walk straight away
+    assert_eq!(unsafe { Frames::from_rbp(0) }.count(), 0);
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+use crate::sys::kernel::drivers::rtc::{self, DateTime, RawTime, STATUS_B_24_HOUR, STATUS_B_BINARY};
+
+#[cfg(test)]
+fn date(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+    DateTime { year, month, day, hour, minute, second }
+}
+
+#[test_case]
+pub fn test_rtc_unix_timestamp() {
+    let known = [
+        (date(1970, 1, 1, 0, 0, 0), 0),
+        (date(1999, 12, 31, 23, 59, 59), 946_684_799),
+        (date(2000, 3, 1, 0, 0, 0), 951_868_800),
+        (date(2024, 2, 29, 12, 34, 56), 1_709_210_096),
+    ];
+
+    for (datetime, timestamp) in known {
+        assert_eq!(datetime.unix_timestamp(), timestamp);
+        assert_eq!(DateTime::from_unix_timestamp(timestamp), datetime);
+    }
+}
+
+#[test_case]
+pub fn test_rtc_decode_bcd_12_hour() {
+    let raw = RawTime {
+        second: 0x56,
+        minute: 0x34,
+        hour: 0x80 | 0x12, // 12 PM
+        day: 0x29,
+        month: 0x02,
+        year: 0x24,
+        century: Some(0x20),
+    };
+    assert_eq!(raw.decode(0), date(2024, 2, 29, 12, 34, 56));
+
+    let midnight = RawTime { hour: 0x12, ..raw };
+    assert_eq!(midnight.decode(0).hour, 0);
+
+    let evening = RawTime { hour: 0x80 | 0x11, ..raw };
+    assert_eq!(evening.decode(0).hour, 23);
+}
+
+#[test_case]
+pub fn test_rtc_decode_binary_24_hour() {
+    let raw = RawTime {
+        second: 59,
+        minute: 59,
+        hour: 23,
+        day: 31,
+        month: 12,
+        year: 99,
+        century: Some(19),
+    };
+    assert_eq!(raw.decode(STATUS_B_BINARY | STATUS_B_24_HOUR), date(1999, 12, 31, 23, 59, 59));
+}
+
+#[test_case]
+pub fn test_rtc_read() {
+    let now = rtc::read();
+    assert!(now.year >= 2024);
+    assert!((1..=12).contains(&now.month));
+    assert!((1..=31).contains(&now.day));
+    assert!(now.hour < 24 && now.minute < 60 && now.second < 60);
+}
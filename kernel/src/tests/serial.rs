@@ -0,0 +1,53 @@
+#[cfg(test)]
+use core::time::Duration;
+#[cfg(test)]
+use crate::serial_println;
+#[cfg(test)]
+use crate::sys::kernel::{drivers::serial::serial, time};
+
+#[test_case]
+pub fn test_serial_output_drains() {
+    // more than the transmit buffer holds
+    for _ in 0..150 {
+        serial_println!("test_serial_output_drains output");
+    }
+
+    let deadline = time::uptime() + Duration::from_secs(2);
+    while serial::serial_pending() > 0 {
+        assert!(time::uptime() < deadline, "serial output stalled");
+        time::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test_case]
+pub fn test_serial_config() {
+    use serial::{ComPort, DataBits, Parity, SerialConfig, SerialError, SerialPort, StopBits};
+
+    let odd = SerialConfig { baud: 7, ..SerialConfig::default() };
+    assert_eq!(SerialPort::new(ComPort::Com1, odd).err(), Some(SerialError::InvalidBaud(7)));
+
+    let fast = SerialConfig {
+        baud: 115_200,
+        data_bits: DataBits::Seven,
+        parity: Parity::Even,
+        stop_bits: StopBits::Two,
+    };
+    assert!(serial::configure(ComPort::Com1, fast).is_ok());
+    assert_eq!(serial::with_port(ComPort::Com1, |port| port.config()), Some(fast));
+
+    assert!(serial::configure(ComPort::Com1, SerialConfig::default()).is_ok());
+    serial_println!("test_serial_config output");
+}
+
+#[test_case]
+pub fn test_serial_configure_failure_keeps_port() {
+    use serial::{ComPort, SerialConfig, SerialError};
+
+    let before = serial::with_port(ComPort::Com1, |port| (port.config(), port.interrupt_driven()));
+
+    let odd = SerialConfig { baud: 7, ..SerialConfig::default() };
+    assert_eq!(serial::configure(ComPort::Com1, odd), Err(SerialError::InvalidBaud(7)));
+    assert_eq!(serial::with_port(ComPort::Com1, |port| (port.config(), port.interrupt_driven())), before);
+
+    serial_println!("test_serial_configure_failure_keeps_port output");
+}
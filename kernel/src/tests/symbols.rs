@@ -0,0 +1,37 @@
+#[cfg(test)]
+use alloc::string::String;
+#[cfg(test)]
+use crate::sys::kernel::{cpu::backtrace::{self, Frames}, symbols};
+
+#[cfg(test)]
+#[inline(never)]
+fn known_function() -> u64 {
+    42
+}
+
+#[test_case]
+pub fn test_symbol_lookup() {
+    assert!(symbols::is_available());
+
+    let address = known_function as fn() -> u64 as usize as u64;
+    let symbol = symbols::lookup(address).expect("no symbol for a kernel function");
+    assert_eq!(symbol.address, address);
+
+    let located = symbols::locate(address + 1);
+    assert_eq!(located.offset(), Some(1));
+    let shown = alloc::format!("{}", located);
+    assert!(shown.contains("known_function+0x1"), "{}", shown);
+
+    // nothing lives at the bottom of the higher half
+    assert_eq!(symbols::locate(0xffff_8000_0000_0000).symbol, None);
+}
+
+#[test_case]
+pub fn test_backtrace_symbolized() {
+    let mut out = String::new();
+    backtrace::write(&mut out, None, Frames::current()).unwrap();
+
+    // the runner called this test
+    assert!(out.lines().count() >= 3, "{}", out);
+    assert!(out.contains("test_runner"), "{}", out);
+}
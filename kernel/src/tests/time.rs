@@ -0,0 +1,111 @@
+#[cfg(test)]
+use alloc::sync::Arc;
+#[cfg(test)]
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(test)]
+use core::time::Duration;
+#[cfg(test)]
+use crate::sys::kernel::time;
+
+#[test_case]
+pub fn test_timer_running() {
+    assert!(time::source().is_some());
+
+    // whichever clock uptime ended up on, TSC, HPET or ticks
+    let (ticks, uptime) = (time::ticks(), time::uptime());
+    time::sleep(Duration::from_millis(20));
+    assert!(time::ticks() > ticks);
+    assert!(time::uptime() > uptime);
+}
+
+#[test_case]
+pub fn test_uptime_monotonic() {
+    let mut last = time::uptime();
+    for _ in 0..1000 {
+        let now = time::uptime();
+        assert!(now >= last);
+        last = now;
+    }
+}
+
+#[test_case]
+pub fn test_sleep_duration() {
+    for ms in [1, 10, 50, 100] {
+        let requested = Duration::from_millis(ms);
+        let start = time::uptime();
+        time::sleep(requested);
+        assert!(time::uptime() - start >= requested);
+    }
+}
+
+#[test_case]
+pub fn test_uptime_matches_pit() {
+    // cross check the clock against PIT channel 2, which nothing else uses
+    let (start, end) = time::pit::measure(20, || time::uptime().as_nanos() as u64);
+    let elapsed = Duration::from_nanos(end - start);
+
+    // the PIT count rounds down a little, interrupts can only add to it
+    assert!(elapsed >= Duration::from_micros(19_900), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(40), "{:?}", elapsed);
+}
+
+#[test_case]
+pub fn test_sleep_matches_hpet() {
+    // cross check the TSC clock against an independent counter
+    if !time::hpet::is_available() {
+        return;
+    }
+
+    let start = time::hpet::counter();
+    time::sleep(Duration::from_millis(50));
+    let elapsed = time::hpet::counter() - start;
+
+    assert!(elapsed * 1000 >= time::hpet::frequency() * 50);
+}
+
+#[test_case]
+pub fn test_one_shot_timer() {
+    let fired = Arc::new(AtomicU32::new(0));
+    let counter = fired.clone();
+
+    time::after(Duration::from_millis(10), move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+
+    time::sleep(Duration::from_millis(50));
+    assert_eq!(fired.load(Ordering::SeqCst), 1);
+}
+
+#[test_case]
+pub fn test_periodic_timer() {
+    let fired = Arc::new(AtomicU32::new(0));
+    let counter = fired.clone();
+
+    let id = time::every(Duration::from_millis(5), move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+
+    time::sleep(Duration::from_millis(100));
+    assert!(time::cancel(id));
+
+    let count = fired.load(Ordering::SeqCst);
+    assert!(count >= 5);
+
+    time::sleep(Duration::from_millis(20));
+    assert_eq!(fired.load(Ordering::SeqCst), count);
+}
+
+#[test_case]
+pub fn test_cancel_timer() {
+    let fired = Arc::new(AtomicU32::new(0));
+    let counter = fired.clone();
+
+    let id = time::after(Duration::from_millis(10), move || {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    assert!(time::cancel(id));
+    assert!(!time::cancel(id));
+
+    time::sleep(Duration::from_millis(30));
+    assert_eq!(fired.load(Ordering::SeqCst), 0);
+}